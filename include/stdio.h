@@ -0,0 +1,26 @@
+/* the parts of <stdio.h> that rem knows about */
+#ifndef _STDIO_H
+#define _STDIO_H
+
+typedef unsigned long size_t;
+typedef struct _IO_FILE FILE;
+
+#define NULL ((void *)0)
+#define EOF (-1)
+
+extern FILE *stdin;
+extern FILE *stdout;
+extern FILE *stderr;
+
+FILE *fopen(const char *restrict path, const char *restrict mode);
+int fclose(FILE *stream);
+int printf(const char *restrict format, ...);
+int fprintf(FILE *restrict stream, const char *restrict format, ...);
+int sprintf(char *restrict s, const char *restrict format, ...);
+int snprintf(char *restrict s, size_t n, const char *restrict format, ...);
+int puts(const char *s);
+int fputs(const char *restrict s, FILE *restrict stream);
+int putchar(int c);
+int getchar(void);
+
+#endif
@@ -0,0 +1,387 @@
+use crate::lexer::{Float, Integer, Span, Spanned};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranslationUnit {
+    pub items: Vec<ExternalDeclaration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExternalDeclaration {
+    Function(FunctionDefinition),
+    Declaration(Declaration),
+    StaticAssert(StaticAssert),
+}
+
+// `_Static_assert(condition, "message");`
+#[derive(Debug, Clone, PartialEq)]
+pub struct StaticAssert {
+    pub condition: Expr,
+    pub message: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionDefinition {
+    pub specifiers: DeclarationSpecifiers,
+    pub declarator: Declarator,
+    pub body: Block,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StorageClass {
+    Typedef,
+    Extern,
+    Static,
+    Auto,
+    Register,
+    ThreadLocal,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Qualifiers {
+    pub is_const: bool,
+    pub is_volatile: bool,
+    pub is_restrict: bool,
+    pub is_atomic: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypeSpecifier {
+    Void,
+    Char,
+    Short,
+    Int,
+    Long,
+    Float,
+    Double,
+    Signed,
+    Unsigned,
+    Bool,
+    Complex,
+    TypedefName(String),
+    Record(RecordSpecifier),
+    Enum(EnumSpecifier),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RecordKind {
+    Struct,
+    Union,
+}
+
+// `struct tag { ... }`, `members` is `None` when only the tag is given
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordSpecifier {
+    pub kind: RecordKind,
+    pub tag: Option<Spanned<String>>,
+    pub members: Option<Vec<MemberDeclaration>>,
+    // `_Static_assert`s among the members
+    pub assertions: Vec<StaticAssert>,
+    pub span: Span,
+}
+
+// no declarators for an anonymous struct or union member
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberDeclaration {
+    pub specifiers: DeclarationSpecifiers,
+    pub declarators: Vec<MemberDeclarator>,
+    pub span: Span,
+}
+
+// the declarator has no name for unnamed bit-fields such as `int : 3`
+#[derive(Debug, Clone, PartialEq)]
+pub struct MemberDeclarator {
+    pub declarator: Declarator,
+    pub width: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumSpecifier {
+    pub tag: Option<Spanned<String>>,
+    pub enumerators: Option<Vec<Enumerator>>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enumerator {
+    pub name: Spanned<String>,
+    pub value: Option<Expr>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DeclarationSpecifiers {
+    pub storage: Option<StorageClass>,
+    pub qualifiers: Qualifiers,
+    pub is_inline: bool,
+    pub is_noreturn: bool,
+    // in source order, `unsigned long long` is three specifiers
+    pub types: Vec<Spanned<TypeSpecifier>>,
+    // `_Alignas`, the strictest one applies
+    pub alignments: Vec<Alignment>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Alignment {
+    // `_Alignas(T)` is `_Alignas(_Alignof(T))`
+    Type(TypeName),
+    Expr(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    pub specifiers: DeclarationSpecifiers,
+    pub declarators: Vec<InitDeclarator>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct InitDeclarator {
+    pub declarator: Declarator,
+    pub initializer: Option<Initializer>,
+}
+
+// the name being declared and the derivations applied to the specified
+// type, innermost first: `*a[3]` is an array of three pointers, so its
+// derivations are [Array(3), Pointer]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declarator {
+    pub name: Option<Spanned<String>>,
+    pub derived: Vec<DerivedDeclarator>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DerivedDeclarator {
+    Pointer(Qualifiers),
+    Array(Option<Box<Expr>>),
+    Function {
+        parameters: Vec<ParameterDeclaration>,
+        variadic: bool,
+    },
+}
+
+// a type on its own, as in casts and `sizeof(int *)`; the declarator is
+// abstract and has no name
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeName {
+    pub specifiers: DeclarationSpecifiers,
+    pub declarator: Declarator,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParameterDeclaration {
+    pub specifiers: DeclarationSpecifiers,
+    pub declarator: Declarator,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Designator {
+    Index(Expr),
+    Member(Spanned<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Initializer {
+    Expression(Expr),
+    List {
+        items: Vec<(Vec<Designator>, Initializer)>,
+        span: Span,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub items: Vec<BlockItem>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockItem {
+    Declaration(Declaration),
+    Statement(Stmt),
+    StaticAssert(StaticAssert),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ForInit {
+    Declaration(Declaration),
+    Expression(Expr),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Compound(Block),
+    // `None` is the empty statement `;`
+    Expression(Option<Expr>),
+    If {
+        condition: Expr,
+        then: Box<Stmt>,
+        otherwise: Option<Box<Stmt>>,
+    },
+    While {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+    DoWhile {
+        body: Box<Stmt>,
+        condition: Expr,
+    },
+    For {
+        init: Option<Box<ForInit>>,
+        condition: Option<Expr>,
+        step: Option<Expr>,
+        body: Box<Stmt>,
+    },
+    Switch {
+        condition: Expr,
+        body: Box<Stmt>,
+    },
+    Case {
+        value: Expr,
+        body: Box<Stmt>,
+    },
+    Default(Box<Stmt>),
+    Labeled {
+        label: Spanned<String>,
+        body: Box<Stmt>,
+    },
+    Goto(Spanned<String>),
+    Break,
+    Continue,
+    Return(Option<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Plus,
+    Minus,
+    Not,
+    BitNot,
+    Deref,
+    AddressOf,
+    PreIncrement,
+    PreDecrement,
+    PostIncrement,
+    PostDecrement,
+}
+
+impl UnaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::Plus => "+",
+            UnaryOp::Minus => "-",
+            UnaryOp::Not => "!",
+            UnaryOp::BitNot => "~",
+            UnaryOp::Deref => "*",
+            UnaryOp::AddressOf => "&",
+            UnaryOp::PreIncrement | UnaryOp::PostIncrement => "++",
+            UnaryOp::PreDecrement | UnaryOp::PostDecrement => "--",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Mod,
+    Add,
+    Sub,
+    ShiftLeft,
+    ShiftRight,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    BitAnd,
+    Xor,
+    BitOr,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Mod => "%",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::ShiftLeft => "<<",
+            BinaryOp::ShiftRight => ">>",
+            BinaryOp::Less => "<",
+            BinaryOp::Greater => ">",
+            BinaryOp::LessEqual => "<=",
+            BinaryOp::GreaterEqual => ">=",
+            BinaryOp::Equal => "==",
+            BinaryOp::NotEqual => "!=",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::Xor => "^",
+            BinaryOp::BitOr => "|",
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprKind {
+    Identifier(String),
+    Integer(Integer),
+    Float(Float),
+    // the value, an int
+    Character(i32),
+    // the bytes without the terminating null, adjacent literals already
+    // concatenated
+    String(Vec<u8>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    // `a += b` carries `Some(BinaryOp::Add)`
+    Assign(Option<BinaryOp>, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+    Comma(Box<Expr>, Box<Expr>),
+    Call(Box<Expr>, Vec<Expr>),
+    Index(Box<Expr>, Box<Expr>),
+    Member {
+        base: Box<Expr>,
+        member: Spanned<String>,
+        // `->` rather than `.`
+        arrow: bool,
+    },
+    Cast(Box<TypeName>, Box<Expr>),
+    // `(T){ ... }`
+    CompoundLiteral(Box<TypeName>, Box<Initializer>),
+    SizeofExpr(Box<Expr>),
+    SizeofType(Box<TypeName>),
+    AlignofType(Box<TypeName>),
+    Generic {
+        control: Box<Expr>,
+        associations: Vec<GenericAssociation>,
+    },
+}
+
+// `T: expr` in a `_Generic` selection, without a type for `default`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GenericAssociation {
+    pub ty: Option<TypeName>,
+    pub expr: Expr,
+}
@@ -0,0 +1,409 @@
+use crate::{
+    ast::{BinaryOp, Expr, ExprKind, TypeName, UnaryOp},
+    lexer::FloatSuffix,
+    types::{merge, FloatKind, IntegerKind, Ordinary, QualifiedType, Type, TypeEnv},
+};
+
+// the value of an integer constant expression, in the range of its type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Constant {
+    pub value: i128,
+    pub kind: IntegerKind,
+}
+
+impl Constant {
+    // `value` converted to `kind`, wrapping around its width as GCC does
+    pub fn new(value: i128, kind: IntegerKind) -> Self {
+        if kind == IntegerKind::Bool {
+            let value = (value != 0) as i128;
+            return Constant { value, kind };
+        }
+        let modulus = 1i128 << (kind.size() * 8);
+        let value = match value.rem_euclid(modulus) {
+            value if kind.is_signed() && value >= modulus / 2 => value - modulus,
+            value => value,
+        };
+        Constant { value, kind }
+    }
+    fn int(value: bool) -> Self {
+        Constant::new(value as i128, IntegerKind::Int)
+    }
+    // whether `value` is in the range of `kind`, signed overflow makes an
+    // expression not constant
+    fn fits(value: i128, kind: IntegerKind) -> bool {
+        Constant::new(value, kind).value == value
+    }
+}
+
+// what the names and types in a constant expression stand for; the
+// environment knows while declarations are being analyzed, the type
+// checker afterwards
+pub trait Constants {
+    fn env(&self) -> &TypeEnv;
+    // the value of an identifier naming an enumeration constant
+    fn enumerator(&self, expr: &Expr) -> Option<i64>;
+    fn type_name(&mut self, name: &TypeName) -> Option<QualifiedType>;
+    // the type of an expression, for `sizeof`, which does not evaluate it
+    fn type_of(&mut self, expr: &Expr) -> Option<QualifiedType>;
+}
+
+// C's integer constant expressions, in the types the usual arithmetic
+// conversions give; `None` if the expression is not one
+pub fn evaluate(cx: &mut impl Constants, expr: &Expr) -> Option<Constant> {
+    let unsigned_long = |value: u64| Constant::new(value as i128, IntegerKind::UnsignedLong);
+    match &expr.kind {
+        ExprKind::Integer(integer) => Some(Constant {
+            value: integer.value as i128,
+            kind: IntegerKind::of_literal(integer)?,
+        }),
+        ExprKind::Character(c) => Some(Constant::new(*c as i128, IntegerKind::Int)),
+        // GCC gives constants that int can't hold the type long
+        ExprKind::Identifier(_) => {
+            let value = cx.enumerator(expr)? as i128;
+            match Constant::fits(value, IntegerKind::Int) {
+                true => Some(Constant::new(value, IntegerKind::Int)),
+                false => Some(Constant::new(value, IntegerKind::Long)),
+            }
+        }
+        ExprKind::Unary(op, operand) => {
+            let operand = evaluate(cx, operand)?;
+            let kind = operand.kind.promoted();
+            match op {
+                UnaryOp::Plus => Some(Constant::new(operand.value, kind)),
+                UnaryOp::Minus if kind.is_signed() => Some(-operand.value)
+                    .filter(|value| Constant::fits(*value, kind))
+                    .map(|value| Constant::new(value, kind)),
+                UnaryOp::Minus => Some(Constant::new(-operand.value, kind)),
+                UnaryOp::BitNot => Some(Constant::new(!operand.value, kind)),
+                UnaryOp::Not => Some(Constant::int(operand.value == 0)),
+                _ => None,
+            }
+        }
+        ExprKind::Binary(op, lhs, rhs) => {
+            let a = evaluate(cx, lhs)?;
+            // only the operand that is evaluated has to be constant
+            match op {
+                BinaryOp::And if a.value == 0 => return Some(Constant::int(false)),
+                BinaryOp::Or if a.value != 0 => return Some(Constant::int(true)),
+                _ => {}
+            }
+            let b = evaluate(cx, rhs)?;
+            binary(*op, a, b)
+        }
+        ExprKind::Conditional(condition, then, otherwise) => {
+            let (taken, other) = match evaluate(cx, condition)?.value {
+                0 => (otherwise, then),
+                _ => (then, otherwise),
+            };
+            let taken = evaluate(cx, taken)?;
+            // the other operand is not evaluated, but its type counts
+            let other = match cx.type_of(other)?.ty {
+                Type::Integer(kind) => kind,
+                Type::Enum { id, .. } => cx.env().enumeration(id).underlying,
+                _ => return None,
+            };
+            Some(Constant::new(taken.value, taken.kind.common(other)))
+        }
+        ExprKind::Cast(name, operand) => {
+            let kind = match cx.type_name(name)?.ty {
+                Type::Integer(kind) => kind,
+                Type::Enum { id, .. } => cx.env().enumeration(id).underlying,
+                _ => return None,
+            };
+            Some(Constant::new(evaluate(cx, operand)?.value, kind))
+        }
+        ExprKind::SizeofExpr(operand) => {
+            let ty = cx.type_of(operand)?;
+            Some(unsigned_long(cx.env().size_of(&ty)?))
+        }
+        ExprKind::SizeofType(name) => {
+            let ty = cx.type_name(name)?;
+            Some(unsigned_long(cx.env().size_of(&ty)?))
+        }
+        ExprKind::AlignofType(name) => {
+            let ty = cx.type_name(name)?;
+            Some(unsigned_long(cx.env().align_of(&ty)?))
+        }
+        _ => None,
+    }
+}
+
+// shifts keep the promoted type of their left operand
+fn shift(op: BinaryOp, a: Constant, b: Constant) -> Option<Constant> {
+    let kind = a.kind.promoted();
+    let bits = kind.size() as i128 * 8;
+    if !(0..bits).contains(&b.value) {
+        return None;
+    }
+    match op {
+        BinaryOp::ShiftLeft if kind.is_signed() => {
+            let value = a.value.checked_mul(1 << b.value)?;
+            let valid = a.value >= 0 && Constant::fits(value, kind);
+            valid.then(|| Constant::new(value, kind))
+        }
+        BinaryOp::ShiftLeft => {
+            let value = (a.value as u64) << b.value;
+            Some(Constant::new(value as i128, kind))
+        }
+        _ => Some(Constant::new(a.value >> b.value, kind)),
+    }
+}
+
+fn binary(op: BinaryOp, a: Constant, b: Constant) -> Option<Constant> {
+    let kind = a.kind.common(b.kind);
+    let (x, y) = (
+        Constant::new(a.value, kind).value,
+        Constant::new(b.value, kind).value,
+    );
+    let value = match op {
+        BinaryOp::ShiftLeft | BinaryOp::ShiftRight => return shift(op, a, b),
+        BinaryOp::Less => return Some(Constant::int(x < y)),
+        BinaryOp::Greater => return Some(Constant::int(x > y)),
+        BinaryOp::LessEqual => return Some(Constant::int(x <= y)),
+        BinaryOp::GreaterEqual => return Some(Constant::int(x >= y)),
+        BinaryOp::Equal => return Some(Constant::int(x == y)),
+        BinaryOp::NotEqual => return Some(Constant::int(x != y)),
+        BinaryOp::And | BinaryOp::Or => return Some(Constant::int(y != 0)),
+        // unsigned products may not fit, but wrap around the same way
+        BinaryOp::Mul => x.wrapping_mul(y),
+        BinaryOp::Div => x.checked_div(y)?,
+        BinaryOp::Mod => x.checked_rem(y)?,
+        BinaryOp::Add => x + y,
+        BinaryOp::Sub => x - y,
+        BinaryOp::BitAnd => x & y,
+        BinaryOp::Xor => x ^ y,
+        BinaryOp::BitOr => x | y,
+    };
+    match kind.is_signed() && !Constant::fits(value, kind) {
+        true => None,
+        false => Some(Constant::new(value, kind)),
+    }
+}
+
+// arrays and functions used as values become pointers
+fn decay(ty: QualifiedType) -> QualifiedType {
+    match ty.ty {
+        Type::Array(element, _) => Type::Pointer(element).into(),
+        Type::Function(_) => Type::Pointer(Box::new(ty)).into(),
+        ty => ty.into(),
+    }
+}
+
+fn pointee(ty: &QualifiedType) -> Option<QualifiedType> {
+    match &ty.ty {
+        Type::Pointer(to) => Some((**to).clone()),
+        _ => None,
+    }
+}
+
+fn is_arithmetic(ty: &Type) -> bool {
+    matches!(
+        ty,
+        Type::Integer(_) | Type::Enum { .. } | Type::Float(_) | Type::Complex(_)
+    )
+}
+
+impl TypeEnv {
+    // the value of an integer constant expression if it fits in an i64
+    pub fn integer_constant(&mut self, expr: &Expr) -> Option<i64> {
+        i64::try_from(evaluate(self, expr)?.value).ok()
+    }
+    // the type of an expression from the declarations in scope; invalid
+    // expressions are left to the type checker and may get any type
+    fn expression_type(&mut self, expr: &Expr) -> Option<QualifiedType> {
+        let integer = |kind| QualifiedType::from(Type::Integer(kind));
+        let ty = match &expr.kind {
+            ExprKind::Identifier(name) => match self.ordinary(name)? {
+                Ordinary::Object(ty) => ty.clone(),
+                Ordinary::Constant(_) => integer(IntegerKind::Int),
+                Ordinary::Typedef(_) => return None,
+            },
+            ExprKind::Integer(value) => integer(IntegerKind::of_literal(value)?),
+            ExprKind::Float(value) => Type::Float(match value.suffix {
+                FloatSuffix::None => FloatKind::Double,
+                FloatSuffix::Float => FloatKind::Float,
+                FloatSuffix::Long => FloatKind::LongDouble,
+            })
+            .into(),
+            ExprKind::Character(_) => integer(IntegerKind::Int),
+            ExprKind::String(value) => {
+                let char = Box::new(integer(IntegerKind::Char));
+                Type::Array(char, Some(value.len() as u64 + 1)).into()
+            }
+            ExprKind::Unary(op, operand) => {
+                let ty = self.expression_type(operand)?;
+                match op {
+                    UnaryOp::Deref => pointee(&decay(ty))?,
+                    UnaryOp::AddressOf => Type::Pointer(Box::new(ty)).into(),
+                    UnaryOp::Not => integer(IntegerKind::Int),
+                    UnaryOp::Plus | UnaryOp::Minus | UnaryOp::BitNot => self.promote(&ty),
+                    _ => ty.ty.into(),
+                }
+            }
+            ExprKind::Binary(op, lhs, rhs) => {
+                let l = decay(self.expression_type(lhs)?);
+                let r = decay(self.expression_type(rhs)?);
+                match op {
+                    BinaryOp::Less
+                    | BinaryOp::Greater
+                    | BinaryOp::LessEqual
+                    | BinaryOp::GreaterEqual
+                    | BinaryOp::Equal
+                    | BinaryOp::NotEqual
+                    | BinaryOp::And
+                    | BinaryOp::Or => integer(IntegerKind::Int),
+                    BinaryOp::ShiftLeft | BinaryOp::ShiftRight => self.promote(&l),
+                    _ if is_arithmetic(&l.ty) && is_arithmetic(&r.ty) => self.common(&l, &r),
+                    // the difference of two pointers is a ptrdiff_t
+                    BinaryOp::Sub if matches!(r.ty, Type::Pointer(_)) => integer(IntegerKind::Long),
+                    _ if matches!(r.ty, Type::Pointer(_)) => r,
+                    _ => l,
+                }
+            }
+            ExprKind::Assign(_, lhs, _) => self.expression_type(lhs)?.ty.into(),
+            ExprKind::Conditional(_, then, otherwise) => {
+                let t = decay(self.expression_type(then)?);
+                let e = decay(self.expression_type(otherwise)?);
+                match is_arithmetic(&t.ty) && is_arithmetic(&e.ty) {
+                    true => self.common(&t, &e),
+                    false => t,
+                }
+            }
+            ExprKind::Comma(_, rhs) => decay(self.expression_type(rhs)?),
+            ExprKind::Call(callee, _) => {
+                let to = pointee(&decay(self.expression_type(callee)?))?;
+                match to.ty {
+                    Type::Function(function) => function.result.ty.into(),
+                    _ => return None,
+                }
+            }
+            // either operand may be the pointer, as `a[i]` is `*(a + i)`
+            ExprKind::Index(base, index) => {
+                let b = decay(self.expression_type(base)?);
+                let i = decay(self.expression_type(index)?);
+                pointee(&b).or_else(|| pointee(&i))?
+            }
+            ExprKind::Member {
+                base,
+                member,
+                arrow,
+            } => {
+                let base = self.expression_type(base)?;
+                let record = match arrow {
+                    true => pointee(&decay(base))?,
+                    false => base,
+                };
+                let Type::Record { id, .. } = record.ty else {
+                    return None;
+                };
+                let found = self.member(id, &member.node)?;
+                QualifiedType {
+                    ty: found.ty.ty,
+                    qualifiers: merge(found.ty.qualifiers, record.qualifiers),
+                }
+            }
+            ExprKind::Cast(name, _) => self.type_name(name).ok()?.ty.into(),
+            ExprKind::CompoundLiteral(name, _) => self.type_name(name).ok()?,
+            ExprKind::SizeofExpr(_) | ExprKind::SizeofType(_) | ExprKind::AlignofType(_) => {
+                integer(IntegerKind::UnsignedLong)
+            }
+            ExprKind::Generic { .. } => return None,
+        };
+        Some(ty)
+    }
+}
+
+impl Constants for TypeEnv {
+    fn env(&self) -> &TypeEnv {
+        self
+    }
+    fn enumerator(&self, expr: &Expr) -> Option<i64> {
+        match &expr.kind {
+            ExprKind::Identifier(name) => self.constant(name),
+            _ => None,
+        }
+    }
+    fn type_name(&mut self, name: &TypeName) -> Option<QualifiedType> {
+        TypeEnv::type_name(self, name).ok()
+    }
+    fn type_of(&mut self, expr: &Expr) -> Option<QualifiedType> {
+        self.expression_type(expr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        sema::{self, SemaErrorKind},
+        syntax,
+        types::{Type, TypeErrorKind},
+    };
+
+    // the length of each array declared at file scope
+    fn lengths(source: &str) -> Vec<(String, Option<u64>)> {
+        let unit = syntax::parse(source).unwrap();
+        let analysis = sema::analyze(&unit).unwrap();
+        let arrays = analysis
+            .symbols
+            .iter()
+            .filter_map(|symbol| match symbol.ty.ty {
+                Type::Array(_, n) => Some((symbol.name.clone(), n)),
+                _ => None,
+            });
+        arrays.collect()
+    }
+
+    fn error(source: &str) -> SemaErrorKind {
+        let unit = syntax::parse(source).unwrap();
+        sema::analyze(&unit).unwrap_err().remove(0).kind
+    }
+
+    #[test]
+    fn conversions_follow_the_operand_types() {
+        let source = "
+            int a[-1 < 0u ? -1 : 1];
+            int b[(0 ? 1u : -1) > 0];
+            int c[(unsigned char)257];
+            int d[0x7fffffff + 1u == 0x80000000];
+            int e[-1L < 0u];
+            int f[(-1LL >> 1 == -1) + (~0u >> 31)];
+        ";
+        let expected = [("a", 1), ("b", 1), ("c", 1), ("d", 1), ("e", 1), ("f", 2)];
+        let expected = expected.map(|(name, n)| (name.to_string(), Some(n)));
+        assert_eq!(lengths(source), expected);
+    }
+
+    #[test]
+    fn sizeof_types_its_operand() {
+        let source = "
+            int x;
+            int arr[10];
+            struct S { char m[3]; int n; } s;
+            int a[sizeof x];
+            int b[sizeof arr / sizeof arr[0]];
+            int c[sizeof s.m + sizeof (&s)->n];
+            int d[sizeof \"abc\"];
+            int e[sizeof (arr + 1)];
+            int f[sizeof -s.m[0]];
+        ";
+        let sizes: Vec<_> = lengths(source).into_iter().skip(1).collect();
+        let expected = [("a", 4), ("b", 10), ("c", 7), ("d", 4), ("e", 8), ("f", 4)];
+        let expected = expected.map(|(name, n)| (name.to_string(), Some(n)));
+        assert_eq!(sizes, expected);
+        let source =
+            "void f(void) { long n; int a[sizeof n]; _Static_assert(sizeof a == 32, \"\"); }";
+        syntax::parse(source)
+            .map(|unit| sema::analyze(&unit).unwrap())
+            .unwrap();
+    }
+
+    #[test]
+    fn signed_overflow_is_not_constant() {
+        let array_size = SemaErrorKind::Type(TypeErrorKind::ArraySize);
+        assert_eq!(error("int a[0x7fffffff + 1];"), array_size);
+        assert_eq!(error("int a[1 << 31];"), array_size);
+        assert_eq!(error("int a[-2147483647 - 1 > 0 ? 1 : -1];"), array_size);
+        assert_eq!(error("int a[1 / 0];"), array_size);
+        assert_eq!(lengths("int a[1u << 31 >> 31];")[0].1, Some(1));
+    }
+}
@@ -0,0 +1,374 @@
+use crate::{
+    ast::RecordKind,
+    lexer::{Span, Spanned},
+    types::{BitField, FloatKind, Member, QualifiedType, Type, TypeEnv, TypeError, TypeErrorKind},
+};
+
+// size and alignment in bytes, as the System V x86-64 ABI lays types out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Layout {
+    pub size: u64,
+    pub align: u64,
+}
+
+impl Layout {
+    fn scalar(size: u64) -> Self {
+        Layout { size, align: size }
+    }
+}
+
+// a member of a record being defined, `width` is set for bit-fields and
+// `align` for members declared `_Alignas`
+#[derive(Debug, Clone)]
+pub(crate) struct Field {
+    pub name: Option<Spanned<String>>,
+    pub ty: QualifiedType,
+    pub width: Option<u64>,
+    pub align: Option<u64>,
+    pub span: Span,
+}
+
+// `None` on overflow
+fn align_up(n: u64, align: u64) -> Option<u64> {
+    n.div_ceil(align).checked_mul(align)
+}
+
+impl TypeEnv {
+    // `None` for incomplete types: void, functions, arrays of unknown size
+    // and structs, unions and enums that were only declared
+    pub fn layout(&self, ty: &QualifiedType) -> Option<Layout> {
+        let float = |kind| match kind {
+            FloatKind::Float => Layout::scalar(4),
+            FloatKind::Double => Layout::scalar(8),
+            FloatKind::LongDouble => Layout::scalar(16),
+        };
+        match &ty.ty {
+            Type::Void | Type::Function(_) | Type::Array(_, None) => None,
+            Type::Integer(kind) => Some(Layout::scalar(kind.size())),
+            Type::Float(kind) => Some(float(*kind)),
+            Type::Complex(kind) => {
+                let part = float(*kind);
+                Some(Layout {
+                    size: part.size * 2,
+                    align: part.align,
+                })
+            }
+            Type::Pointer(_) => Some(Layout::scalar(8)),
+            Type::Array(element, Some(n)) => {
+                let element = self.layout(element)?;
+                Some(Layout {
+                    size: element.size.checked_mul(*n)?,
+                    align: element.align,
+                })
+            }
+            Type::Record { id, .. } => self.record(*id).layout,
+            Type::Enum { id, .. } => {
+                let enumeration = self.enumeration(*id);
+                enumeration.constants.as_ref()?;
+                self.layout(&Type::Integer(enumeration.underlying).into())
+            }
+        }
+    }
+    pub fn size_of(&self, ty: &QualifiedType) -> Option<u64> {
+        Some(self.layout(ty)?.size)
+    }
+    pub fn align_of(&self, ty: &QualifiedType) -> Option<u64> {
+        Some(self.layout(ty)?.align)
+    }
+    // a member of a complete record by name, looking inside anonymous
+    // members; the offset is from the start of the record `id`
+    pub fn member(&self, id: usize, name: &str) -> Option<Member> {
+        for member in self.record(id).members.as_ref()? {
+            match (&member.name, &member.ty.ty) {
+                (Some(n), _) if n == name => return Some(member.clone()),
+                (None, Type::Record { id, .. }) => {
+                    if let Some(mut found) = self.member(*id, name) {
+                        found.offset += member.offset;
+                        return Some(found);
+                    }
+                }
+                _ => {}
+            }
+        }
+        None
+    }
+    // `offsetof(ty, a.b.c)` with the path given as its member names; bit-
+    // fields have no offset
+    pub fn offset_of(&self, ty: &QualifiedType, path: &[&str]) -> Option<u64> {
+        let mut ty = ty.clone();
+        let mut offset = 0;
+        for name in path {
+            let Type::Record { id, .. } = ty.ty else {
+                return None;
+            };
+            let member = self.member(id, name)?;
+            if member.bit_field.is_some() {
+                return None;
+            }
+            offset += member.offset;
+            ty = member.ty;
+        }
+        Some(offset)
+    }
+
+    // places the fields of a struct one after another and those of a union
+    // over each other; a bit-field goes in the next bits unless that would
+    // cross a boundary of its type's alignment, and unnamed bit-fields do
+    // not affect the alignment of the record
+    pub(crate) fn lay_out(
+        &self,
+        kind: RecordKind,
+        fields: Vec<Field>,
+    ) -> Result<(Vec<Member>, Layout), TypeError> {
+        let mut members = Vec::new();
+        let mut bits = 0;
+        let mut align = 1;
+        let mut span = Span::default();
+        let count = fields.len();
+        // positions are in bits, which a huge member overflows
+        let too_large = |span| TypeError {
+            kind: TypeErrorKind::TooLarge,
+            span,
+        };
+        for (i, field) in fields.into_iter().enumerate() {
+            span = field.span;
+            let mut layout = match (self.layout(&field.ty), &field.ty.ty) {
+                (Some(layout), _) => layout,
+                // flexible array member, only last in a struct
+                (None, Type::Array(element, None))
+                    if kind == RecordKind::Struct && i + 1 == count && i > 0 =>
+                {
+                    Layout {
+                        size: 0,
+                        align: self.align_of(element).unwrap_or(1),
+                    }
+                }
+                _ => {
+                    let name = field.name.map_or("(anonymous)".to_string(), |n| n.node);
+                    return Err(TypeError {
+                        kind: TypeErrorKind::IncompleteMember(name),
+                        span: field.span,
+                    });
+                }
+            };
+            layout.align = layout.align.max(field.align.unwrap_or(1));
+            let start = match kind {
+                RecordKind::Struct => bits,
+                RecordKind::Union => 0,
+            };
+            let unit = layout.align * 8;
+            let (start, end) = match field.width {
+                Some(0) => {
+                    let start = align_up(start, unit).ok_or(too_large(span))?;
+                    (start, start)
+                }
+                Some(width) => {
+                    let end = start.checked_add(width).ok_or(too_large(span))?;
+                    let start = match start / unit != (end - 1) / unit {
+                        true => align_up(start, unit).ok_or(too_large(span))?,
+                        false => start,
+                    };
+                    (start, start.checked_add(width).ok_or(too_large(span))?)
+                }
+                None => {
+                    let start = align_up(start, unit).ok_or(too_large(span))?;
+                    let bits = layout.size.checked_mul(8);
+                    let end = bits.and_then(|bits| start.checked_add(bits));
+                    (start, end.ok_or(too_large(span))?)
+                }
+            };
+            let named = field.width.is_none() || field.name.is_some();
+            if named {
+                align = align.max(layout.align);
+            }
+            bits = match kind {
+                RecordKind::Struct => end,
+                RecordKind::Union => bits.max(end),
+            };
+            let bit_field = field.width.map(|width| BitField {
+                offset: start % unit,
+                width,
+            });
+            if named {
+                members.push(Member {
+                    name: field.name.map(|n| n.node),
+                    ty: field.ty,
+                    offset: start / unit * layout.align,
+                    bit_field,
+                });
+            }
+        }
+        let size = align_up(bits.div_ceil(8), align).ok_or(too_large(span))?;
+        Ok((members, Layout { size, align }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Layout;
+    use crate::{
+        sema::{self, Analysis, SemaErrorKind},
+        syntax,
+        types::{Type, TypeErrorKind},
+    };
+
+    fn analyze(source: &str) -> Analysis {
+        sema::analyze(&syntax::parse(source).unwrap()).unwrap()
+    }
+
+    // a member as its name, offset, and bit offset and width
+    type Placed = (String, u64, Option<(u64, u64)>);
+
+    // the layout of the object `name` and the members of its record
+    fn record(source: &str, name: &str) -> (Layout, Vec<Placed>) {
+        let analysis = analyze(source);
+        let symbol = analysis.symbols.iter().find(|s| s.name == name).unwrap();
+        let Type::Record { id, .. } = symbol.ty.ty else {
+            panic!("{name} is not a record");
+        };
+        let members = analysis.env.record(id).members.iter().flatten();
+        let members = members.map(|member| {
+            let bits = member.bit_field.as_ref().map(|b| (b.offset, b.width));
+            let name = member.name.clone().unwrap_or_default();
+            (name, member.offset, bits)
+        });
+        let members = members.collect();
+        (analysis.env.layout(&symbol.ty).unwrap(), members)
+    }
+
+    fn layout(size: u64, align: u64) -> Layout {
+        Layout { size, align }
+    }
+
+    fn error(source: &str) -> TypeErrorKind {
+        let unit = syntax::parse(source).unwrap();
+        match sema::analyze(&unit).unwrap_err().remove(0).kind {
+            SemaErrorKind::Type(kind) => kind,
+            kind => panic!("not a type error: {kind}"),
+        }
+    }
+
+    #[test]
+    fn members_are_aligned_and_records_padded() {
+        let (struct_, members) = record("struct { char c; int i; char d; } s;", "s");
+        assert_eq!(struct_, layout(12, 4));
+        let offsets: Vec<_> = members.iter().map(|(n, o, _)| (n.as_str(), *o)).collect();
+        assert_eq!(offsets, [("c", 0), ("i", 4), ("d", 8)]);
+        let (union, members) = record("union { char c[5]; int i; } u;", "u");
+        assert_eq!(union, layout(8, 4));
+        assert!(members.iter().all(|(_, offset, _)| *offset == 0));
+        let source = "struct { int a; union { char b; double c; }; } s;";
+        let analysis = analyze(source);
+        let ty = &analysis.symbols[0].ty;
+        assert_eq!(analysis.env.offset_of(ty, &["c"]), Some(8));
+        assert_eq!(analysis.env.size_of(ty), Some(16));
+    }
+
+    #[test]
+    fn bit_fields_share_units_until_they_would_cross_one() {
+        let (packed, members) = record("struct { char a; int b : 4; int c : 20; } s;", "s");
+        assert_eq!(packed, layout(4, 4));
+        let expected = [
+            ("a".to_string(), 0, None),
+            ("b".to_string(), 0, Some((8, 4))),
+            ("c".to_string(), 0, Some((12, 20))),
+        ];
+        assert_eq!(members, expected);
+        // 3 + 30 bits do not fit one int
+        let (crossing, members) = record("struct { int a : 3; int b : 30; } s;", "s");
+        assert_eq!(crossing, layout(8, 4));
+        assert_eq!(members[1], ("b".to_string(), 4, Some((0, 30))));
+        let source = "struct { long long a : 40; int b : 30; } s;";
+        let (wide, members) = record(source, "s");
+        assert_eq!(wide, layout(16, 8));
+        assert_eq!(members[1], ("b".to_string(), 8, Some((0, 30))));
+        let analysis = analyze("struct { int a; int b : 3; } s;");
+        assert_eq!(
+            analysis.env.offset_of(&analysis.symbols[0].ty, &["a"]),
+            Some(0)
+        );
+        assert_eq!(
+            analysis.env.offset_of(&analysis.symbols[0].ty, &["b"]),
+            None
+        );
+    }
+
+    #[test]
+    fn zero_width_and_unnamed_bit_fields() {
+        let (zero, members) = record("struct { int a : 3; int : 0; int b : 2; } s;", "s");
+        assert_eq!(zero, layout(8, 4));
+        let names: Vec<_> = members.iter().map(|(n, _, _)| n.as_str()).collect();
+        assert_eq!(names, ["a", "b"]);
+        assert_eq!(members[1], ("b".to_string(), 4, Some((0, 2))));
+        // an unnamed bit-field takes space but not alignment
+        let (unnamed, _) = record("struct { char a; int : 4; } s;", "s");
+        assert_eq!(unnamed, layout(2, 1));
+        let (named, _) = record("struct { char a; int b : 4; } s;", "s");
+        assert_eq!(named, layout(4, 4));
+    }
+
+    #[test]
+    fn flexible_array_members_come_last_and_add_only_alignment() {
+        let (chars, members) = record("struct { int n; char data[]; } s;", "s");
+        assert_eq!(chars, layout(4, 4));
+        assert_eq!(members[1], ("data".to_string(), 4, None));
+        let (doubles, members) = record("struct { char c; double d[]; } s;", "s");
+        assert_eq!(doubles, layout(8, 8));
+        assert_eq!(members[1], ("d".to_string(), 8, None));
+        let member = |name: &str| TypeErrorKind::IncompleteMember(name.to_string());
+        assert_eq!(error("struct { int n; char a[]; int m; } s;"), member("a"));
+        assert_eq!(error("struct { char a[]; } s;"), member("a"));
+        assert_eq!(error("union { int n; char a[]; } u;"), member("a"));
+        assert_eq!(error("struct t; struct { struct t t; } s;"), member("t"));
+    }
+
+    #[test]
+    fn alignas_raises_member_and_record_alignment() {
+        let source = "struct { char c; _Alignas(16) int x; } s;";
+        let (aligned, members) = record(source, "s");
+        assert_eq!(aligned, layout(32, 16));
+        assert_eq!(members[1], ("x".to_string(), 16, None));
+        let source = "struct { char c; _Alignas(double) char d; } s;";
+        let (by_type, members) = record(source, "s");
+        assert_eq!(by_type, layout(16, 8));
+        assert_eq!(members[1], ("d".to_string(), 8, None));
+        // 0 asks for nothing
+        let (none, _) = record("struct { char c; _Alignas(0) char d; } s;", "s");
+        assert_eq!(none, layout(2, 1));
+    }
+
+    #[test]
+    fn invalid_widths_and_alignments_are_errors() {
+        assert_eq!(
+            error("struct { int a : 33; } s;"),
+            TypeErrorKind::BitFieldWidth
+        );
+        assert_eq!(
+            error("struct { int a : 0; } s;"),
+            TypeErrorKind::BitFieldWidth
+        );
+        assert_eq!(
+            error("struct { double a : 3; } s;"),
+            TypeErrorKind::BitFieldType
+        );
+        assert_eq!(
+            error("struct { _Alignas(3) int a; } s;"),
+            TypeErrorKind::InvalidAlignment(3)
+        );
+        assert_eq!(
+            error("struct { _Alignas(2) int a; } s;"),
+            TypeErrorKind::AlignmentTooWeak {
+                requested: 2,
+                natural: 4
+            }
+        );
+        assert_eq!(
+            error("struct { _Alignas(4) int a : 3; } s;"),
+            TypeErrorKind::MisplacedAlignment
+        );
+        assert_eq!(
+            error("typedef _Alignas(8) int t;"),
+            TypeErrorKind::MisplacedAlignment
+        );
+    }
+}
@@ -1,12 +1,51 @@
 use phf::phf_map;
 use std::{
+    collections::VecDeque,
+    error, fmt,
     fs::File,
-    io::{self, BufRead, BufReader, Error, ErrorKind, Read},
+    hash::{Hash, Hasher},
+    io::{BufRead, BufReader, ErrorKind, Read},
 };
-#[derive(Debug, Clone, PartialEq, Hash, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq, Default)]
+pub enum IntegerSuffix {
+    #[default]
+    None,
+    Unsigned,
+    Long,
+    UnsignedLong,
+    LongLong,
+    UnsignedLongLong,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq)]
+pub struct Integer {
+    pub value: u64,
+    pub radix: u32,
+    pub suffix: IntegerSuffix,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Hash, Eq, Default)]
+pub enum FloatSuffix {
+    #[default]
+    None,
+    Float,
+    Long,
+}
+#[derive(Debug, Clone, Copy)]
 pub struct Float {
-    integral: u64,
-    fractional: u64,
+    pub value: f64,
+    pub suffix: FloatSuffix,
+}
+// floats are compared bitwise so tokens can live in hash sets
+impl PartialEq for Float {
+    fn eq(&self, other: &Self) -> bool {
+        self.value.to_bits() == other.value.to_bits() && self.suffix == other.suffix
+    }
+}
+impl Eq for Float {}
+impl Hash for Float {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.value.to_bits().hash(state);
+        self.suffix.hash(state);
+    }
 }
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub enum Token {
@@ -24,9 +63,10 @@ pub enum Token {
     DoubleColon,
     Colon,
     Function,
-    Integer(i64),
+    Integer(Integer),
     Float(Float),
-    String(String),
+    // the literal's bytes with escapes decoded, UTF-8 for source text
+    String(Vec<u8>),
     Div,
     Directive(String),
     Comma,
@@ -46,7 +86,7 @@ pub enum Token {
     Assign,
     LeftBracket,
     RightBracket,
-    RightLeft,
+    ShiftRight,
     GreaterThan,
     GreaterThanEqual,
     Question,
@@ -74,17 +114,233 @@ pub enum Token {
     Volatile,
     Do,
     Static,
-    Character(String),
+    // the value, an int
+    Character(i32),
+    Increment,
+    Decrement,
+    PlusAssign,
+    MinusAssign,
+    StarAssign,
+    DivAssign,
+    ModAssign,
+    BitAndAssign,
+    BitOrAssign,
+    XorAssign,
+    ShiftLeftAssign,
+    ShiftRightAssign,
+    Ellipsis,
+    Int,
+    Char,
+    Void,
+    Short,
+    Long,
+    FloatKeyword,
+    Double,
+    Signed,
+    Unsigned,
+    Sizeof,
+    Inline,
+    Restrict,
+    Bool,
+    Complex,
+    Imaginary,
+    Alignas,
+    Alignof,
+    Atomic,
+    Generic,
+    Noreturn,
+    StaticAssert,
+    ThreadLocal,
+    HashHash,
+    Newline,
 }
 
-#[derive(Debug)]
-pub struct Lexer {
-    f: BufReader<File>,
+fn escaped(ch: char, quote: char) -> String {
+    match ch {
+        '\n' => "\\n".to_string(),
+        '\t' => "\\t".to_string(),
+        '\r' => "\\r".to_string(),
+        '\\' => "\\\\".to_string(),
+        ch if ch == quote => format!("\\{ch}"),
+        ch if (ch as u32) < 0x20 || ch as u32 == 0x7F => format!("\\{:03o}", ch as u32),
+        ch => ch.to_string(),
+    }
 }
 
-use Token as tk;
-impl Lexer {
-    const KEYWORDS: phf::Map<&'static str, Token> = phf_map! {
+// literal bytes between quotes; bytes that are not UTF-8 are spelled as
+// octal escapes, which unlike `\x` can't run into a following digit
+fn quoted(bytes: &[u8], quote: char) -> String {
+    let mut text = String::new();
+    for chunk in bytes.utf8_chunks() {
+        text.extend(chunk.valid().chars().map(|ch| escaped(ch, quote)));
+        for byte in chunk.invalid() {
+            text += &format!("\\{byte:03o}");
+        }
+    }
+    format!("{quote}{text}{quote}")
+}
+
+impl fmt::Display for Integer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.radix {
+            16 => write!(f, "0x{:x}", self.value)?,
+            8 if self.value != 0 => write!(f, "0{:o}", self.value)?,
+            2 => write!(f, "0b{:b}", self.value)?,
+            _ => write!(f, "{}", self.value)?,
+        }
+        let suffix = match self.suffix {
+            IntegerSuffix::None => "",
+            IntegerSuffix::Unsigned => "u",
+            IntegerSuffix::Long => "l",
+            IntegerSuffix::UnsignedLong => "ul",
+            IntegerSuffix::LongLong => "ll",
+            IntegerSuffix::UnsignedLongLong => "ull",
+        };
+        write!(f, "{suffix}")
+    }
+}
+
+impl fmt::Display for Float {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let suffix = match self.suffix {
+            FloatSuffix::None => "",
+            FloatSuffix::Float => "f",
+            FloatSuffix::Long => "l",
+        };
+        write!(f, "{:?}{suffix}", self.value)
+    }
+}
+
+// prints tokens the way they are spelled in source
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let spelling = match self {
+            Token::Identifier(name) => return write!(f, "{name}"),
+            Token::Integer(i) => return write!(f, "{i}"),
+            Token::Float(float) => return write!(f, "{float}"),
+            Token::String(bytes) => return write!(f, "{}", quoted(bytes, '"')),
+            Token::Character(value) => {
+                // negative values are bytes past ASCII, spelled as escapes
+                let mut buffer = [*value as u8; 4];
+                let bytes = match char::from_u32(*value as u32) {
+                    Some(ch) => ch.encode_utf8(&mut buffer).as_bytes(),
+                    None => &buffer[..1],
+                };
+                return write!(f, "{}", quoted(bytes, '\''));
+            }
+            Token::Directive(d) => return write!(f, "#{d}"),
+            Token::EOF => "<EOF>",
+            Token::Newline => "\n",
+            Token::LeftParen => "(",
+            Token::RightParen => ")",
+            Token::LeftBrace => "{",
+            Token::RightBrace => "}",
+            Token::LeftBracket => "[",
+            Token::RightBracket => "]",
+            Token::Semicolon => ";",
+            Token::Comma => ",",
+            Token::Colon => ":",
+            Token::DoubleColon => "::",
+            Token::Question => "?",
+            Token::Dot => ".",
+            Token::Ellipsis => "...",
+            Token::Arrow => "->",
+            Token::Hash => "#",
+            Token::HashHash => "##",
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Div => "/",
+            Token::Mod => "%",
+            Token::Increment => "++",
+            Token::Decrement => "--",
+            Token::BitAnd => "&",
+            Token::BitOr => "|",
+            Token::Xor => "^",
+            Token::BitNot => "~",
+            Token::Not => "!",
+            Token::And => "&&",
+            Token::Or => "||",
+            Token::ShiftLeft => "<<",
+            Token::ShiftRight => ">>",
+            Token::LessThan => "<",
+            Token::LessThanEqual => "<=",
+            Token::GreaterThan => ">",
+            Token::GreaterThanEqual => ">=",
+            Token::Equal => "==",
+            Token::NotEqual => "!=",
+            Token::Assign => "=",
+            Token::PlusAssign => "+=",
+            Token::MinusAssign => "-=",
+            Token::StarAssign => "*=",
+            Token::DivAssign => "/=",
+            Token::ModAssign => "%=",
+            Token::BitAndAssign => "&=",
+            Token::BitOrAssign => "|=",
+            Token::XorAssign => "^=",
+            Token::ShiftLeftAssign => "<<=",
+            Token::ShiftRightAssign => ">>=",
+            Token::Function => "fn",
+            Token::While => "while",
+            Token::Return => "return",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::Continue => "continue",
+            Token::Struct => "struct",
+            Token::Switch => "switch",
+            Token::Typedef => "typedef",
+            Token::Case => "case",
+            Token::Auto => "auto",
+            Token::Break => "break",
+            Token::Enum => "enum",
+            Token::Register => "register",
+            Token::Extern => "extern",
+            Token::Union => "union",
+            Token::Const => "const",
+            Token::For => "for",
+            Token::Default => "default",
+            Token::Goto => "goto",
+            Token::Volatile => "volatile",
+            Token::Do => "do",
+            Token::Static => "static",
+            Token::Int => "int",
+            Token::Char => "char",
+            Token::Void => "void",
+            Token::Short => "short",
+            Token::Long => "long",
+            Token::FloatKeyword => "float",
+            Token::Double => "double",
+            Token::Signed => "signed",
+            Token::Unsigned => "unsigned",
+            Token::Sizeof => "sizeof",
+            Token::Inline => "inline",
+            Token::Restrict => "restrict",
+            Token::Bool => "_Bool",
+            Token::Complex => "_Complex",
+            Token::Imaginary => "_Imaginary",
+            Token::Alignas => "_Alignas",
+            Token::Alignof => "_Alignof",
+            Token::Atomic => "_Atomic",
+            Token::Generic => "_Generic",
+            Token::Noreturn => "_Noreturn",
+            Token::StaticAssert => "_Static_assert",
+            Token::ThreadLocal => "_Thread_local",
+        };
+        write!(f, "{spelling}")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Dialect {
+    C89,
+    C99,
+    #[default]
+    C11,
+    Rem,
+}
+
+impl Dialect {
+    const C89_KEYWORDS: phf::Map<&'static str, Token> = phf_map! {
         "return" => Token::Return,
         "if" => Token::If,
         "else" => Token::Else,
@@ -107,45 +363,341 @@ impl Lexer {
         "do" => Token::Do,
         "static" => Token::Static,
         "while" => Token::While,
+        "int" => Token::Int,
+        "char" => Token::Char,
+        "void" => Token::Void,
+        "short" => Token::Short,
+        "long" => Token::Long,
+        "float" => Token::FloatKeyword,
+        "double" => Token::Double,
+        "signed" => Token::Signed,
+        "unsigned" => Token::Unsigned,
+        "sizeof" => Token::Sizeof,
+    };
+    const C99_KEYWORDS: phf::Map<&'static str, Token> = phf_map! {
+        "inline" => Token::Inline,
+        "restrict" => Token::Restrict,
+        "_Bool" => Token::Bool,
+        "_Complex" => Token::Complex,
+        "_Imaginary" => Token::Imaginary,
+    };
+    const C11_KEYWORDS: phf::Map<&'static str, Token> = phf_map! {
+        "_Alignas" => Token::Alignas,
+        "_Alignof" => Token::Alignof,
+        "_Atomic" => Token::Atomic,
+        "_Generic" => Token::Generic,
+        "_Noreturn" => Token::Noreturn,
+        "_Static_assert" => Token::StaticAssert,
+        "_Thread_local" => Token::ThreadLocal,
+    };
+    const REM_KEYWORDS: phf::Map<&'static str, Token> = phf_map! {
+        "fn" => Token::Function,
     };
+
+    pub fn keyword(self, ident: &str) -> Option<Token> {
+        let tables = match self {
+            Dialect::C89 => &[Self::C89_KEYWORDS][..],
+            Dialect::C99 => &[Self::C89_KEYWORDS, Self::C99_KEYWORDS],
+            Dialect::C11 => &[Self::C89_KEYWORDS, Self::C99_KEYWORDS, Self::C11_KEYWORDS],
+            Dialect::Rem => &[
+                Self::C89_KEYWORDS,
+                Self::C99_KEYWORDS,
+                Self::C11_KEYWORDS,
+                Self::REM_KEYWORDS,
+            ],
+        };
+        tables.iter().find_map(|table| table.get(ident).cloned())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Position {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Position {
+    fn advance(&mut self, ch: u8) {
+        self.offset += 1;
+        if ch == b'\n' {
+            self.line += 1;
+            self.column = 1;
+        } else if ch & 0xC0 != 0x80 {
+            // utf-8 continuation bytes belong to the previous column
+            self.column += 1;
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+    pub len: usize,
+    // 0 for text read by a lexer on its own; the preprocessor numbers the
+    // files it reads and every macro expansion, so spans from different
+    // places never compare equal
+    pub origin: u32,
+}
+
+impl Span {
+    pub fn new(start: Position, end: Position) -> Self {
+        Span {
+            offset: start.offset,
+            line: start.line,
+            column: start.column,
+            len: end.offset - start.offset,
+            origin: 0,
+        }
+    }
+    pub fn end(&self) -> usize {
+        self.offset + self.len
+    }
+    /// smallest span covering both `self` and `other`
+    pub fn to(&self, other: Span) -> Span {
+        let (first, last) = if self.offset <= other.offset {
+            (self, &other)
+        } else {
+            (&other, self)
+        };
+        Span {
+            len: first.end().max(last.end()) - first.offset,
+            ..*first
+        }
+    }
+    /// the exact source text covered by this span
+    pub fn snippet<'a>(&self, source: &'a str) -> Option<&'a str> {
+        source.get(self.offset..self.end())
+    }
+    /// the whole line the span starts on, without the line terminator
+    pub fn source_line<'a>(&self, source: &'a str) -> Option<&'a str> {
+        let start = source.get(..self.offset)?.rfind('\n').map_or(0, |i| i + 1);
+        let end = source[self.offset..]
+            .find('\n')
+            .map_or(source.len(), |i| self.offset + i);
+        Some(source[start..end].trim_end_matches('\r'))
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum LexErrorKind {
+    UnknownCharacter(char),
+    UnterminatedString,
+    UnterminatedCharacter,
+    UnterminatedComment,
+    MalformedNumber(String),
+    NumberOverflow(String),
+    InvalidEscape(String),
+    EmptyCharacter,
+    MultiCharacter(String),
+    Io(ErrorKind),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LexError {
+    pub kind: LexErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for LexErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LexErrorKind::UnknownCharacter(ch) => {
+                write!(f, "unknown character {ch:?} (U+{:04X})", *ch as u32)
+            }
+            LexErrorKind::UnterminatedString => write!(f, "unterminated string literal"),
+            LexErrorKind::UnterminatedCharacter => write!(f, "unterminated character literal"),
+            LexErrorKind::UnterminatedComment => write!(f, "unterminated block comment"),
+            LexErrorKind::MalformedNumber(n) => write!(f, "malformed number {n:?}"),
+            LexErrorKind::NumberOverflow(n) => write!(f, "number {n} is too large"),
+            LexErrorKind::InvalidEscape(e) => write!(f, "invalid escape sequence \\{e}"),
+            LexErrorKind::EmptyCharacter => write!(f, "empty character literal"),
+            LexErrorKind::MultiCharacter(c) => write!(f, "multi-character literal '{c}'"),
+            LexErrorKind::Io(kind) => write!(f, "failed to read source: {kind}"),
+        }
+    }
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.kind)
+    }
+}
+
+impl error::Error for LexError {}
+
+// a decoded escape sequence or source character: `\ooo` and `\xhh` name
+// a byte, the others a character
+enum Escape {
+    Byte(u8),
+    Char(char),
+}
+
+#[derive(Debug)]
+pub struct Lexer<R = BufReader<File>> {
+    f: R,
+    pos: Position,
+    start: Position,
+    lookahead: VecDeque<u8>,
+    dialect: Dialect,
+    newlines: bool,
+    halted: bool,
+    io_error: Option<ErrorKind>,
+}
+
+use Token as tk;
+impl Lexer {
     pub fn from(f: File) -> Self {
+        Lexer::new(BufReader::new(f))
+    }
+}
+
+impl<R: Read> Lexer<BufReader<R>> {
+    pub fn from_reader(reader: R) -> Self {
+        Lexer::new(BufReader::new(reader))
+    }
+}
+
+impl<'a> Lexer<&'a [u8]> {
+    pub fn from_bytes(source: &'a [u8]) -> Self {
+        Lexer::new(source)
+    }
+    pub fn from_source(source: &'a str) -> Self {
+        Lexer::new(source.as_bytes())
+    }
+}
+
+impl<R: BufRead> Lexer<R> {
+    pub fn new(reader: R) -> Self {
         Lexer {
-            f: BufReader::new(f),
+            f: reader,
+            pos: Default::default(),
+            start: Default::default(),
+            lookahead: VecDeque::new(),
+            dialect: Default::default(),
+            newlines: false,
+            halted: false,
+            io_error: None,
         }
     }
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+    // the preprocessor needs to see where lines end
+    pub fn with_newlines(mut self, newlines: bool) -> Self {
+        self.newlines = newlines;
+        self
+    }
+    pub fn dialect(&self) -> Dialect {
+        self.dialect
+    }
+    pub fn position(&self) -> Position {
+        self.pos
+    }
+    fn bump(&mut self) {
+        self.getch();
+    }
+    fn span(&self) -> Span {
+        Span::new(self.start, self.pos)
+    }
     fn getch(&mut self) -> Option<u8> {
-        let mut ch: [u8; 1] = [0];
-        if self.eof().ok()? {
-            return None;
-        }
-        if let Err(e) = self.f.read_exact(&mut ch) {
-            eprintln!("error {e}");
-            return None;
+        let ch = match self.lookahead.pop_front() {
+            Some(ch) => ch,
+            None => {
+                let ch = self.fill()?;
+                self.f.consume(1);
+                ch
+            }
+        };
+        self.pos.advance(ch);
+        Some(ch)
+    }
+    fn fail(&mut self, kind: ErrorKind) {
+        // an i/o error ends the token stream, it is reported once by `next`
+        self.halted = true;
+        self.io_error = Some(kind);
+    }
+    fn peek(&mut self) -> Option<u8> {
+        match self.lookahead.front() {
+            Some(&ch) => Some(ch),
+            None => self.fill(),
         }
-        Some(ch[0])
     }
-    fn eof(&mut self) -> io::Result<bool> {
-        Ok(self.f.fill_buf()?.is_empty())
+    // the reader may hand out a single byte at a buffer boundary, so bytes
+    // needed for longer lookahead are moved out of it into `lookahead`
+    fn peek_at(&mut self, n: usize) -> Option<u8> {
+        while self.lookahead.len() <= n {
+            let ch = self.fill()?;
+            self.f.consume(1);
+            self.lookahead.push_back(ch);
+        }
+        self.lookahead.get(n).copied()
     }
-    fn peek(&mut self) -> io::Result<u8> {
-        let buf = self.f.fill_buf()?;
-        if buf.is_empty() {
-            return Err(Error::from(ErrorKind::UnexpectedEof));
+    fn fill(&mut self) -> Option<u8> {
+        if self.halted {
+            return None;
+        }
+        match self.f.fill_buf() {
+            Ok(buf) => buf.first().copied(),
+            Err(e) => {
+                self.fail(e.kind());
+                None
+            }
         }
-        return Ok(buf[0]);
     }
-    fn take_while<P>(&mut self, mut initial: String, mut predicate: P) -> Option<String>
+    fn take_while<P>(&mut self, mut initial: String, mut predicate: P) -> String
     where
         P: FnMut(&&u8) -> bool,
     {
-        loop {
+        while let Some(&ch) = self.lookahead.front() {
+            if !predicate(&&ch) {
+                return initial;
+            }
+            self.lookahead.pop_front();
+            initial.push(ch as char);
+            self.pos.advance(ch);
+        }
+        while !self.halted {
             let mut len = 0;
-            let Ok(buf) = self.f.fill_buf() else {
-                return None;
+            let buf = match self.f.fill_buf() {
+                Ok(buf) => buf,
+                Err(e) => {
+                    self.fail(e.kind());
+                    break;
+                }
             };
             let buf_len = buf.len();
-            for &ch in buf.into_iter().take_while(&mut predicate) {
+            if buf_len == 0 {
+                break;
+            }
+            for &ch in buf.iter().take_while(&mut predicate) {
                 initial.push(ch as char);
+                self.pos.advance(ch);
                 len += 1;
             }
             self.f.consume(len);
@@ -153,151 +705,352 @@ impl Lexer {
                 break;
             }
         }
-        Some(initial)
+        initial
+    }
+
+    fn digits(&mut self, radix: u32, max: usize) -> String {
+        let mut len = 0;
+        self.take_while(String::new(), |&&ch| {
+            len += 1;
+            len <= max && (ch as char).is_digit(radix)
+        })
+    }
+
+    // numeric escapes give a byte, the others a character
+    fn escape(&mut self) -> Result<Option<Escape>, LexErrorKind> {
+        let Some(ch) = self.getch() else {
+            return Err(LexErrorKind::InvalidEscape(String::new()));
+        };
+        let ch = match ch {
+            b'n' => '\n',
+            b't' => '\t',
+            b'r' => '\r',
+            b'a' => '\x07',
+            b'b' => '\x08',
+            b'f' => '\x0C',
+            b'v' => '\x0B',
+            b'\\' | b'\'' | b'"' | b'?' => ch as char,
+            // line continuation inside a literal
+            b'\n' => return Ok(None),
+            b'\r' if self.peek() == Some(b'\n') => {
+                self.bump();
+                return Ok(None);
+            }
+            b'0'..=b'7' => {
+                let octal = (ch as char).to_string() + &self.digits(8, 2);
+                let value = u32::from_str_radix(&octal, 8).unwrap_or(u32::MAX);
+                if value > 0xFF {
+                    return Err(LexErrorKind::InvalidEscape(octal));
+                }
+                return Ok(Some(Escape::Byte(value as u8)));
+            }
+            b'x' | b'u' | b'U' => {
+                let (max, exact) = match ch {
+                    b'x' => (usize::MAX, false),
+                    b'u' => (4, true),
+                    _ => (8, true),
+                };
+                let hex = self.digits(16, max);
+                let text = (ch as char).to_string() + &hex;
+                match u32::from_str_radix(&hex, 16).ok() {
+                    Some(v) if ch == b'x' && v <= 0xFF => return Ok(Some(Escape::Byte(v as u8))),
+                    Some(v) if exact && hex.len() == max => {
+                        char::from_u32(v).ok_or(LexErrorKind::InvalidEscape(text))?
+                    }
+                    _ => return Err(LexErrorKind::InvalidEscape(text)),
+                }
+            }
+            _ => {
+                let mut text = vec![ch];
+                if ch >= 0x80 {
+                    let rest = self.take_while(String::new(), |&&ch| ch & 0xC0 == 0x80);
+                    text.extend(rest.chars().map(|ch| ch as u8));
+                }
+                let text = String::from_utf8_lossy(&text).into_owned();
+                return Err(LexErrorKind::InvalidEscape(text));
+            }
+        };
+        Ok(Some(Escape::Char(ch)))
     }
 
-    fn string(&mut self, ch: u8) -> Option<Token> {
-        let string = (ch as char).to_string();
-        let mut found_end = false;
-        let string = self.take_while(string, |&&ch| {
-            if found_end {
-                return false;
+    // the characters and bytes between quotes; source text that is valid
+    // UTF-8 gives characters, like the escapes other than `\ooo` and `\xhh`
+    fn quoted(
+        &mut self,
+        quote: u8,
+        unterminated: LexErrorKind,
+    ) -> Result<Vec<Escape>, LexErrorKind> {
+        let mut contents = vec![];
+        let mut error = None;
+        loop {
+            match self.peek() {
+                None | Some(b'\n') => return Err(unterminated),
+                Some(ch) if ch == quote => {
+                    self.bump();
+                    break;
+                }
+                Some(b'\\') => {
+                    self.bump();
+                    // keep going after a bad escape so the whole literal is consumed
+                    match self.escape() {
+                        Ok(Some(escape)) => contents.push(escape),
+                        Ok(None) => {}
+                        Err(e) => error = error.or(Some(e)),
+                    }
+                }
+                Some(ch) if ch >= 0x80 => {
+                    self.bump();
+                    let mut bytes = vec![ch];
+                    let rest = self.take_while(String::new(), |&&ch| ch & 0xC0 == 0x80);
+                    bytes.extend(rest.chars().map(|ch| ch as u8));
+                    match std::str::from_utf8(&bytes) {
+                        Ok(text) => contents.extend(text.chars().map(Escape::Char)),
+                        Err(_) => contents.extend(bytes.into_iter().map(Escape::Byte)),
+                    }
+                }
+                Some(ch) => {
+                    self.bump();
+                    contents.push(Escape::Char(ch as char));
+                }
             }
-            if ch == b'"' {
-                found_end = true;
+        }
+        match error {
+            Some(e) => Err(e),
+            None => Ok(contents),
+        }
+    }
+
+    fn string(&mut self) -> Result<Token, LexErrorKind> {
+        let mut string = vec![];
+        for escape in self.quoted(b'"', LexErrorKind::UnterminatedString)? {
+            match escape {
+                Escape::Byte(byte) => string.push(byte),
+                Escape::Char(ch) => {
+                    string.extend_from_slice(ch.encode_utf8(&mut [0; 4]).as_bytes())
+                }
             }
-            true
-        })?;
-        Some(tk::String(string))
+        }
+        Ok(tk::String(string))
     }
 
     fn greater_than(&mut self) -> Option<Token> {
-        let next = self.peek().ok();
+        let next = self.peek();
         match next {
-            None => Some(tk::GreaterThan),
-            Some(next) => match next {
-                b'>' => {
-                    self.f.consume(1);
-                    Some(tk::RightLeft)
+            Some(b'>') => {
+                self.bump();
+                match self.peek() {
+                    Some(b'=') => {
+                        self.bump();
+                        Some(tk::ShiftRightAssign)
+                    }
+                    _ => Some(tk::ShiftRight),
                 }
-                b'=' => {
-                    self.f.consume(1);
-                    Some(tk::GreaterThanEqual)
-                }
-                _ => Some(tk::GreaterThan),
-            },
+            }
+            Some(b'=') => {
+                self.bump();
+                Some(tk::GreaterThanEqual)
+            }
+            _ => Some(tk::GreaterThan),
         }
     }
 
     fn less_than(&mut self) -> Option<Token> {
-        let next = self.peek().ok();
+        let next = self.peek();
         match next {
-            None => Some(tk::LessThan),
-            Some(next) => match next {
-                b'<' => {
-                    self.f.consume(1);
-                    Some(tk::ShiftLeft)
+            Some(b'<') => {
+                self.bump();
+                match self.peek() {
+                    Some(b'=') => {
+                        self.bump();
+                        Some(tk::ShiftLeftAssign)
+                    }
+                    _ => Some(tk::ShiftLeft),
                 }
-                b'=' => {
-                    self.f.consume(1);
-                    Some(tk::LessThanEqual)
-                }
-                _ => Some(tk::LessThan),
-            },
+            }
+            Some(b'=') => {
+                self.bump();
+                Some(tk::LessThanEqual)
+            }
+            _ => Some(tk::LessThan),
         }
     }
 
     fn not(&mut self) -> Option<Token> {
-        let next = self.peek().ok();
+        let next = self.peek();
         match next {
-            None => Some(tk::Not),
-            Some(next) => match next {
-                b'=' => {
-                    self.f.consume(1);
-                    Some(tk::NotEqual)
-                }
-                _ => Some(tk::Not),
-            },
+            Some(b'=') => {
+                self.bump();
+                Some(tk::NotEqual)
+            }
+            _ => Some(tk::Not),
         }
     }
 
     fn and(&mut self) -> Option<Token> {
-        let next = self.peek().ok();
+        let next = self.peek();
         match next {
-            None => Some(tk::BitAnd),
-            Some(next) => match next {
-                b'&' => {
-                    self.f.consume(1);
-                    Some(tk::And)
-                }
-                _ => Some(tk::BitAnd),
-            },
+            Some(b'&') => {
+                self.bump();
+                Some(tk::And)
+            }
+            Some(b'=') => {
+                self.bump();
+                Some(tk::BitAndAssign)
+            }
+            _ => Some(tk::BitAnd),
         }
     }
 
     fn minus(&mut self) -> Option<Token> {
-        let next = self.peek().ok();
+        let next = self.peek();
         match next {
-            None => Some(tk::Minus),
-            Some(next) => match next {
-                b'>' => {
-                    self.f.consume(1);
-                    Some(tk::Arrow)
-                }
-                _ => Some(tk::Minus),
-            },
+            Some(b'>') => {
+                self.bump();
+                Some(tk::Arrow)
+            }
+            Some(b'-') => {
+                self.bump();
+                Some(tk::Decrement)
+            }
+            Some(b'=') => {
+                self.bump();
+                Some(tk::MinusAssign)
+            }
+            _ => Some(tk::Minus),
+        }
+    }
+
+    fn plus(&mut self) -> Option<Token> {
+        let next = self.peek();
+        match next {
+            Some(b'+') => {
+                self.bump();
+                Some(tk::Increment)
+            }
+            Some(b'=') => {
+                self.bump();
+                Some(tk::PlusAssign)
+            }
+            _ => Some(tk::Plus),
+        }
+    }
+
+    fn star(&mut self) -> Option<Token> {
+        let next = self.peek();
+        match next {
+            Some(b'=') => {
+                self.bump();
+                Some(tk::StarAssign)
+            }
+            _ => Some(tk::Star),
+        }
+    }
+
+    fn percent(&mut self) -> Option<Token> {
+        let next = self.peek();
+        match next {
+            Some(b'=') => {
+                self.bump();
+                Some(tk::ModAssign)
+            }
+            _ => Some(tk::Mod),
+        }
+    }
+
+    fn caret(&mut self) -> Option<Token> {
+        let next = self.peek();
+        match next {
+            Some(b'=') => {
+                self.bump();
+                Some(tk::XorAssign)
+            }
+            _ => Some(tk::Xor),
+        }
+    }
+
+    fn colon(&mut self) -> Option<Token> {
+        let next = self.peek();
+        match next {
+            Some(b':') => {
+                self.bump();
+                Some(tk::DoubleColon)
+            }
+            _ => Some(tk::Colon),
+        }
+    }
+
+    fn dot(&mut self) -> Result<Option<Token>, LexErrorKind> {
+        match self.peek() {
+            Some(b'0'..=b'9') => self.number(b'.').map(Some),
+            // `..` on its own is two dots, only a third one makes an ellipsis
+            Some(b'.') if self.peek_at(1) == Some(b'.') => {
+                self.bump();
+                self.bump();
+                Ok(Some(tk::Ellipsis))
+            }
+            _ => Ok(Some(tk::Dot)),
         }
     }
 
     fn equal(&mut self) -> Option<Token> {
-        let next = self.peek().ok();
+        let next = self.peek();
         match next {
-            None => Some(tk::Assign),
-            Some(next) => match next {
-                b'=' => {
-                    self.f.consume(1);
-                    Some(tk::Equal)
-                }
-                _ => Some(tk::Assign),
-            },
+            Some(b'=') => {
+                self.bump();
+                Some(tk::Equal)
+            }
+            _ => Some(tk::Assign),
         }
     }
 
     fn or(&mut self) -> Option<Token> {
-        let next = self.peek().ok();
+        let next = self.peek();
         match next {
-            None => Some(tk::BitOr),
-            Some(next) => match next {
-                b'|' => {
-                    self.f.consume(1);
-                    Some(tk::Or)
-                }
-                _ => Some(tk::BitOr),
-            },
+            Some(b'|') => {
+                self.bump();
+                Some(tk::Or)
+            }
+            Some(b'=') => {
+                self.bump();
+                Some(tk::BitOrAssign)
+            }
+            _ => Some(tk::BitOr),
         }
     }
 
-    fn hash(&mut self, ch: u8) -> Option<Token> {
-        let directive = (ch as char).to_string();
-        let ident = self.take_while(directive, |&&ch| {
-            (b'A'..=b'Z').contains(&ch)
-                || (b'a'..=b'z').contains(&ch)
-                || (b'0'..=b'9').contains(&ch)
-                || ch == b'_'
-        })?;
-        Some(tk::Directive(ident))
+    fn hash(&mut self) -> Option<Token> {
+        let next = self.peek();
+        match next {
+            Some(b'#') => {
+                self.bump();
+                Some(tk::HashHash)
+            }
+            _ => Some(tk::Hash),
+        }
     }
 
-    fn slash(&mut self) -> Option<Token> {
-        let next = self.peek().ok()?;
-        match next {
-            b'/' => {
+    fn backslash(&mut self) -> Result<Option<Token>, LexErrorKind> {
+        // a backslash right before a line break splices the two lines
+        match (self.peek(), self.peek_at(1)) {
+            (Some(b'\n'), _) => self.bump(),
+            (Some(b'\r'), Some(b'\n')) => {
+                self.bump();
+                self.bump();
+            }
+            _ => return Err(LexErrorKind::UnknownCharacter('\\')),
+        }
+        Ok(None)
+    }
+
+    fn slash(&mut self) -> Result<Option<Token>, LexErrorKind> {
+        match self.peek() {
+            Some(b'/') => {
                 let comment = String::new();
-                self.take_while(comment, |&&ch| ch != b'\n')?;
-                self.next()
+                self.take_while(comment, |&&ch| ch != b'\n');
+                Ok(None)
             }
-            b'*' => {
-                self.f.consume(1);
+            Some(b'*') => {
+                self.bump();
                 let comment = String::new();
                 enum CommentState {
                     Looping,
@@ -330,109 +1083,502 @@ impl Lexer {
                         }
                         CommentState::Done => false,
                     },
-                })?;
-                self.next()
+                });
+                match state {
+                    CommentState::Done => Ok(None),
+                    _ => Err(LexErrorKind::UnterminatedComment),
+                }
+            }
+            Some(b'=') => {
+                self.bump();
+                Ok(Some(tk::DivAssign))
             }
-            _ => Some(tk::Div),
+            _ => Ok(Some(tk::Div)),
         }
     }
 
     fn identifier(&mut self, ch: u8) -> Option<Token> {
         let ident = (ch as char).to_string();
-        let ident = self.take_while(ident, |&&ch| {
-            (b'A'..=b'Z').contains(&ch)
-                || (b'a'..=b'z').contains(&ch)
-                || (b'0'..=b'9').contains(&ch)
-                || ch == b'_'
-        })?;
+        let ident = self.take_while(ident, |&&ch| ch.is_ascii_alphanumeric() || ch == b'_');
         Some(
-            Lexer::KEYWORDS
-                .get(&ident)
-                .cloned()
+            self.dialect
+                .keyword(&ident)
                 .unwrap_or(tk::Identifier(ident)),
         )
     }
 
-    fn number(&mut self, ch: u8) -> Option<Token> {
-        let number = (ch as char).to_string();
-        let mut is_float = false;
-        let number = self.take_while(number, |&&ch| {
-            if !is_float && ch == b'.' {
-                is_float = true;
-                return true;
-            }
-            (b'0'..=b'9').contains(&ch)
-        })?;
-        self.f.consume(number.len() - 1);
+    fn number(&mut self, ch: u8) -> Result<Token, LexErrorKind> {
+        // take the whole preprocessing number, signs are only allowed after exponents
+        let mut prev = ch;
+        let number = self.take_while((ch as char).to_string(), |&&ch| {
+            let accept = ch.is_ascii_alphanumeric()
+                || ch == b'_'
+                || ch == b'.'
+                || (matches!(ch, b'+' | b'-') && matches!(prev, b'e' | b'E' | b'p' | b'P'));
+            prev = ch;
+            accept
+        });
+        let lower = number.to_ascii_lowercase();
+        let (radix, body) = if let Some(rest) = lower.strip_prefix("0x") {
+            (16, rest)
+        } else if let Some(rest) = lower.strip_prefix("0b") {
+            (2, rest)
+        } else {
+            (10, lower.as_str())
+        };
+        let is_float = match radix {
+            16 => body.contains(['.', 'p']),
+            10 => body.contains(['.', 'e']),
+            _ => false,
+        };
         if is_float {
-            let mut parts = number.split('.');
-            let integral = parts.next().unwrap();
-            let fraction = parts.next().unwrap();
-            Some(tk::Float(Float {
-                integral: integral.parse().unwrap_or(0),
-                fractional: fraction.parse().unwrap_or(0),
-            }))
+            float_literal(&number, radix, body).map(tk::Float)
         } else {
-            Some(tk::Integer(number.parse().unwrap()))
+            let suffix_len = body.len() - body.trim_end_matches(['u', 'l']).len();
+            let suffix = &number[number.len() - suffix_len..];
+            let digits = &body[..body.len() - suffix_len];
+            integer_literal(&number, radix, digits, suffix).map(tk::Integer)
         }
     }
 
-    fn character(&mut self, ch: u8) -> Option<Token> {
-        let string = (ch as char).to_string();
-        let mut found_end = false;
-        let string = self.take_while(string, |&&ch| {
-            if found_end {
-                return false;
+    // a byte is sign extended as char is signed, while a character, even
+    // one past ASCII, gives its code point
+    fn character(&mut self) -> Result<Token, LexErrorKind> {
+        let contents = self.quoted(b'\'', LexErrorKind::UnterminatedCharacter)?;
+        match contents[..] {
+            [Escape::Byte(byte)] => Ok(tk::Character(byte as i8 as i32)),
+            [Escape::Char(ch)] => Ok(tk::Character(ch as i32)),
+            [] => Err(LexErrorKind::EmptyCharacter),
+            _ => {
+                let mut text = String::new();
+                for escape in contents {
+                    match escape {
+                        Escape::Byte(byte) => text.push_str(&format!("\\{byte:03o}")),
+                        Escape::Char(ch) => text.push(ch),
+                    }
+                }
+                Err(LexErrorKind::MultiCharacter(text))
             }
-            if ch == b'\'' {
-                found_end = true;
+        }
+    }
+
+    fn unknown(&mut self, ch: u8) -> LexErrorKind {
+        let mut bytes = vec![ch];
+        if ch >= 0x80 {
+            let rest = self.take_while(String::new(), |&&ch| ch & 0xC0 == 0x80);
+            bytes.extend(rest.chars().map(|ch| ch as u8));
+        }
+        let ch = String::from_utf8_lossy(&bytes)
+            .chars()
+            .next()
+            .unwrap_or(ch as char);
+        LexErrorKind::UnknownCharacter(ch)
+    }
+
+    fn lex(&mut self, ch: u8) -> Result<Option<Token>, LexErrorKind> {
+        match ch {
+            b'\'' => self.character().map(Some),
+            b'"' => self.string().map(Some),
+            b'*' => Ok(self.star()),
+            b'>' => Ok(self.greater_than()),
+            b'<' => Ok(self.less_than()),
+
+            b'!' => Ok(self.not()),
+            b'&' => Ok(self.and()),
+            b'-' => Ok(self.minus()),
+            b'=' => Ok(self.equal()),
+            b'|' => Ok(self.or()),
+            b'#' => Ok(self.hash()),
+            b',' => Ok(Some(tk::Comma)),
+            b'?' => Ok(Some(tk::Question)),
+            b'~' => Ok(Some(tk::BitNot)),
+            b'%' => Ok(self.percent()),
+            b'.' => self.dot(),
+            b'^' => Ok(self.caret()),
+            b':' => Ok(self.colon()),
+            b'/' => self.slash(),
+            b'+' => Ok(self.plus()),
+            b'(' => Ok(Some(tk::LeftParen)),
+            b')' => Ok(Some(tk::RightParen)),
+            b'}' => Ok(Some(tk::RightBrace)),
+            b'{' => Ok(Some(tk::LeftBrace)),
+            b'[' => Ok(Some(tk::LeftBracket)),
+            b']' => Ok(Some(tk::RightBracket)),
+            b';' => Ok(Some(tk::Semicolon)),
+            b'A'..=b'Z' | b'a'..=b'z' | b'_' => Ok(self.identifier(ch)),
+            b'0'..=b'9' => self.number(ch).map(Some),
+            b'\\' => self.backslash(),
+            b'\n' if self.newlines => Ok(Some(tk::Newline)),
+            b' ' | b'\n' | b'\r' | b'\t' | 0xB | 0xC => Ok(None), // ignore whitespacs
+            _ => Err(self.unknown(ch)),
+        }
+    }
+
+    fn token(&mut self) -> Option<Result<Token, LexErrorKind>> {
+        loop {
+            self.start = self.pos;
+            let ch = self.getch()?;
+            if let Some(token) = self.lex(ch).transpose() {
+                return Some(token);
             }
-            true
-        })?;
-        Some(tk::Character(string))
+        }
     }
 }
-impl Iterator for Lexer {
-    type Item = Token;
+
+impl<R: BufRead> Iterator for Lexer<R> {
+    type Item = Result<Spanned<Token>, LexError>;
     fn next(&mut self) -> Option<Self::Item> {
-        let ch = self.getch()?;
-        match ch {
-            b'\'' => self.character(ch),
-            b'"' => self.string(ch),
-            b'*' => Some(tk::Star),
-            b'>' => self.greater_than(),
-            b'<' => self.less_than(),
-
-            b'!' => self.not(),
-            b'&' => self.and(),
-            b'-' => self.minus(),
-            b'=' => self.equal(),
-            b'|' => self.or(),
-            b'#' => self.hash(ch),
-            b',' => Some(tk::Comma),
-            b'?' => Some(tk::Question),
-            b'~' => Some(tk::BitNot),
-            b'%' => Some(tk::Mod),
-            b'.' => Some(tk::Dot),
-            b'^' => Some(tk::Xor),
-            b':' => Some(tk::Colon), //TODO: DoubleColon
-            b'/' => self.slash(),
-            b'+' => Some(tk::Plus),
-            b'(' => Some(tk::LeftParen),
-            b')' => Some(tk::RightParen),
-            b'}' => Some(tk::RightBrace),
-            b'{' => Some(tk::LeftBrace),
-            b'[' => Some(tk::LeftBracket),
-            b']' => Some(tk::RightBracket),
-            b';' => Some(tk::Semicolon),
-            b'A'..=b'Z' | b'a'..=b'z' | b'_' => self.identifier(ch),
-            b'0'..=b'9' => self.number(ch),
-            //TODO: properly handle \
-            b' ' | b'\n' | b'\t' | 0xC | b'\\' => self.next(), // ignore whitespacs
-            _ => {
-                eprintln!("char is \"{}\" with code {}", ch as char, ch);
-                None
+        let Some(token) = self.token() else {
+            let kind = self.io_error.take()?;
+            return Some(Err(LexError {
+                kind: LexErrorKind::Io(kind),
+                span: Span::new(self.pos, self.pos),
+            }));
+        };
+        let span = self.span();
+        Some(match token {
+            Ok(node) => Ok(Spanned { node, span }),
+            Err(kind) => Err(LexError { kind, span }),
+        })
+    }
+}
+
+fn integer_suffix(suffix: &str) -> Option<IntegerSuffix> {
+    let long = suffix.trim_start_matches(['u', 'U']);
+    let long = long.strip_suffix(['u', 'U']).unwrap_or(long);
+    let unsigned = long.len() != suffix.len();
+    if suffix.len() - long.len() > 1 {
+        return None;
+    }
+    Some(match (unsigned, long) {
+        (false, "") => IntegerSuffix::None,
+        (true, "") => IntegerSuffix::Unsigned,
+        (false, "l" | "L") => IntegerSuffix::Long,
+        (true, "l" | "L") => IntegerSuffix::UnsignedLong,
+        (false, "ll" | "LL") => IntegerSuffix::LongLong,
+        (true, "ll" | "LL") => IntegerSuffix::UnsignedLongLong,
+        _ => return None,
+    })
+}
+
+fn integer_literal(
+    number: &str,
+    radix: u32,
+    digits: &str,
+    suffix: &str,
+) -> Result<Integer, LexErrorKind> {
+    let malformed = || LexErrorKind::MalformedNumber(number.to_string());
+    let suffix = integer_suffix(suffix).ok_or_else(malformed)?;
+    let radix = match radix {
+        10 if digits.len() > 1 && digits.starts_with('0') => 8,
+        _ => radix,
+    };
+    if digits.is_empty() {
+        return Err(malformed());
+    }
+    let mut value: u64 = 0;
+    let mut overflow = false;
+    for ch in digits.chars() {
+        let digit = ch.to_digit(radix).ok_or_else(malformed)?;
+        match value
+            .checked_mul(radix as u64)
+            .and_then(|v| v.checked_add(digit as u64))
+        {
+            Some(v) => value = v,
+            None => overflow = true,
+        }
+    }
+    if overflow {
+        return Err(LexErrorKind::NumberOverflow(number.to_string()));
+    }
+    Ok(Integer {
+        value,
+        radix,
+        suffix,
+    })
+}
+
+fn float_literal(number: &str, radix: u32, body: &str) -> Result<Float, LexErrorKind> {
+    let malformed = || LexErrorKind::MalformedNumber(number.to_string());
+    let (body, suffix) = match body.as_bytes().last() {
+        // `f` is a hex digit, hex floats can only carry a suffix after the exponent
+        Some(b'f') if radix == 10 || body.contains('p') => {
+            (&body[..body.len() - 1], FloatSuffix::Float)
+        }
+        Some(b'l') => (&body[..body.len() - 1], FloatSuffix::Long),
+        _ => (body, FloatSuffix::None),
+    };
+    let value = if radix == 16 {
+        let (mantissa, exponent) = body.split_once('p').ok_or_else(malformed)?;
+        let exponent: i32 = exponent.parse().map_err(|_| malformed())?;
+        let (integral, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        if integral.is_empty() && fraction.is_empty() {
+            return Err(malformed());
+        }
+        let mut value = 0f64;
+        for ch in integral.chars().chain(fraction.chars()) {
+            value = value * 16.0 + ch.to_digit(16).ok_or_else(malformed)? as f64;
+        }
+        value * 2f64.powi(exponent.saturating_sub(4 * fraction.len() as i32))
+    } else {
+        if !body.starts_with(|ch: char| ch.is_ascii_digit() || ch == '.') {
+            return Err(malformed());
+        }
+        body.parse::<f64>().map_err(|_| malformed())?
+    };
+    if value.is_infinite() {
+        return Err(LexErrorKind::NumberOverflow(number.to_string()));
+    }
+    Ok(Float { value, suffix })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Dialect, Float, FloatSuffix, Integer, IntegerSuffix, LexErrorKind, Lexer, Span, Token,
+    };
+    use std::io::{self, Read};
+
+    fn lex(source: &str) -> Result<Vec<Token>, LexErrorKind> {
+        let tokens = Lexer::from_source(source).map(|token| token.map(|t| t.node));
+        tokens.collect::<Result<_, _>>().map_err(|e| e.kind)
+    }
+
+    #[test]
+    fn character_constants_are_single_values() {
+        let source = r"'a' '\n' '\0' '\x7f' '\xff' '\377' 'é' '\u00e9' '\U0001F600'";
+        let values = [97, 10, 0, 127, -1, -1, 0xE9, 0xE9, 0x1F600];
+        assert_eq!(lex(source), Ok(values.map(Token::Character).to_vec()));
+        for source in [r"'ab'", r"'\xc3\xa9'", "'é!'"] {
+            assert!(matches!(lex(source), Err(LexErrorKind::MultiCharacter(_))));
+        }
+        assert_eq!(lex("''"), Err(LexErrorKind::EmptyCharacter));
+        // the spelling reads back as the same value
+        for value in values {
+            let spelling = Token::Character(value).to_string();
+            assert_eq!(lex(&spelling), Ok(vec![Token::Character(value)]));
+        }
+        let string = Token::String("é\u{e9}".as_bytes().to_vec());
+        assert_eq!(lex(r#""é\u00e9""#), Ok(vec![string]));
+    }
+
+    fn spans(source: &str) -> Vec<Span> {
+        let tokens = Lexer::from_source(source).map(|token| token.unwrap().span);
+        tokens.collect()
+    }
+
+    #[test]
+    fn spans_give_lines_columns_and_text() {
+        let source = "int x;\n  /* two\nlines */ x += 0x1f;\r\n\"é\" y";
+        let spans = spans(source);
+        let positions: Vec<_> = spans.iter().map(|s| (s.line, s.column)).collect();
+        let expected = [
+            (1, 1),
+            (1, 5),
+            (1, 6),
+            (3, 10),
+            (3, 12),
+            (3, 15),
+            (3, 19),
+            (4, 1),
+            (4, 5),
+        ];
+        assert_eq!(positions, expected);
+        let text: Vec<_> = spans.iter().map(|s| s.snippet(source).unwrap()).collect();
+        assert_eq!(
+            text,
+            ["int", "x", ";", "x", "+=", "0x1f", ";", "\"é\"", "y"]
+        );
+        assert_eq!(spans[4].source_line(source), Some("lines */ x += 0x1f;"));
+        let covering = spans[5].to(spans[3]);
+        assert_eq!(covering.snippet(source), Some("x += 0x1f"));
+        assert_eq!(covering.to_string(), "3:10");
+    }
+
+    #[test]
+    fn readers_give_the_same_tokens_as_strings() {
+        let source = "x >>= 1.5e+3 ... '\\x41' \"a\\\nb\" // done\n";
+        let expected = lex(source).unwrap();
+        // a one byte buffer makes every lookahead cross a refill
+        let reader = io::BufReader::with_capacity(1, source.as_bytes());
+        let tokens = Lexer::new(reader).map(|token| token.unwrap().node);
+        assert_eq!(tokens.collect::<Vec<_>>(), expected);
+        let tokens = Lexer::from_reader(source.as_bytes()).map(|token| token.unwrap().node);
+        assert_eq!(tokens.collect::<Vec<_>>(), expected);
+        // a failing reader ends the tokens with one i/o error
+        let failing = source.as_bytes().chain(Failing);
+        let results: Vec<_> = Lexer::from_reader(failing).collect();
+        assert_eq!(results.len(), expected.len() + 1);
+        let error = results.last().unwrap().as_ref().unwrap_err();
+        assert_eq!(error.kind, LexErrorKind::Io(io::ErrorKind::Other));
+    }
+
+    struct Failing;
+
+    impl io::Read for Failing {
+        fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+            Err(io::Error::other("unplugged"))
+        }
+    }
+
+    #[test]
+    fn errors_carry_spans_and_lexing_goes_on() {
+        let source = "a @ b\n\"open\nc '\\q' d /* never closed";
+        let results: Vec<_> = Lexer::from_source(source).collect();
+        let summary: Vec<_> = results
+            .iter()
+            .map(|result| match result {
+                Ok(token) => Ok(token.node.to_string()),
+                Err(e) => Err((e.kind.clone(), e.span.line, e.span.column)),
+            })
+            .collect();
+        let expected = [
+            Ok("a".to_string()),
+            Err((LexErrorKind::UnknownCharacter('@'), 1, 3)),
+            Ok("b".to_string()),
+            Err((LexErrorKind::UnterminatedString, 2, 1)),
+            Ok("c".to_string()),
+            Err((LexErrorKind::InvalidEscape("q".to_string()), 3, 3)),
+            Ok("d".to_string()),
+            Err((LexErrorKind::UnterminatedComment, 3, 10)),
+        ];
+        assert_eq!(summary, expected);
+        let error = results[1].as_ref().unwrap_err();
+        assert_eq!(error.to_string(), "1:3: unknown character '@' (U+0040)");
+        assert_eq!(lex("€"), Err(LexErrorKind::UnknownCharacter('€')));
+    }
+
+    #[test]
+    fn string_escapes_decode_to_bytes() {
+        let string = |source: &str| match lex(source) {
+            Ok(tokens) => match &tokens[..] {
+                [Token::String(bytes)] => Ok(bytes.clone()),
+                _ => panic!("not one string: {tokens:?}"),
+            },
+            Err(e) => Err(e),
+        };
+        let source = r#""\a\b\f\n\r\t\v\\\'\"\?""#;
+        assert_eq!(string(source), Ok(b"\x07\x08\x0c\n\r\t\x0b\\'\"?".to_vec()));
+        // octal takes at most three digits, hex as many as there are
+        assert_eq!(string(r#""\1234\x41g\0""#), Ok(b"S4Ag\0".to_vec()));
+        assert_eq!(
+            string(r#""\u00e9\U0001F600""#),
+            Ok("é😀".as_bytes().to_vec())
+        );
+        assert_eq!(string("\"a\\\nb\""), Ok(b"ab".to_vec()));
+        for (source, escape) in [
+            (r#""\x""#, "x"),
+            (r#""\x100""#, "x100"),
+            (r#""\400""#, "400"),
+            (r#""\u12""#, "u12"),
+            (r#""\uD800""#, "uD800"),
+            (r#""\q""#, "q"),
+        ] {
+            let error = LexErrorKind::InvalidEscape(escape.to_string());
+            assert_eq!(string(source), Err(error), "{source}");
+        }
+        // the spelling of a string reads back as the same bytes
+        let bytes = b"tab\t\"quote\" \xff\x01 \xc3\xa9".to_vec();
+        let spelling = Token::String(bytes.clone()).to_string();
+        assert_eq!(string(&spelling), Ok(bytes));
+    }
+
+    #[test]
+    fn numbers_take_radix_suffix_and_exponent() {
+        let integer = |value, radix, suffix| {
+            Token::Integer(Integer {
+                value,
+                radix,
+                suffix,
+            })
+        };
+        let float = |value, suffix| Token::Float(Float { value, suffix });
+        let source = "0 0x1F 017 0b101 42ul 10LLU 18446744073709551615u";
+        let expected = [
+            integer(0, 10, IntegerSuffix::None),
+            integer(31, 16, IntegerSuffix::None),
+            integer(15, 8, IntegerSuffix::None),
+            integer(5, 2, IntegerSuffix::None),
+            integer(42, 10, IntegerSuffix::UnsignedLong),
+            integer(10, 10, IntegerSuffix::UnsignedLongLong),
+            integer(u64::MAX, 10, IntegerSuffix::Unsigned),
+        ];
+        assert_eq!(lex(source), Ok(expected.to_vec()));
+        let source = "1.5e3 .25f 1e-2 6.L 0x1.8p1 0x10p-4f";
+        let expected = [
+            float(1500.0, FloatSuffix::None),
+            float(0.25, FloatSuffix::Float),
+            float(0.01, FloatSuffix::None),
+            float(6.0, FloatSuffix::Long),
+            float(3.0, FloatSuffix::None),
+            float(1.0, FloatSuffix::Float),
+        ];
+        assert_eq!(lex(source), Ok(expected.to_vec()));
+        // a sign only continues a number right after an exponent letter
+        let tokens = lex("1+2").unwrap();
+        assert_eq!(tokens.len(), 3);
+        for source in [
+            "1.2.3", "0x", "09", "12abc", "1e+", "0x1e+1", "1lul", "0x1.8",
+        ] {
+            let error = LexErrorKind::MalformedNumber(source.to_string());
+            assert_eq!(lex(source), Err(error), "{source}");
+        }
+        for source in ["18446744073709551616", "1e999"] {
+            let error = LexErrorKind::NumberOverflow(source.to_string());
+            assert_eq!(lex(source), Err(error), "{source}");
+        }
+    }
+
+    #[test]
+    fn operators_take_the_longest_spelling() {
+        let spellings = [
+            "+", "-", "*", "/", "%", "++", "--", "&", "|", "^", "~", "!", "&&", "||", "<<", ">>",
+            "<", "<=", ">", ">=", "==", "!=", "=", "+=", "-=", "*=", "/=", "%=", "&=", "|=", "^=",
+            "<<=", ">>=", "->", ".", "...", "?", ":", "::", ",", ";", "#", "##",
+        ];
+        for spelling in spellings {
+            let tokens = lex(spelling).unwrap();
+            assert_eq!(tokens.len(), 1, "{spelling}");
+            assert_eq!(tokens[0].to_string(), spelling);
+        }
+        let spelled = |source: &str| {
+            let tokens = lex(source).unwrap();
+            tokens.iter().map(Token::to_string).collect::<Vec<_>>()
+        };
+        assert_eq!(spelled("a+++b"), ["a", "++", "+", "b"]);
+        assert_eq!(spelled("x-->y"), ["x", "--", ">", "y"]);
+        assert_eq!(spelled("a&&&b"), ["a", "&&", "&", "b"]);
+        assert_eq!(spelled("p->q..r"), ["p", "->", "q", ".", ".", "r"]);
+        assert_eq!(spelled("a<<=b>>c"), ["a", "<<=", "b", ">>", "c"]);
+    }
+
+    #[test]
+    fn keywords_depend_on_the_dialect() {
+        let dialects = [Dialect::C89, Dialect::C99, Dialect::C11, Dialect::Rem];
+        // the first dialect each word is a keyword in
+        let words = [
+            ("while", 0),
+            ("sizeof", 0),
+            ("inline", 1),
+            ("_Bool", 1),
+            ("_Static_assert", 2),
+            ("_Thread_local", 2),
+            ("fn", 3),
+        ];
+        for (word, since) in words {
+            for (i, dialect) in dialects.into_iter().enumerate() {
+                let tokens = Lexer::from_source(word).with_dialect(dialect);
+                let token = tokens.map(|token| token.unwrap().node).next().unwrap();
+                let keyword = dialect.keyword(word);
+                assert_eq!(keyword.is_some(), i >= since, "{word} in {dialect:?}");
+                let expected = keyword.unwrap_or(Token::Identifier(word.to_string()));
+                assert_eq!(token, expected);
+                assert_eq!(token.to_string(), word);
             }
         }
+        assert_eq!(Lexer::from_source("").dialect(), Dialect::C11);
+        // keywords are whole identifiers only
+        let tokens = lex("int_ Int _Boolean").unwrap();
+        assert!(tokens.iter().all(|t| matches!(t, Token::Identifier(_))));
     }
 }
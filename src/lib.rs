@@ -1,2 +1,10 @@
+pub mod ast;
+pub mod constant;
+pub mod layout;
 pub mod lexer;
 pub mod parser;
+pub mod preprocessor;
+pub mod sema;
+pub mod syntax;
+pub mod typeck;
+pub mod types;
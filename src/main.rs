@@ -1,46 +1,83 @@
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fs::File,
+    io,
+};
 
-use std::collections::{HashMap, HashSet};
-
-use rem::parser::{Grammar, Symbol, Terminal};
+use rem::{
+    lexer::Lexer,
+    parser::{Grammar, LrKind, Symbol, Terminal},
+    preprocessor::{Preprocessor, INCLUDE_DIR},
+    sema,
+    syntax::Parser,
+    typeck,
+};
 
 fn print_hashmap(set: HashMap<Symbol, HashSet<Terminal>>) {
     for (k, v) in set {
-        match k {
-            rem::parser::Symbol::Terminal(t) => match t {
-                rem::parser::Terminal::Token(t) => {
-                    if let rem::lexer::Token::String(s) = t {
-                        print!("{s:?}")
-                    } else {
-                        print!("{t:?}")
-                    }
-                }
-                rem::parser::Terminal::Epsilon => print!("Ep30lon"),
-            },
-            rem::parser::Symbol::NonTerminal(t) => print!("{t:?}"),
-        }
-        print!(" => ");
+        print!("{k} => ");
         for vv in v {
-            match vv {
-                rem::parser::Terminal::Token(t) => {
-                    if let rem::lexer::Token::String(s) = t {
-                        print!("{s:?}, ")
-                    } else {
-                        print!("{t:?}, ")
-                    }
-                }
-                rem::parser::Terminal::Epsilon => print!("Ep30lon, "),
-            };
+            print!("{vv}, ");
         }
         println!();
     }
 }
-fn main() -> std::io::Result<()> {
-    // let f = File::open("test.c")?;
-    // let tokens = Lexer::from(f);
-    // for token in tokens {
-    //     println!("{:?}", token);
-    // }
-    let grammar = Grammar::from_stdin();
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    // `-I dir` and `-Idir` add include directories, searched in order
+    let mut args = vec![];
+    let mut include_paths = vec![];
+    let mut rest = env::args().skip(1);
+    while let Some(arg) = rest.next() {
+        match arg.strip_prefix("-I") {
+            Some("") => include_paths.extend(rest.next()),
+            Some(dir) => include_paths.push(dir.to_string()),
+            None => args.push(arg),
+        }
+    }
+    // C sources are parsed into a syntax tree instead of read as a grammar
+    if let Some(path) = args.first().filter(|path| path.ends_with(".c")) {
+        let preprocessor = include_paths
+            .into_iter()
+            .chain([INCLUDE_DIR.to_string()])
+            .fold(Preprocessor::new(), Preprocessor::with_include_path);
+        let unit = Parser::with_preprocessor(preprocessor, path)
+            .and_then(|mut parser| parser.translation_unit())
+            .map_err(|e| e.to_string())?;
+        println!("{unit:#?}");
+        match sema::analyze(&unit) {
+            Ok(analysis) => {
+                if let Err(errors) = typeck::check(&unit, &analysis) {
+                    for error in errors {
+                        eprintln!("{path}:{error}");
+                    }
+                }
+            }
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{path}:{error}");
+                }
+            }
+        }
+        return Ok(());
+    }
+    let grammar = match args.first() {
+        Some(path) => Grammar::from_file(path)?,
+        None => io::read_to_string(io::stdin())?.parse()?,
+    };
+    let (grammar, warnings) = grammar.reduce();
+    for warning in warnings {
+        eprintln!("warning: {warning}");
+    }
+    println!("{grammar}");
+    let tokens = match args.get(1) {
+        Some(path) => Some(
+            Lexer::from(File::open(path)?)
+                .map(|token| token.map(|t| t.node))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        None => None,
+    };
     println!("printing first set of the grammmar");
     let first_set = grammar.first_set();
     print_hashmap(first_set);
@@ -48,5 +85,37 @@ fn main() -> std::io::Result<()> {
     println!("printing follow set of the grammmar");
     let follow_set = grammar.follow_set();
     print_hashmap(follow_set);
+    println!("======================================");
+    match grammar.ll1_table() {
+        Ok(table) => {
+            print!("{table}");
+            if let Some(tokens) = &tokens {
+                println!("======================================");
+                print!("{}", table.parse(tokens.clone())?);
+            }
+        }
+        Err(conflicts) => {
+            println!("grammar is not LL(1)");
+            for conflict in conflicts {
+                println!("{conflict}");
+            }
+        }
+    }
+    println!("======================================");
+    match grammar.lr_table(LrKind::Lalr1) {
+        Ok(table) => {
+            print!("{table}");
+            if let Some(tokens) = tokens {
+                println!("======================================");
+                print!("{}", table.parse(tokens)?);
+            }
+        }
+        Err(conflicts) => {
+            println!("grammar is not LALR(1)");
+            for conflict in conflicts {
+                println!("{conflict}");
+            }
+        }
+    }
     Ok(())
 }
@@ -1,186 +1,504 @@
 use std::{
     collections::{HashMap, HashSet},
-    io,
+    error, fmt, fs,
+    io::ErrorKind,
+    path::Path,
+    str::FromStr,
 };
 
 use crate::lexer::Token;
 
-type NonTerminal = String;
+mod analysis;
+mod bnf;
+mod cyk;
+mod earley;
+mod ll;
+mod lr;
+mod normal;
+mod transform;
+mod tree;
+
+pub use cyk::CykTable;
+pub use earley::{Forest, ForestNode, Packed};
+pub use ll::{LlConflict, LlConflictKind, LlTable};
+pub use lr::{Action, LrAutomaton, LrConflict, LrConflictKind, LrItem, LrKind, LrTable};
+pub use normal::{NormalForm, Origin};
+pub use tree::{ParseTree, SyntaxError};
+
+pub type NonTerminal = String;
+
+// terminals standing for any token of a kind, e.g. every identifier
+#[derive(PartialEq, Clone, Copy, Eq, Hash, Debug)]
+pub enum TokenClass {
+    Identifier,
+    Integer,
+    Float,
+    String,
+    Character,
+}
+
+impl TokenClass {
+    pub fn of(token: &Token) -> Option<Self> {
+        Some(match token {
+            Token::Identifier(_) => TokenClass::Identifier,
+            Token::Integer(_) => TokenClass::Integer,
+            Token::Float(_) => TokenClass::Float,
+            Token::String(_) => TokenClass::String,
+            Token::Character(_) => TokenClass::Character,
+            _ => return None,
+        })
+    }
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "identifier" => TokenClass::Identifier,
+            "integer" => TokenClass::Integer,
+            "float" => TokenClass::Float,
+            "string" => TokenClass::String,
+            "character" => TokenClass::Character,
+            _ => return None,
+        })
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            TokenClass::Identifier => "identifier",
+            TokenClass::Integer => "integer",
+            TokenClass::Float => "float",
+            TokenClass::String => "string",
+            TokenClass::Character => "character",
+        }
+    }
+}
+
 #[derive(PartialEq, Clone, Eq, Hash, Debug)]
 pub enum Terminal {
     Token(Token),
+    Class(TokenClass),
     Epsilon,
 }
 
+impl Terminal {
+    pub fn matches(&self, token: &Token) -> bool {
+        match self {
+            Terminal::Token(t) => t == token,
+            Terminal::Class(class) => TokenClass::of(token) == Some(*class),
+            Terminal::Epsilon => false,
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Hash, Clone, Debug)]
 pub enum Symbol {
     Terminal(Terminal),
     NonTerminal(NonTerminal),
 }
-type Production = Vec<Symbol>;
+pub type Production = Vec<Symbol>;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GrammarError {
+    Io(ErrorKind),
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for GrammarError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrammarError::Io(kind) => write!(f, "failed to read grammar: {kind}"),
+            GrammarError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(f, "line {line}, column {column}: {message}"),
+        }
+    }
+}
+
+impl error::Error for GrammarError {}
+
+// what `Grammar::reduce` removed and why
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GrammarWarning {
+    Undefined(NonTerminal),
+    Unproductive(NonTerminal),
+    Unreachable(NonTerminal),
+    UnusedTerminal(Terminal),
+}
+
+impl fmt::Display for GrammarWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GrammarWarning::Undefined(nt) => write!(f, "{nt} is used but has no rules"),
+            GrammarWarning::Unproductive(nt) => write!(f, "{nt} derives no string of terminals"),
+            GrammarWarning::Unreachable(nt) => write!(f, "{nt} is unreachable from the start"),
+            GrammarWarning::UnusedTerminal(t) => write!(f, "terminal {t} is never used"),
+        }
+    }
+}
 
-#[derive(Default)]
+impl fmt::Display for Terminal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Terminal::Token(token) => {
+                let spelling = token.to_string();
+                match spelling.contains('\'') {
+                    true => write!(f, "\"{spelling}\""),
+                    false => write!(f, "'{spelling}'"),
+                }
+            }
+            Terminal::Class(class) => write!(f, "%{}", class.name()),
+            Terminal::Epsilon => write!(f, "%empty"),
+        }
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Symbol::Terminal(t) => write!(f, "{t}"),
+            Symbol::NonTerminal(nt) => write!(f, "{nt}"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Associativity {
+    Left,
+    Right,
+    NonAssoc,
+}
+
+// yacc style precedence, later declarations bind tighter
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Precedence {
+    pub level: usize,
+    pub associativity: Associativity,
+}
+
+#[derive(Default, Debug, Clone)]
 pub struct Grammar {
     start: NonTerminal,
     rules: HashMap<NonTerminal, Vec<Production>>,
     terminals: HashSet<Terminal>,
     non_terminals: HashSet<NonTerminal>,
+    precedence: HashMap<Terminal, Precedence>,
+    // `%prec` overrides, keyed by non-terminal and production index
+    prec_overrides: HashMap<(NonTerminal, usize), Terminal>,
 }
 
 impl Grammar {
-    pub fn from_stdin() -> Self {
-        let mut grammar: Self = Default::default();
-        println!("enter rules as SYMBOL => terminal + NONTERMINAL");
-        println!("enter END to end getting rules");
-        let mut input = String::from("start");
-        loop {
-            input.clear();
-            io::stdin()
-                .read_line(&mut input)
-                .expect("failed to read line");
-            input = input.trim().to_string();
-            if input == "END" {
-                break;
-            }
-            let mut parts = input.split("=>");
-            let symbol = parts.next().expect("invalid syntax").trim().to_string();
-            let rest = parts.next().expect("invalid syntax").trim().to_string();
-            let parts = rest.split_whitespace();
-            let mut rest: Production = vec![];
-            for part in parts {
-                if part.to_string().chars().all(char::is_alphabetic) && part == part.to_uppercase()
-                {
-                    rest.push(Symbol::NonTerminal(part.to_string()));
-                    grammar.non_terminals.insert(part.to_string());
-                } else {
-                    if part == "ep30" {
-                        rest.push(Symbol::Terminal(Terminal::Epsilon));
-                        grammar.terminals.insert(Terminal::Epsilon);
-                    } else {
-                        let token = Terminal::Token(Token::String(part.to_string()));
-                        rest.push(Symbol::Terminal(token.clone()));
-                        grammar.terminals.insert(token);
-                    }
-                }
-            }
-            grammar.rules.entry(symbol.clone()).or_default().push(rest);
-            grammar.non_terminals.insert(symbol);
-        }
-        loop {
-            println!("set start symbol");
-            input.clear();
-            io::stdin()
-                .read_line(&mut input)
-                .expect("failed to read line");
-            input = input.trim().to_string();
-            if grammar.non_terminals.contains(&input) {
-                grammar.start = input;
-                break;
-            }
-            print!("your nonterminals are: ");
-            for v in &grammar.non_terminals {
-                print!("{v} ");
-            }
-            println!();
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, GrammarError> {
+        let text = fs::read_to_string(path).map_err(|e| GrammarError::Io(e.kind()))?;
+        text.parse()
+    }
+    pub fn start(&self) -> &NonTerminal {
+        &self.start
+    }
+    pub fn productions(&self, non_terminal: &str) -> &[Production] {
+        self.rules.get(non_terminal).map_or(&[], Vec::as_slice)
+    }
+    // start symbol first, then the rest by name so output is stable
+    pub fn non_terminals(&self) -> Vec<&NonTerminal> {
+        let mut non_terminals: Vec<_> = self
+            .non_terminals
+            .iter()
+            .filter(|nt| **nt != self.start)
+            .collect();
+        non_terminals.sort();
+        non_terminals.insert(0, &self.start);
+        non_terminals
+    }
+    pub fn terminals(&self) -> &HashSet<Terminal> {
+        &self.terminals
+    }
+    fn add_rule(&mut self, lhs: NonTerminal, production: Production) -> usize {
+        for symbol in &production {
+            match symbol {
+                Symbol::Terminal(t) => self.terminals.insert(t.clone()),
+                Symbol::NonTerminal(nt) => self.non_terminals.insert(nt.clone()),
+            };
+        }
+        self.non_terminals.insert(lhs.clone());
+        let productions = self.rules.entry(lhs).or_default();
+        productions.push(production);
+        productions.len() - 1
+    }
+    // adds a level binding tighter than every earlier declaration
+    pub fn declare_precedence(
+        &mut self,
+        associativity: Associativity,
+        terminals: impl IntoIterator<Item = Terminal>,
+    ) {
+        let level = self.precedence.values().map(|p| p.level).max().unwrap_or(0) + 1;
+        for t in terminals {
+            let precedence = Precedence {
+                level,
+                associativity,
+            };
+            self.precedence.insert(t, precedence);
         }
-
-        grammar
+    }
+    pub fn precedence(&self, terminal: &Terminal) -> Option<Precedence> {
+        self.precedence.get(terminal).copied()
+    }
+    // the `%prec` terminal if given, otherwise the last terminal of the
+    // production that has a precedence
+    pub fn production_precedence(&self, non_terminal: &str, index: usize) -> Option<Precedence> {
+        if let Some(t) = self.prec_overrides.get(&(non_terminal.to_string(), index)) {
+            return self.precedence(t);
+        }
+        let production = self.productions(non_terminal).get(index)?;
+        production.iter().rev().find_map(|symbol| match symbol {
+            Symbol::Terminal(t) => self.precedence(t),
+            Symbol::NonTerminal(_) => None,
+        })
     }
     pub fn follow_set(&self) -> HashMap<Symbol, HashSet<Terminal>> {
-        let mut follow: HashMap<Symbol, HashSet<Terminal>> = Default::default();
-        let mut first = self.first_set();
+        let first = self.first_set();
+        let mut follow: HashMap<Symbol, HashSet<Terminal>> = HashMap::new();
         for nt in &self.non_terminals {
-            let s = Symbol::NonTerminal(nt.clone());
-            follow.entry(s).or_default();
+            follow.insert(Symbol::NonTerminal(nt.clone()), HashSet::new());
         }
-        let s = Symbol::NonTerminal(self.start.clone());
+        let start = Symbol::NonTerminal(self.start.clone());
         follow
-            .entry(s)
+            .entry(start)
             .or_default()
             .insert(Terminal::Token(Token::EOF));
         let mut changing = true;
         while changing {
             changing = false;
-            for (key, value) in &self.rules {
-                for rule in value {
-                    let s = Symbol::NonTerminal(key.clone());
-                    let mut trailer = follow.entry(s).or_default().clone();
-                    for b in rule.iter().rev() {
-                        match b {
-                            Symbol::Terminal(t) => {
-                                trailer = Default::default();
-                                trailer.insert(t.clone());
-                            }
-                            Symbol::NonTerminal(_nt) => {
-                                for symbol in trailer.iter() {
-                                    let follow_b = follow.entry(b.clone()).or_default();
-                                    changing = follow_b.insert(symbol.clone()) || changing;
-                                }
-                                let mut first_b = first.entry(b.clone()).or_default().clone();
-                                if first_b.contains(&Terminal::Epsilon) {
-                                    first_b.remove(&Terminal::Epsilon);
-                                    trailer.extend(first_b);
-                                } else {
-                                    trailer = first_b;
-                                }
-                            }
+            for (lhs, productions) in &self.rules {
+                let lhs = Symbol::NonTerminal(lhs.clone());
+                for production in productions {
+                    for (i, symbol) in production.iter().enumerate() {
+                        if !matches!(symbol, Symbol::NonTerminal(_)) {
+                            continue;
+                        }
+                        let mut trailer = first_of_sequence(&first, &production[i + 1..]);
+                        if trailer.remove(&Terminal::Epsilon) {
+                            trailer.extend(follow[&lhs].iter().cloned());
                         }
+                        let entry = follow.entry(symbol.clone()).or_default();
+                        let before = entry.len();
+                        entry.extend(trailer);
+                        changing |= entry.len() != before;
                     }
                 }
             }
         }
         follow
     }
+    // FIRST of every symbol; a non-terminal contains epsilon when it can
+    // vanish, which includes having an empty production
     pub fn first_set(&self) -> HashMap<Symbol, HashSet<Terminal>> {
-        let mut first: HashMap<Symbol, HashSet<Terminal>> = Default::default();
+        let mut first: HashMap<Symbol, HashSet<Terminal>> = HashMap::new();
         for t in &self.terminals {
-            let s = Symbol::Terminal(t.clone());
-            first.entry(s).or_default().insert(t.clone());
+            first.insert(Symbol::Terminal(t.clone()), HashSet::from([t.clone()]));
         }
         for nt in &self.non_terminals {
-            let s = Symbol::NonTerminal(nt.clone());
-            first.entry(s).or_default();
+            first.insert(Symbol::NonTerminal(nt.clone()), HashSet::new());
         }
         let mut changing = true;
         while changing {
             changing = false;
-            for (key, value) in &self.rules {
-                for rule in value {
-                    // let s = Symbol::NonTerminal(key.clone());
-                    let s = rule.first().unwrap().clone();
-                    let mut rhs = first.entry(s).or_default().clone();
-                    rhs.remove(&Terminal::Epsilon);
-                    let mut trailing = true;
-                    for (pos, v) in rule[..rule.len() - 1].iter().enumerate() {
-                        if first
-                            .entry(v.clone())
-                            .or_default()
-                            .contains(&Terminal::Epsilon)
-                        {
-                            let mut left = first.entry(rule[pos + 1].clone()).or_default().clone();
-                            left.remove(&Terminal::Epsilon);
-                            for symbol in left {
-                                rhs.insert(symbol);
-                            }
-                        } else {
-                            trailing = false;
-                            break;
-                        }
-                    }
-                    let last = rule.last().unwrap().clone();
-                    let last = first.entry(last).or_default().clone();
-                    if trailing && last.contains(&Terminal::Epsilon) {
-                        rhs.insert(Terminal::Epsilon);
-                    }
-                    let s = Symbol::NonTerminal(key.clone());
-                    let key_first = first.entry(s).or_default();
-                    for symbol in rhs {
-                        changing = key_first.insert(symbol) || changing;
-                    }
+            for (lhs, productions) in &self.rules {
+                for production in productions {
+                    let set = first_of_sequence(&first, production);
+                    let entry = first.entry(Symbol::NonTerminal(lhs.clone())).or_default();
+                    let before = entry.len();
+                    entry.extend(set);
+                    changing |= entry.len() != before;
                 }
             }
         }
-
         first
     }
 }
+
+// stable order for printing, end of input last
+fn sorted(terminals: impl IntoIterator<Item = Terminal>) -> Vec<Terminal> {
+    let mut terminals: Vec<_> = terminals.into_iter().collect();
+    terminals.sort_by_cached_key(|t| match t {
+        Terminal::Token(Token::EOF) => (1, String::new()),
+        t => (0, t.to_string()),
+    });
+    terminals
+}
+
+// FIRST of a string of symbols given the FIRST sets of a grammar; it has
+// epsilon only if every symbol can vanish, so an empty string gives just
+// epsilon
+pub fn first_of_sequence(
+    first: &HashMap<Symbol, HashSet<Terminal>>,
+    symbols: &[Symbol],
+) -> HashSet<Terminal> {
+    let mut result = HashSet::new();
+    for symbol in symbols {
+        match symbol {
+            Symbol::Terminal(Terminal::Epsilon) => continue,
+            Symbol::Terminal(t) => {
+                result.insert(t.clone());
+                return result;
+            }
+            Symbol::NonTerminal(_) => {
+                let Some(set) = first.get(symbol) else {
+                    return result;
+                };
+                result.extend(set.iter().filter(|t| **t != Terminal::Epsilon).cloned());
+                if !set.contains(&Terminal::Epsilon) {
+                    return result;
+                }
+            }
+        }
+    }
+    result.insert(Terminal::Epsilon);
+    result
+}
+
+impl FromStr for Grammar {
+    type Err = GrammarError;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        bnf::parse(text)
+    }
+}
+
+// prints the grammar in the format `FromStr` reads
+impl fmt::Display for Grammar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "%start {}", self.start)?;
+        let mut levels: Vec<(Precedence, Vec<Terminal>)> = Vec::new();
+        for (t, precedence) in &self.precedence {
+            match levels.iter_mut().find(|(p, _)| p == precedence) {
+                Some((_, terminals)) => terminals.push(t.clone()),
+                None => levels.push((*precedence, vec![t.clone()])),
+            }
+        }
+        levels.sort_by_key(|(p, _)| p.level);
+        for (precedence, terminals) in levels {
+            let directive = match precedence.associativity {
+                Associativity::Left => "%left",
+                Associativity::Right => "%right",
+                Associativity::NonAssoc => "%nonassoc",
+            };
+            write!(f, "{directive}")?;
+            for t in sorted(terminals) {
+                write!(f, " {t}")?;
+            }
+            writeln!(f)?;
+        }
+        for nt in self.non_terminals() {
+            for (i, production) in self.productions(nt).iter().enumerate() {
+                match i {
+                    0 => write!(f, "{nt} =>")?,
+                    _ => write!(f, "{:width$} |", "", width = nt.chars().count())?,
+                }
+                for symbol in production {
+                    write!(f, " {symbol}")?;
+                }
+                if let Some(t) = self.prec_overrides.get(&(nt.clone(), i)) {
+                    write!(f, " %prec {t}")?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn token(text: &str) -> Terminal {
+        match text {
+            "id" => Terminal::Class(TokenClass::Identifier),
+            "$" => Terminal::Token(Token::EOF),
+            "ε" => Terminal::Epsilon,
+            _ => {
+                let token = Lexer::from_source(text).next().unwrap().unwrap();
+                Terminal::Token(token.node)
+            }
+        }
+    }
+
+    fn set(texts: &[&str]) -> HashSet<Terminal> {
+        texts.iter().map(|text| token(text)).collect()
+    }
+
+    fn of<'a>(sets: &'a HashMap<Symbol, HashSet<Terminal>>, nt: &str) -> &'a HashSet<Terminal> {
+        &sets[&Symbol::NonTerminal(nt.to_string())]
+    }
+
+    const EXPRESSIONS: &str = "
+        E => T Ep ;
+        Ep => '+' T Ep | ε ;
+        T => F Tp ;
+        Tp => '*' F Tp | ε ;
+        F => '(' E ')' | %identifier ;
+    ";
+
+    #[test]
+    fn first_and_follow_of_expression_grammar() {
+        let grammar: Grammar = EXPRESSIONS.parse().unwrap();
+        let first = grammar.first_set();
+        for nt in ["E", "T", "F"] {
+            assert_eq!(of(&first, nt), &set(&["(", "id"]), "FIRST({nt})");
+        }
+        assert_eq!(of(&first, "Ep"), &set(&["+", "ε"]));
+        assert_eq!(of(&first, "Tp"), &set(&["*", "ε"]));
+
+        let follow = grammar.follow_set();
+        for nt in ["E", "Ep"] {
+            assert_eq!(of(&follow, nt), &set(&[")", "$"]), "FOLLOW({nt})");
+        }
+        for nt in ["T", "Tp"] {
+            assert_eq!(of(&follow, nt), &set(&["+", ")", "$"]), "FOLLOW({nt})");
+        }
+        assert_eq!(of(&follow, "F"), &set(&["+", "*", ")", "$"]));
+    }
+
+    #[test]
+    fn empty_production_is_nullable() {
+        let mut grammar = Grammar {
+            start: "S".to_string(),
+            ..Grammar::default()
+        };
+        let a = Symbol::NonTerminal("A".to_string());
+        grammar.add_rule("S".to_string(), vec![a, Symbol::Terminal(token("x"))]);
+        grammar.add_rule("A".to_string(), vec![]);
+        let first = grammar.first_set();
+        assert_eq!(of(&first, "A"), &set(&["ε"]));
+        assert_eq!(of(&first, "S"), &set(&["x"]));
+        let follow = grammar.follow_set();
+        assert_eq!(of(&follow, "A"), &set(&["x"]));
+        assert_eq!(of(&follow, "S"), &set(&["$"]));
+    }
+
+    #[test]
+    fn epsilon_only_production() {
+        let grammar: Grammar = "S => A B ; A => ε ; B => 'x' | ε ;".parse().unwrap();
+        let first = grammar.first_set();
+        assert_eq!(of(&first, "A"), &set(&["ε"]));
+        assert_eq!(of(&first, "S"), &set(&["x", "ε"]));
+        let follow = grammar.follow_set();
+        assert_eq!(of(&follow, "A"), &set(&["x", "$"]));
+        assert_eq!(of(&follow, "B"), &set(&["$"]));
+    }
+
+    #[test]
+    fn sequence_through_nullable_prefix() {
+        let grammar: Grammar = EXPRESSIONS.parse().unwrap();
+        let first = grammar.first_set();
+        let symbol = |name: &str| Symbol::NonTerminal(name.to_string());
+        let sequence = [symbol("Ep"), symbol("Tp"), Symbol::Terminal(token(")"))];
+        assert_eq!(first_of_sequence(&first, &sequence), set(&["+", "*", ")"]));
+        let nullable = [symbol("Ep"), symbol("Tp")];
+        assert_eq!(first_of_sequence(&first, &nullable), set(&["+", "*", "ε"]));
+        let blocked = [symbol("Ep"), symbol("F"), symbol("Tp")];
+        assert_eq!(first_of_sequence(&first, &blocked), set(&["+", "(", "id"]));
+        assert_eq!(first_of_sequence(&first, &[]), set(&["ε"]));
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::HashSet;
+
+use super::{sorted, Grammar, NonTerminal, Symbol, Terminal};
+
+impl Grammar {
+    // fixed point over the productions: a non-terminal is marked once some
+    // production of it has every symbol accepted by `accept`
+    fn mark(
+        &self,
+        accept: impl Fn(&Symbol, &HashSet<NonTerminal>) -> bool,
+    ) -> HashSet<NonTerminal> {
+        let mut marked = HashSet::new();
+        let mut changing = true;
+        while changing {
+            changing = false;
+            for (nt, productions) in &self.rules {
+                if marked.contains(nt) {
+                    continue;
+                }
+                if productions
+                    .iter()
+                    .any(|p| p.iter().all(|symbol| accept(symbol, &marked)))
+                {
+                    marked.insert(nt.clone());
+                    changing = true;
+                }
+            }
+        }
+        marked
+    }
+    // non-terminals deriving at least one string of terminals
+    pub fn productive(&self) -> HashSet<NonTerminal> {
+        self.mark(|symbol, productive| match symbol {
+            Symbol::Terminal(_) => true,
+            Symbol::NonTerminal(nt) => productive.contains(nt),
+        })
+    }
+    // non-terminals deriving the empty string
+    pub fn nullable(&self) -> HashSet<NonTerminal> {
+        self.mark(|symbol, nullable| match symbol {
+            Symbol::Terminal(t) => *t == Terminal::Epsilon,
+            Symbol::NonTerminal(nt) => nullable.contains(nt),
+        })
+    }
+    // non-terminals appearing in some sentential form derived from start
+    pub fn reachable(&self) -> HashSet<NonTerminal> {
+        let mut reachable = HashSet::from([self.start.clone()]);
+        let mut work = vec![&self.start];
+        while let Some(nt) = work.pop() {
+            for symbol in self.productions(nt).iter().flatten() {
+                if let Symbol::NonTerminal(next) = symbol {
+                    if reachable.insert(next.clone()) {
+                        work.push(next);
+                    }
+                }
+            }
+        }
+        reachable
+    }
+    // non-terminals used on a right hand side without any rules
+    pub fn undefined(&self) -> Vec<NonTerminal> {
+        let mut undefined: Vec<_> = self
+            .non_terminals
+            .iter()
+            .filter(|nt| !self.rules.contains_key(*nt))
+            .cloned()
+            .collect();
+        undefined.sort();
+        undefined
+    }
+    // terminals given a precedence that no production uses
+    pub fn unused_terminals(&self) -> Vec<Terminal> {
+        let unused = self
+            .precedence
+            .keys()
+            .filter(|t| !self.terminals.contains(*t));
+        sorted(unused.cloned())
+    }
+}
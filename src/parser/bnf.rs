@@ -0,0 +1,395 @@
+use std::collections::HashSet;
+
+use super::{
+    Associativity, Grammar, GrammarError, NonTerminal, Production, Symbol, Terminal, TokenClass,
+};
+use crate::lexer::Lexer;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Item {
+    Ident(String),
+    Quoted(String),
+    Directive(String),
+    Arrow,
+    Bar,
+    Semicolon,
+    LeftParen,
+    RightParen,
+    Star,
+    Plus,
+    Question,
+    Empty,
+}
+
+#[derive(Debug, Clone)]
+struct Located {
+    item: Item,
+    line: usize,
+    column: usize,
+}
+
+fn error<T>(line: usize, column: usize, message: impl Into<String>) -> Result<T, GrammarError> {
+    Err(GrammarError::Syntax {
+        line,
+        column,
+        message: message.into(),
+    })
+}
+
+fn scan(text: &str) -> Result<Vec<Located>, GrammarError> {
+    let mut items = vec![];
+    for (line, source) in text.lines().enumerate() {
+        let line = line + 1;
+        let mut chars = source.char_indices().peekable();
+        while let Some((start, ch)) = chars.next() {
+            let column = source[..start].chars().count() + 1;
+            let mut push = |item| items.push(Located { item, line, column });
+            let rest = &source[start..];
+            match ch {
+                ch if ch.is_whitespace() => {}
+                '#' => break,
+                '|' => push(Item::Bar),
+                ';' => push(Item::Semicolon),
+                '(' => push(Item::LeftParen),
+                ')' => push(Item::RightParen),
+                '*' => push(Item::Star),
+                '+' => push(Item::Plus),
+                '?' => push(Item::Question),
+                'ε' => push(Item::Empty),
+                '=' | '-' | ':' => {
+                    let arrow = ["=>", "->", "::=", ":"]
+                        .into_iter()
+                        .find(|arrow| rest.starts_with(arrow));
+                    let Some(arrow) = arrow else {
+                        return error(line, column, format!("unexpected {ch:?}"));
+                    };
+                    for _ in 1..arrow.len() {
+                        chars.next();
+                    }
+                    push(Item::Arrow);
+                }
+                '\'' | '"' => {
+                    let Some(end) = rest[1..].find(ch) else {
+                        return error(line, column, "unterminated terminal");
+                    };
+                    for _ in 0..rest[1..end + 1].chars().count() + 1 {
+                        chars.next();
+                    }
+                    push(Item::Quoted(rest[1..end + 1].to_string()));
+                }
+                '%' | 'A'..='Z' | 'a'..='z' | '_' => {
+                    let mut end = start + ch.len_utf8();
+                    while let Some(&(i, ch)) = chars.peek() {
+                        if !(ch.is_ascii_alphanumeric() || ch == '_') {
+                            break;
+                        }
+                        end = i + ch.len_utf8();
+                        chars.next();
+                    }
+                    let word = source[start..end].to_string();
+                    if ch == '%' {
+                        push(Item::Directive(word[1..].to_string()));
+                        continue;
+                    }
+                    // primes are allowed at the end of names, as in E'
+                    let mut word = word;
+                    while let Some(&(_, '\'')) = chars.peek() {
+                        word.push('\'');
+                        chars.next();
+                    }
+                    push(Item::Ident(word));
+                }
+                _ => return error(line, column, format!("unexpected {ch:?}")),
+            }
+        }
+    }
+    Ok(items)
+}
+
+struct Parser {
+    items: Vec<Located>,
+    pos: usize,
+    names: HashSet<String>,
+    // each rule with its `%prec` terminal
+    rules: Vec<(NonTerminal, Production, Option<Terminal>)>,
+    start: Option<(NonTerminal, usize, usize)>,
+    // the first rule written, the start symbol without `%start`; rules for
+    // groups and repetitions are pushed before the rule using them
+    first: Option<NonTerminal>,
+    precedence: Vec<(Associativity, Vec<Terminal>)>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Item> {
+        self.items.get(self.pos).map(|located| &located.item)
+    }
+    fn location(&self) -> (usize, usize) {
+        match self.items.get(self.pos).or(self.items.last()) {
+            Some(located) => (located.line, located.column),
+            None => (1, 1),
+        }
+    }
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, GrammarError> {
+        let (line, column) = self.location();
+        error(line, column, message)
+    }
+    // a name followed by an arrow starts the next rule
+    fn at_rule_start(&self) -> bool {
+        matches!(self.peek(), Some(Item::Ident(_)))
+            && matches!(
+                self.items.get(self.pos + 1).map(|l| &l.item),
+                Some(Item::Arrow)
+            )
+    }
+    fn fresh(&mut self, lhs: &str) -> NonTerminal {
+        let name = (1..)
+            .map(|n| format!("{lhs}_{n}"))
+            .find(|name| !self.names.contains(name))
+            .unwrap_or_default();
+        self.names.insert(name.clone());
+        name
+    }
+
+    fn parse(&mut self) -> Result<(), GrammarError> {
+        while let Some(item) = self.peek().cloned() {
+            match item {
+                Item::Directive(directive) => self.directive(&directive)?,
+                Item::Ident(lhs) if self.at_rule_start() => {
+                    self.pos += 2;
+                    self.first.get_or_insert_with(|| lhs.clone());
+                    for (production, prec) in self.alternatives(&lhs)? {
+                        self.rules.push((lhs.clone(), production, prec));
+                    }
+                    if self.peek() == Some(&Item::Semicolon) {
+                        self.pos += 1;
+                    }
+                }
+                Item::Semicolon => self.pos += 1,
+                Item::Ident(name) => return self.error(format!("expected '=>' after {name}")),
+                item => return self.error(format!("expected a rule, found {item:?}")),
+            }
+        }
+        Ok(())
+    }
+    fn directive(&mut self, directive: &str) -> Result<(), GrammarError> {
+        match directive {
+            "start" => {
+                self.pos += 1;
+                let Some(Item::Ident(name)) = self.peek().cloned() else {
+                    return self.error("%start expects a non-terminal");
+                };
+                let (line, column) = self.location();
+                self.start = Some((name, line, column));
+                self.pos += 1;
+                Ok(())
+            }
+            "left" | "right" | "nonassoc" => {
+                let associativity = match directive {
+                    "left" => Associativity::Left,
+                    "right" => Associativity::Right,
+                    _ => Associativity::NonAssoc,
+                };
+                self.pos += 1;
+                let mut terminals = vec![];
+                while let Some(t) = self.declared_terminal()? {
+                    terminals.push(t);
+                }
+                if terminals.is_empty() {
+                    return self.error(format!("%{directive} expects terminals"));
+                }
+                self.precedence.push((associativity, terminals));
+                Ok(())
+            }
+            _ => self.error(format!("unknown directive %{directive}")),
+        }
+    }
+    // a quoted terminal or token class in a declaration
+    fn declared_terminal(&mut self) -> Result<Option<Terminal>, GrammarError> {
+        let t = match self.peek().cloned() {
+            Some(Item::Quoted(text)) => self.terminal(&text)?,
+            Some(Item::Directive(d)) => match TokenClass::from_name(&d) {
+                Some(class) => Terminal::Class(class),
+                None => return Ok(None),
+            },
+            _ => return Ok(None),
+        };
+        self.pos += 1;
+        Ok(Some(t))
+    }
+    fn alternatives(
+        &mut self,
+        lhs: &str,
+    ) -> Result<Vec<(Production, Option<Terminal>)>, GrammarError> {
+        let mut alternatives = vec![self.sequence(lhs)?];
+        while self.peek() == Some(&Item::Bar) {
+            self.pos += 1;
+            alternatives.push(self.sequence(lhs)?);
+        }
+        Ok(alternatives)
+    }
+    fn sequence(&mut self, lhs: &str) -> Result<(Production, Option<Terminal>), GrammarError> {
+        let mut production = vec![];
+        let mut prec = None;
+        loop {
+            match self.peek() {
+                Some(Item::Directive(d)) if d == "prec" => {
+                    self.pos += 1;
+                    match self.declared_terminal()? {
+                        Some(t) => prec = Some(t),
+                        None => return self.error("%prec expects a terminal"),
+                    }
+                    continue;
+                }
+                None | Some(Item::Bar | Item::Semicolon | Item::RightParen) => break,
+                Some(Item::Directive(d)) if TokenClass::from_name(d).is_none() && d != "empty" => {
+                    break
+                }
+                Some(Item::Ident(_)) if self.at_rule_start() => break,
+                _ => {}
+            }
+            let symbol = self.atom(lhs)?;
+            let symbol = self.postfix(lhs, symbol)?;
+            production.extend(symbol);
+        }
+        if production.is_empty() {
+            production.push(Symbol::Terminal(Terminal::Epsilon));
+        }
+        Ok((production, prec))
+    }
+    fn atom(&mut self, lhs: &str) -> Result<Option<Symbol>, GrammarError> {
+        let Some(item) = self.peek().cloned() else {
+            return self.error("unexpected end of grammar");
+        };
+        let symbol = match item {
+            Item::Ident(name) => Some(Symbol::NonTerminal(name)),
+            Item::Quoted(text) => Some(Symbol::Terminal(self.terminal(&text)?)),
+            Item::Empty => None,
+            Item::Directive(d) if d == "empty" => None,
+            Item::Directive(d) => {
+                let class = TokenClass::from_name(&d);
+                let class = class.map_or_else(|| self.error(format!("unknown class %{d}")), Ok)?;
+                Some(Symbol::Terminal(Terminal::Class(class)))
+            }
+            Item::LeftParen => {
+                self.pos += 1;
+                let alternatives = self.alternatives(lhs)?;
+                if self.peek() != Some(&Item::RightParen) {
+                    return self.error("expected ')'");
+                }
+                let group = self.fresh(lhs);
+                for (production, prec) in alternatives {
+                    self.rules.push((group.clone(), production, prec));
+                }
+                Some(Symbol::NonTerminal(group))
+            }
+            item => return self.error(format!("unexpected {item:?}")),
+        };
+        self.pos += 1;
+        Ok(symbol)
+    }
+    fn postfix(&mut self, lhs: &str, symbol: Option<Symbol>) -> Result<Vec<Symbol>, GrammarError> {
+        let mut symbols: Vec<Symbol> = symbol.into_iter().collect();
+        while let Some(op) = self.peek().cloned() {
+            if !matches!(op, Item::Star | Item::Plus | Item::Question) {
+                break;
+            }
+            self.pos += 1;
+            if symbols.is_empty() {
+                continue;
+            }
+            if symbols.len() > 1 {
+                // a further operator applies to the whole previous repetition
+                let group = self.fresh(lhs);
+                self.rules.push((group.clone(), symbols, None));
+                symbols = vec![Symbol::NonTerminal(group)];
+            }
+            // X* => N where N => X N | ε, X+ => X X*, X? => N where N => X | ε
+            let epsilon = vec![Symbol::Terminal(Terminal::Epsilon)];
+            let n = self.fresh(lhs);
+            match op {
+                Item::Question => {
+                    self.rules.push((n.clone(), symbols.clone(), None));
+                    self.rules.push((n.clone(), epsilon, None));
+                    symbols = vec![Symbol::NonTerminal(n)];
+                }
+                _ => {
+                    let mut repeat = symbols.clone();
+                    repeat.push(Symbol::NonTerminal(n.clone()));
+                    self.rules.push((n.clone(), repeat, None));
+                    self.rules.push((n.clone(), epsilon, None));
+                    if op == Item::Star {
+                        symbols.clear();
+                    }
+                    symbols.push(Symbol::NonTerminal(n));
+                }
+            }
+        }
+        Ok(symbols)
+    }
+    fn terminal(&self, text: &str) -> Result<Terminal, GrammarError> {
+        let mut tokens = Lexer::from_source(text);
+        match (tokens.next(), tokens.next()) {
+            (Some(Ok(token)), None) => Ok(Terminal::Token(token.node)),
+            _ => self.error(format!("terminal '{text}' is not a single token")),
+        }
+    }
+}
+
+pub(super) fn parse(text: &str) -> Result<Grammar, GrammarError> {
+    let items = scan(text)?;
+    let names = items
+        .iter()
+        .filter_map(|located| match &located.item {
+            Item::Ident(name) => Some(name.clone()),
+            _ => None,
+        })
+        .collect();
+    let mut parser = Parser {
+        items,
+        pos: 0,
+        names,
+        rules: vec![],
+        start: None,
+        first: None,
+        precedence: vec![],
+    };
+    parser.parse()?;
+    let Some(first) = parser.first.clone() else {
+        return error(1, 1, "grammar has no rules");
+    };
+    let (start, line, column) = parser.start.clone().unwrap_or((first, 1, 1));
+    let mut grammar = Grammar::default();
+    for (lhs, production, prec) in parser.rules {
+        let index = grammar.add_rule(lhs.clone(), production);
+        if let Some(t) = prec {
+            grammar.prec_overrides.insert((lhs, index), t);
+        }
+    }
+    for (associativity, terminals) in parser.precedence {
+        grammar.declare_precedence(associativity, terminals);
+    }
+    if !grammar.rules.contains_key(&start) {
+        return error(line, column, format!("start symbol {start} has no rules"));
+    }
+    grammar.start = start;
+    Ok(grammar)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn start_defaults_to_first_rule_written() {
+        for text in ["S => ( a | b ) c ;", "S => a * ;", "S => a + b ? ;"] {
+            let grammar = parse(text).unwrap();
+            assert_eq!(grammar.start, "S", "{text}");
+        }
+    }
+
+    #[test]
+    fn start_directive_overrides_default() {
+        let grammar = parse("%start T\nS => a ;\nT => S b ;").unwrap();
+        assert_eq!(grammar.start, "T");
+    }
+}
@@ -0,0 +1,231 @@
+use std::{collections::HashMap, fmt};
+
+use super::{Grammar, NonTerminal, Symbol, Terminal};
+use crate::lexer::Token;
+
+// the filled CYK table of a Chomsky normal form grammar; cells hold every
+// non-terminal deriving a span of the input with the number of its
+// distinct derivations
+#[derive(Debug, Clone)]
+pub struct CykTable {
+    start: NonTerminal,
+    tokens: Vec<Token>,
+    // derivations of the empty input
+    empty: u128,
+    // cells[len - 1][i] covers tokens `i..i + len`
+    cells: Vec<Vec<Vec<(NonTerminal, u128)>>>,
+}
+
+impl CykTable {
+    // counts saturate at u128::MAX instead of overflowing; `grammar` must
+    // already be in Chomsky normal form, see `Grammar::cyk`
+    pub fn new(grammar: &Grammar, tokens: impl IntoIterator<Item = Token>) -> Self {
+        let tokens: Vec<Token> = tokens
+            .into_iter()
+            .take_while(|t| *t != Token::EOF)
+            .collect();
+        let n = tokens.len();
+        let mut units: Vec<(&NonTerminal, &Terminal)> = Vec::new();
+        let mut pairs: Vec<(&NonTerminal, &NonTerminal, &NonTerminal)> = Vec::new();
+        let mut empty: u128 = 0;
+        for nt in grammar.non_terminals() {
+            for production in grammar.productions(nt) {
+                match &production[..] {
+                    [Symbol::Terminal(Terminal::Epsilon)] if nt == grammar.start() => {
+                        empty = empty.saturating_add(1)
+                    }
+                    [Symbol::Terminal(t)] => units.push((nt, t)),
+                    [Symbol::NonTerminal(b), Symbol::NonTerminal(c)] => pairs.push((nt, b, c)),
+                    _ => {}
+                }
+            }
+        }
+        let mut counts: Vec<Vec<HashMap<&NonTerminal, u128>>> = Vec::new();
+        for len in 1..=n {
+            let mut row = Vec::new();
+            for i in 0..=n - len {
+                let mut cell: HashMap<&NonTerminal, u128> = HashMap::new();
+                if len == 1 {
+                    for (a, t) in &units {
+                        if t.matches(&tokens[i]) {
+                            *cell.entry(a).or_default() += 1;
+                        }
+                    }
+                }
+                for split in 1..len {
+                    let left = &counts[split - 1][i];
+                    let right = &counts[len - split - 1][i + split];
+                    for (a, b, c) in &pairs {
+                        let (Some(x), Some(y)) = (left.get(b), right.get(c)) else {
+                            continue;
+                        };
+                        let count = cell.entry(a).or_default();
+                        *count = count.saturating_add(x.saturating_mul(*y));
+                    }
+                }
+                row.push(cell);
+            }
+            counts.push(row);
+        }
+        let cells = counts
+            .into_iter()
+            .map(|row| {
+                row.into_iter()
+                    .map(|cell| {
+                        let mut cell: Vec<_> = cell
+                            .into_iter()
+                            .map(|(nt, count)| (nt.clone(), count))
+                            .collect();
+                        cell.sort();
+                        cell
+                    })
+                    .collect()
+            })
+            .collect();
+        CykTable {
+            start: grammar.start().clone(),
+            tokens,
+            empty,
+            cells,
+        }
+    }
+    // non-terminals deriving tokens `start..end` with their derivation counts
+    pub fn cell(&self, start: usize, end: usize) -> &[(NonTerminal, u128)] {
+        match end.checked_sub(start) {
+            Some(len @ 1..) if end <= self.tokens.len() => &self.cells[len - 1][start],
+            _ => &[],
+        }
+    }
+    pub fn derivations(&self) -> u128 {
+        if self.tokens.is_empty() {
+            return self.empty;
+        }
+        let cell = self.cell(0, self.tokens.len());
+        cell.iter()
+            .find(|(nt, _)| *nt == self.start)
+            .map_or(0, |(_, count)| *count)
+    }
+    pub fn accepts(&self) -> bool {
+        self.derivations() > 0
+    }
+    pub fn is_ambiguous(&self) -> bool {
+        self.derivations() > 1
+    }
+}
+
+impl Grammar {
+    // CYK over the Chomsky normal form of the grammar, which lists a
+    // production once per derivation it stands for, so the counts are those
+    // of the grammar itself
+    pub fn cyk(&self, tokens: impl IntoIterator<Item = Token>) -> CykTable {
+        CykTable::new(&self.to_cnf().grammar, tokens)
+    }
+}
+
+// the triangle with the whole input on top and single tokens at the bottom
+impl fmt::Display for CykTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = |cell: &[(NonTerminal, u128)]| {
+            let names: Vec<String> = cell
+                .iter()
+                .map(|(nt, count)| match count {
+                    1 => nt.clone(),
+                    _ => format!("{nt}({count})"),
+                })
+                .collect();
+            format!("{{{}}}", names.join(", "))
+        };
+        let tokens: Vec<String> = self.tokens.iter().map(|t| t.to_string()).collect();
+        let width = self
+            .cells
+            .iter()
+            .flatten()
+            .map(|cell| text(cell).chars().count())
+            .chain(tokens.iter().map(|t| t.chars().count()))
+            .max()
+            .unwrap_or(0);
+        for (len, row) in self.cells.iter().enumerate().rev() {
+            write!(f, "{:indent$}", "", indent = len * (width + 1) / 2)?;
+            for cell in row {
+                write!(f, "{:width$} ", text(cell))?;
+            }
+            writeln!(f)?;
+        }
+        for token in tokens {
+            write!(f, "{token:width$} ")?;
+        }
+        writeln!(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn tokens(text: &str) -> Vec<Token> {
+        Lexer::from_source(text).map(|t| t.unwrap().node).collect()
+    }
+
+    // CYK counts on the normal form must agree with the trees Earley finds
+    // on the grammar as written
+    #[test]
+    fn derivations_match_earley() {
+        let cases = [
+            ("S => A | B ; A => 'a' ; B => 'a' ;", "a", 2),
+            ("E => E '+' E | %identifier ;", "x + y + z", 2),
+            ("E => E '+' E | %identifier ;", "w + x + y + z", 5),
+            ("S => A B ; A => 'a' | ε ; B => 'a' | ε ;", "a", 2),
+            ("S => A B ; A => 'a' | ε ; B => 'a' | ε ;", "", 1),
+            ("S => N N 'a' ; N => ε | M ; M => ε ;", "a", 4),
+            ("S => S | 'a' ;", "a", 1),
+            ("S => 'a' S 'b' | 'a' 'b' ;", "a a b b", 1),
+        ];
+        for (text, input, expected) in cases {
+            let grammar: Grammar = text.parse().unwrap();
+            let trees = grammar.earley(tokens(input)).unwrap().trees().len();
+            assert_eq!(trees as u128, expected, "Earley, {text} on {input:?}");
+            let cyk = grammar.cyk(tokens(input));
+            assert_eq!(cyk.derivations(), expected, "CYK, {text} on {input:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_what_the_grammar_does_not_derive() {
+        let grammar: Grammar = "S => 'a' S 'b' | 'a' 'b' ;".parse().unwrap();
+        assert!(!grammar.cyk(tokens("a b b")).accepts());
+        assert!(!grammar.cyk(tokens("")).accepts());
+        let table = grammar.cyk(tokens("a a b b"));
+        assert!(table.accepts() && !table.is_ambiguous());
+    }
+
+    #[test]
+    fn cells_hold_the_non_terminals_of_each_span() {
+        // already in Chomsky normal form, so the table is built on it as is
+        let grammar: Grammar = "
+            S => A B | B C ;
+            A => B A | 'a' ;
+            B => C C | 'b' ;
+            C => A B | 'a' ;
+        "
+        .parse()
+        .unwrap();
+        let table = CykTable::new(&grammar, tokens("b a a b a"));
+        let names = |start, end| {
+            let cell = table.cell(start, end);
+            cell.iter().map(|(nt, _)| nt.as_str()).collect::<Vec<_>>()
+        };
+        assert_eq!(names(0, 1), ["B"]);
+        assert_eq!(names(1, 2), ["A", "C"]);
+        assert_eq!(names(0, 2), ["A", "S"]);
+        assert_eq!(names(1, 4), ["B"]);
+        assert_eq!(names(0, 5), ["A", "C", "S"]);
+        assert!(names(2, 2).is_empty() && names(3, 9).is_empty());
+        assert!(table.accepts());
+        // Catalan many trees overflow any integer, the count saturates
+        let grammar: Grammar = "E => E E | 'a' ;".parse().unwrap();
+        let table = grammar.cyk(tokens(&["a"; 80].join(" ")));
+        assert_eq!(table.derivations(), u128::MAX);
+        assert_eq!(grammar.cyk(tokens("a a a a")).derivations(), 5);
+    }
+}
@@ -0,0 +1,420 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
+use super::{sorted, Grammar, NonTerminal, ParseTree, Symbol, SyntaxError, Terminal};
+use crate::lexer::Token;
+
+// one way of deriving a forest node: the production used and the nodes of
+// its symbols
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packed {
+    // index into the productions of the node's non-terminal
+    pub production: usize,
+    pub children: Vec<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForestNode {
+    pub symbol: Symbol,
+    // the node covers tokens `start..end`
+    pub start: usize,
+    pub end: usize,
+    // the token a terminal node matched
+    pub token: Option<Token>,
+    pub alternatives: Vec<Packed>,
+}
+
+// shared packed parse forest, nodes are shared between every tree using
+// the same symbol over the same tokens
+#[derive(Debug, Clone)]
+pub struct Forest {
+    nodes: Vec<ForestNode>,
+}
+
+impl Forest {
+    pub fn root(&self) -> &ForestNode {
+        &self.nodes[0]
+    }
+    pub fn node(&self, id: usize) -> &ForestNode {
+        &self.nodes[id]
+    }
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+    pub fn is_ambiguous(&self) -> bool {
+        self.nodes.iter().any(|node| node.alternatives.len() > 1)
+    }
+    // every parse tree in the forest; derivations that loop back to a node
+    // they are already inside, as in `A => A`, are left out
+    pub fn trees(&self) -> Vec<ParseTree> {
+        self.trees_of(0, &mut Vec::new())
+    }
+    fn trees_of(&self, id: usize, inside: &mut Vec<usize>) -> Vec<ParseTree> {
+        let node = &self.nodes[id];
+        let non_terminal = match (&node.symbol, &node.token) {
+            (Symbol::NonTerminal(nt), _) => nt,
+            (_, Some(token)) => return vec![ParseTree::Leaf(token.clone())],
+            (_, None) => return vec![],
+        };
+        if inside.contains(&id) {
+            return vec![];
+        }
+        inside.push(id);
+        let mut trees = Vec::new();
+        for packed in &node.alternatives {
+            let mut combinations: Vec<Vec<ParseTree>> = vec![vec![]];
+            for &child in &packed.children {
+                let subtrees = self.trees_of(child, inside);
+                combinations = combinations
+                    .into_iter()
+                    .flat_map(|prefix| {
+                        subtrees.iter().map(move |tree| {
+                            let mut children = prefix.clone();
+                            children.push(tree.clone());
+                            children
+                        })
+                    })
+                    .collect();
+            }
+            trees.extend(combinations.into_iter().map(|children| ParseTree::Node {
+                non_terminal: non_terminal.clone(),
+                children,
+            }));
+        }
+        inside.pop();
+        trees
+    }
+}
+
+impl fmt::Display for Forest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = |id: usize| {
+            let node = &self.nodes[id];
+            format!("{}[{}, {}]", node.symbol, node.start, node.end)
+        };
+        for (id, node) in self.nodes.iter().enumerate() {
+            if node.alternatives.is_empty() {
+                continue;
+            }
+            write!(f, "{} =>", name(id))?;
+            for (i, packed) in node.alternatives.iter().enumerate() {
+                if i > 0 {
+                    write!(f, " |")?;
+                }
+                if packed.children.is_empty() {
+                    write!(f, " %empty")?;
+                }
+                for &child in &packed.children {
+                    write!(f, " {}", name(child))?;
+                }
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+// (production, dot, origin), productions numbered across the whole grammar
+type Item = (usize, usize, usize);
+
+struct Chart<'a> {
+    tokens: Vec<Token>,
+    // every production with epsilon left out of the right hand side
+    productions: Vec<(&'a NonTerminal, usize, Vec<Symbol>)>,
+    by_lhs: HashMap<&'a str, Vec<usize>>,
+    sets: Vec<Vec<Item>>,
+    seen: Vec<HashSet<Item>>,
+}
+
+impl<'a> Chart<'a> {
+    fn new(grammar: &'a Grammar, tokens: Vec<Token>) -> Self {
+        let mut productions = Vec::new();
+        let mut by_lhs: HashMap<&str, Vec<usize>> = HashMap::new();
+        for nt in grammar.non_terminals() {
+            for (i, production) in grammar.productions(nt).iter().enumerate() {
+                let epsilon = Symbol::Terminal(Terminal::Epsilon);
+                let rhs = production.iter().filter(|s| **s != epsilon).cloned();
+                by_lhs.entry(nt).or_default().push(productions.len());
+                productions.push((nt, i, rhs.collect()));
+            }
+        }
+        let len = tokens.len() + 1;
+        Chart {
+            tokens,
+            productions,
+            by_lhs,
+            sets: vec![Vec::new(); len],
+            seen: vec![HashSet::new(); len],
+        }
+    }
+    fn add(&mut self, k: usize, item: Item) {
+        if self.seen[k].insert(item) {
+            self.sets[k].push(item);
+        }
+    }
+    fn next(&self, (p, dot, _): Item) -> Option<&Symbol> {
+        self.productions[p].2.get(dot)
+    }
+    // the nullable fix of Aycock and Horspool: predicting a nullable
+    // non-terminal also steps over it, so completions of empty derivations
+    // are never missed
+    fn recognize(&mut self, grammar: &Grammar) {
+        let nullable = grammar.nullable();
+        let start = self.by_lhs.get(grammar.start().as_str());
+        for p in start.cloned().unwrap_or_default() {
+            self.add(0, (p, 0, 0));
+        }
+        for k in 0..self.sets.len() {
+            let mut i = 0;
+            while i < self.sets[k].len() {
+                let item @ (p, dot, origin) = self.sets[k][i];
+                match self.next(item).cloned() {
+                    None => {
+                        let lhs = Symbol::NonTerminal(self.productions[p].0.clone());
+                        let mut w = 0;
+                        while w < self.sets[origin].len() {
+                            let waiting @ (q, d, o) = self.sets[origin][w];
+                            if self.next(waiting) == Some(&lhs) {
+                                self.add(k, (q, d + 1, o));
+                            }
+                            w += 1;
+                        }
+                    }
+                    Some(Symbol::NonTerminal(b)) => {
+                        for q in self.by_lhs.get(b.as_str()).cloned().unwrap_or_default() {
+                            self.add(k, (q, 0, k));
+                        }
+                        if nullable.contains(&b) {
+                            self.add(k, (p, dot + 1, origin));
+                        }
+                    }
+                    Some(Symbol::Terminal(t)) => {
+                        if k < self.tokens.len() && t.matches(&self.tokens[k]) {
+                            self.add(k + 1, (p, dot + 1, origin));
+                        }
+                    }
+                }
+                i += 1;
+            }
+        }
+    }
+    fn accepted(&self, start: &str) -> bool {
+        let n = self.tokens.len();
+        self.sets[n].iter().any(|&(p, dot, origin)| {
+            let (lhs, _, rhs) = &self.productions[p];
+            origin == 0 && dot == rhs.len() && *lhs == start
+        })
+    }
+    fn error(&self) -> SyntaxError {
+        let position = (0..self.sets.len())
+            .rev()
+            .find(|&k| !self.sets[k].is_empty())
+            .unwrap_or(0);
+        let mut expected: HashSet<Terminal> = HashSet::new();
+        for &item in &self.sets[position] {
+            if let Some(Symbol::Terminal(t)) = self.next(item) {
+                expected.insert(t.clone());
+            }
+        }
+        SyntaxError {
+            position,
+            found: self.tokens.get(position).cloned().unwrap_or(Token::EOF),
+            expected: sorted(expected),
+        }
+    }
+    // ways the first `dot` symbols of production `p` can cover `i..j`, each
+    // as the spans of those symbols
+    fn splits(&self, p: usize, dot: usize, i: usize, j: usize) -> Vec<Vec<(usize, usize)>> {
+        if dot == 0 {
+            return match i == j {
+                true => vec![vec![]],
+                false => vec![],
+            };
+        }
+        let mut starts = Vec::new();
+        match &self.productions[p].2[dot - 1] {
+            Symbol::Terminal(t) => {
+                if j > i && t.matches(&self.tokens[j - 1]) {
+                    starts.push(j - 1);
+                }
+            }
+            Symbol::NonTerminal(x) => {
+                for &(q, d, origin) in &self.sets[j] {
+                    let (lhs, _, rhs) = &self.productions[q];
+                    if *lhs == x && d == rhs.len() && origin >= i && !starts.contains(&origin) {
+                        starts.push(origin);
+                    }
+                }
+            }
+        }
+        let mut splits = Vec::new();
+        for k in starts {
+            if !self.seen[k].contains(&(p, dot - 1, i)) {
+                continue;
+            }
+            for mut prefix in self.splits(p, dot - 1, i, k) {
+                prefix.push((k, j));
+                splits.push(prefix);
+            }
+        }
+        splits
+    }
+    fn forest(&self, start: &NonTerminal) -> Forest {
+        let mut nodes: Vec<ForestNode> = Vec::new();
+        let mut ids: HashMap<(Symbol, usize, usize), usize> = HashMap::new();
+        let mut node = |nodes: &mut Vec<ForestNode>, symbol: &Symbol, start, end| {
+            let key = (symbol.clone(), start, end);
+            if let Some(&id) = ids.get(&key) {
+                return (id, false);
+            }
+            let token = match symbol {
+                Symbol::Terminal(_) => Some(self.tokens[start].clone()),
+                Symbol::NonTerminal(_) => None,
+            };
+            nodes.push(ForestNode {
+                symbol: symbol.clone(),
+                start,
+                end,
+                token,
+                alternatives: Vec::new(),
+            });
+            ids.insert(key, nodes.len() - 1);
+            (nodes.len() - 1, true)
+        };
+        let root = Symbol::NonTerminal(start.clone());
+        node(&mut nodes, &root, 0, self.tokens.len());
+        let mut work = vec![0];
+        while let Some(id) = work.pop() {
+            let (i, j) = (nodes[id].start, nodes[id].end);
+            let Symbol::NonTerminal(nt) = nodes[id].symbol.clone() else {
+                continue;
+            };
+            let mut alternatives = Vec::new();
+            for &(p, dot, origin) in &self.sets[j] {
+                let (lhs, index, rhs) = &self.productions[p];
+                if **lhs != nt || origin != i || dot != rhs.len() {
+                    continue;
+                }
+                for spans in self.splits(p, dot, i, j) {
+                    let mut children = Vec::new();
+                    for (symbol, (start, end)) in rhs.iter().zip(spans) {
+                        let (child, new) = node(&mut nodes, symbol, start, end);
+                        if new {
+                            work.push(child);
+                        }
+                        children.push(child);
+                    }
+                    alternatives.push(Packed {
+                        production: *index,
+                        children,
+                    });
+                }
+            }
+            nodes[id].alternatives = alternatives;
+        }
+        Forest { nodes }
+    }
+}
+
+impl Grammar {
+    // Earley parse of any context free grammar, ambiguous and left recursive
+    // ones included; the input ends at the first `Token::EOF`
+    pub fn earley(&self, tokens: impl IntoIterator<Item = Token>) -> Result<Forest, SyntaxError> {
+        let tokens = tokens.into_iter().take_while(|t| *t != Token::EOF);
+        let mut chart = Chart::new(self, tokens.collect());
+        chart.recognize(self);
+        match chart.accepted(&self.start) {
+            true => Ok(chart.forest(&self.start)),
+            false => Err(chart.error()),
+        }
+    }
+    pub fn recognizes(&self, tokens: impl IntoIterator<Item = Token>) -> bool {
+        let tokens = tokens.into_iter().take_while(|t| *t != Token::EOF);
+        let mut chart = Chart::new(self, tokens.collect());
+        chart.recognize(self);
+        chart.accepted(&self.start)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        lexer::{Lexer, Token},
+        parser::{Grammar, ParseTree, Symbol},
+    };
+
+    fn tokens(source: &str) -> Vec<Token> {
+        let tokens = Lexer::from_source(source).map(|token| token.unwrap().node);
+        tokens.collect()
+    }
+
+    // the tree with every node of more than one child in parentheses
+    fn grouped(tree: &ParseTree) -> String {
+        match tree {
+            ParseTree::Leaf(token) => token.to_string(),
+            ParseTree::Node { children, .. } => {
+                let parts: Vec<_> = children.iter().map(grouped).collect();
+                match parts.len() {
+                    1 => parts.join(""),
+                    _ => format!("({})", parts.join(" ")),
+                }
+            }
+        }
+    }
+
+    fn e() -> Symbol {
+        Symbol::NonTerminal("E".to_string())
+    }
+
+    #[test]
+    fn ambiguous_input_gives_every_tree() {
+        let grammar: Grammar = "E => E '-' E | %identifier ;".parse().unwrap();
+        let forest = grammar.earley(tokens("a - b - c")).unwrap();
+        assert!(forest.is_ambiguous());
+        let mut trees: Vec<_> = forest.trees().iter().map(grouped).collect();
+        trees.sort();
+        assert_eq!(trees, ["((a - b) - c)", "(a - (b - c))"]);
+        // the forest shares `E` over each span between the two trees
+        let root = forest.root();
+        assert_eq!((root.symbol.clone(), root.start, root.end), (e(), 0, 5));
+        assert_eq!(root.alternatives.len(), 2);
+        let spans = (0..forest.len()).map(|id| forest.node(id));
+        let shared = spans.filter(|node| node.symbol == e()).count();
+        assert_eq!(shared, 6);
+        let forest = grammar.earley(tokens("a")).unwrap();
+        assert!(!forest.is_ambiguous());
+        assert_eq!(forest.to_string(), "E[0, 1] => %identifier[0, 1]\n");
+    }
+
+    #[test]
+    fn left_recursion_and_empty_rules() {
+        let grammar: Grammar = "L => L ',' I | I ; I => %identifier | ε ;".parse().unwrap();
+        for source in ["a", "a , b , c", ", ,", ""] {
+            let trees = grammar.earley(tokens(source)).unwrap().trees();
+            assert_eq!(trees.len(), 1, "{source:?}");
+        }
+        // cycles have infinitely many derivations; only those that don't
+        // loop back are trees
+        let grammar: Grammar = "S => S | 'a' ;".parse().unwrap();
+        assert_eq!(grammar.earley(tokens("a")).unwrap().trees().len(), 1);
+        assert!(grammar.recognizes(tokens("a")));
+        assert!(!grammar.recognizes(tokens("a a")));
+    }
+
+    #[test]
+    fn errors_point_past_the_longest_prefix() {
+        let grammar: Grammar = "S => '(' S ')' S | ε ;".parse().unwrap();
+        assert!(grammar.recognizes(tokens("( ( ) ) ( )")));
+        let error = grammar.earley(tokens("( ) ) (")).unwrap_err();
+        assert_eq!((error.position, error.found), (2, Token::RightParen));
+        let error = grammar.earley(tokens("( (")).unwrap_err();
+        assert_eq!((error.position, error.found), (2, Token::EOF));
+        let expected: Vec<_> = error.expected.iter().map(|t| t.to_string()).collect();
+        assert_eq!(expected, ["'('", "')'"]);
+    }
+}
@@ -0,0 +1,375 @@
+use std::{collections::HashMap, error, fmt};
+
+use super::{
+    first_of_sequence, sorted, Grammar, NonTerminal, ParseTree, Production, Symbol, SyntaxError,
+    Terminal, TokenClass,
+};
+use crate::lexer::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LlConflictKind {
+    // two productions start with the same terminal
+    FirstFirst,
+    // a production that can vanish competes with one starting with a
+    // terminal that may also follow the non-terminal
+    FirstFollow,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LlConflict {
+    pub kind: LlConflictKind,
+    pub non_terminal: NonTerminal,
+    pub lookahead: Terminal,
+    pub productions: Vec<Production>,
+}
+
+impl fmt::Display for LlConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            LlConflictKind::FirstFirst => "FIRST/FIRST",
+            LlConflictKind::FirstFollow => "FIRST/FOLLOW",
+        };
+        write!(
+            f,
+            "{kind} conflict in {} on {}:",
+            self.non_terminal, self.lookahead
+        )?;
+        for production in &self.productions {
+            write!(f, "\n    {} =>", self.non_terminal)?;
+            for symbol in production {
+                write!(f, " {symbol}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for LlConflict {}
+
+// predictive parsing table, maps a non-terminal and a lookahead to the
+// production to expand
+#[derive(Debug, Clone)]
+pub struct LlTable {
+    start: NonTerminal,
+    order: Vec<NonTerminal>,
+    table: HashMap<NonTerminal, HashMap<Terminal, Production>>,
+}
+
+impl LlTable {
+    pub fn new(grammar: &Grammar) -> Result<Self, Vec<LlConflict>> {
+        let first = grammar.first_set();
+        let follow = grammar.follow_set();
+        let mut table: HashMap<NonTerminal, HashMap<Terminal, Production>> = HashMap::new();
+        let mut conflicts: Vec<LlConflict> = Vec::new();
+        for nt in grammar.non_terminals() {
+            // lookahead -> (production, selected through FOLLOW)
+            let mut entries: Vec<(Terminal, Vec<(&Production, bool)>)> = Vec::new();
+            let mut add = |t: Terminal, production, via_follow| match entries
+                .iter_mut()
+                .find(|(la, _)| *la == t)
+            {
+                Some((_, candidates)) => candidates.push((production, via_follow)),
+                None => entries.push((t, vec![(production, via_follow)])),
+            };
+            for production in grammar.productions(nt) {
+                let mut first = first_of_sequence(&first, production);
+                let nullable = first.remove(&Terminal::Epsilon);
+                for t in sorted(first) {
+                    add(t, production, false);
+                }
+                if nullable {
+                    let s = Symbol::NonTerminal(nt.clone());
+                    let follow = follow.get(&s).cloned().unwrap_or_default();
+                    for t in sorted(follow) {
+                        add(t, production, true);
+                    }
+                }
+            }
+            // an exact token is also an instance of its class, so whatever
+            // the class predicts competes for the token's cell
+            let classes: Vec<_> = entries
+                .iter()
+                .filter(|(t, _)| matches!(t, Terminal::Class(_)))
+                .cloned()
+                .collect();
+            for (lookahead, candidates) in &mut entries {
+                let Terminal::Token(token) = lookahead else {
+                    continue;
+                };
+                let Some(class) = TokenClass::of(token).map(Terminal::Class) else {
+                    continue;
+                };
+                let Some((_, more)) = classes.iter().find(|(t, _)| *t == class) else {
+                    continue;
+                };
+                for (production, via_follow) in more {
+                    if candidates.iter().all(|(p, _)| p != production) {
+                        candidates.push((production, *via_follow));
+                    }
+                }
+            }
+            let row = table.entry(nt.clone()).or_default();
+            for (lookahead, candidates) in entries {
+                if let [(production, _)] = candidates[..] {
+                    row.insert(lookahead, production.clone());
+                    continue;
+                }
+                let kind = match candidates.iter().any(|(_, via_follow)| *via_follow) {
+                    true => LlConflictKind::FirstFollow,
+                    false => LlConflictKind::FirstFirst,
+                };
+                let mut productions: Vec<Production> = Vec::new();
+                for (production, _) in candidates {
+                    if !productions.contains(production) {
+                        productions.push(production.clone());
+                    }
+                }
+                conflicts.push(LlConflict {
+                    kind,
+                    non_terminal: nt.clone(),
+                    lookahead,
+                    productions,
+                });
+            }
+        }
+        if !conflicts.is_empty() {
+            return Err(conflicts);
+        }
+        Ok(LlTable {
+            start: grammar.start().clone(),
+            order: grammar.non_terminals().into_iter().cloned().collect(),
+            table,
+        })
+    }
+    pub fn start(&self) -> &NonTerminal {
+        &self.start
+    }
+    pub fn get(&self, non_terminal: &str, lookahead: &Terminal) -> Option<&Production> {
+        self.table.get(non_terminal)?.get(lookahead)
+    }
+    // a token and its class never predict different productions, the table
+    // reports that as a conflict
+    fn predict(&self, non_terminal: &str, token: &Token) -> Option<&Production> {
+        let row = self.table.get(non_terminal)?;
+        row.get(&Terminal::Token(token.clone())).or_else(|| {
+            let class = TokenClass::of(token)?;
+            row.get(&Terminal::Class(class))
+        })
+    }
+    fn expected(&self, non_terminal: &str) -> Vec<Terminal> {
+        let row = self.table.get(non_terminal);
+        sorted(row.into_iter().flat_map(|row| row.keys().cloned()))
+    }
+    // predictive parse with an explicit stack, the input ends at the first
+    // `Token::EOF` or when the iterator runs out
+    pub fn parse(&self, tokens: impl IntoIterator<Item = Token>) -> Result<ParseTree, SyntaxError> {
+        let mut tokens = tokens.into_iter().chain(std::iter::repeat(Token::EOF));
+        let mut lookahead = tokens.next().unwrap_or(Token::EOF);
+        let mut position = 0;
+        let error = |position, found: &Token, expected| SyntaxError {
+            position,
+            found: found.clone(),
+            expected,
+        };
+        let Some(production) = self.predict(&self.start, &lookahead) else {
+            return Err(error(position, &lookahead, self.expected(&self.start)));
+        };
+        // each frame is a non-terminal being expanded, the symbols of its
+        // production still to match and the subtrees built so far
+        let mut stack = vec![(self.start.clone(), production.iter(), Vec::new())];
+        loop {
+            let (_, remaining, children) = stack.last_mut().unwrap();
+            match remaining.next() {
+                None => {
+                    let (non_terminal, _, children) = stack.pop().unwrap();
+                    let node = ParseTree::Node {
+                        non_terminal,
+                        children,
+                    };
+                    match stack.last_mut() {
+                        Some((_, _, children)) => children.push(node),
+                        None if lookahead == Token::EOF => return Ok(node),
+                        None => {
+                            let expected = vec![Terminal::Token(Token::EOF)];
+                            return Err(error(position, &lookahead, expected));
+                        }
+                    }
+                }
+                Some(Symbol::Terminal(Terminal::Epsilon)) => {}
+                Some(Symbol::Terminal(t)) => {
+                    if !t.matches(&lookahead) {
+                        return Err(error(position, &lookahead, vec![t.clone()]));
+                    }
+                    let next = tokens.next().unwrap_or(Token::EOF);
+                    children.push(ParseTree::Leaf(std::mem::replace(&mut lookahead, next)));
+                    position += 1;
+                }
+                Some(Symbol::NonTerminal(nt)) => {
+                    let Some(production) = self.predict(nt, &lookahead) else {
+                        return Err(error(position, &lookahead, self.expected(nt)));
+                    };
+                    stack.push((nt.clone(), production.iter(), Vec::new()));
+                }
+            }
+        }
+    }
+}
+
+impl Grammar {
+    pub fn ll1_table(&self) -> Result<LlTable, Vec<LlConflict>> {
+        LlTable::new(self)
+    }
+}
+
+impl fmt::Display for LlTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for nt in &self.order {
+            let Some(row) = self.table.get(nt) else {
+                continue;
+            };
+            for lookahead in sorted(row.keys().cloned()) {
+                write!(f, "[{nt}, {lookahead}] => {nt} =>")?;
+                for symbol in &row[&lookahead] {
+                    write!(f, " {symbol}")?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LlConflict, LlConflictKind, LlTable};
+    use crate::{
+        lexer::{Lexer, Token},
+        parser::{Grammar, ParseTree, Symbol, Terminal, TokenClass},
+    };
+
+    fn table(source: &str) -> Result<LlTable, Vec<LlConflict>> {
+        LlTable::new(&source.parse::<Grammar>().unwrap())
+    }
+
+    #[test]
+    fn token_and_class_in_one_cell_conflict() {
+        let conflicts = table("A => %identifier | 'x' ;").unwrap_err();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].kind, LlConflictKind::FirstFirst);
+        let x = Lexer::from_source("x").next().unwrap().unwrap().node;
+        assert_eq!(conflicts[0].lookahead, Terminal::Token(x));
+        assert_eq!(conflicts[0].productions.len(), 2);
+        assert!(table("A => B 'y' ; B => 'x' | ε ; C => %identifier ;").is_ok());
+        let conflicts = table("A => B 'x' ; B => %identifier | ε ;").unwrap_err();
+        assert_eq!(conflicts[0].kind, LlConflictKind::FirstFollow);
+    }
+
+    const EXPRESSIONS: &str = "
+        E => T Ep ;
+        Ep => '+' T Ep | ε ;
+        T => F Tp ;
+        Tp => '*' F Tp | ε ;
+        F => '(' E ')' | %identifier ;
+    ";
+
+    fn terminal(text: &str) -> Terminal {
+        match text {
+            "id" => Terminal::Class(TokenClass::Identifier),
+            "$" => Terminal::Token(Token::EOF),
+            _ => Terminal::Token(Lexer::from_source(text).next().unwrap().unwrap().node),
+        }
+    }
+
+    #[test]
+    fn expression_table_predicts_through_first_and_follow() {
+        let table = table(EXPRESSIONS).unwrap();
+        let predicted = |nt: &str, lookahead: &str| {
+            let production = table.get(nt, &terminal(lookahead))?;
+            Some(production.iter().map(Symbol::to_string).collect::<Vec<_>>())
+        };
+        assert_eq!(predicted("E", "("), Some(vec!["T".into(), "Ep".into()]));
+        assert_eq!(predicted("E", "id"), Some(vec!["T".into(), "Ep".into()]));
+        assert_eq!(predicted("E", "+"), None);
+        assert_eq!(predicted("Ep", "+").map(|p| p.len()), Some(3));
+        // the empty production is chosen on FOLLOW(Ep) = { ')', $ }
+        for lookahead in [")", "$"] {
+            assert_eq!(predicted("Ep", lookahead), Some(vec!["%empty".into()]));
+            assert_eq!(predicted("Tp", lookahead), Some(vec!["%empty".into()]));
+        }
+        assert_eq!(predicted("Tp", "+"), Some(vec!["%empty".into()]));
+        assert_eq!(predicted("Tp", "("), None);
+        let cells = table.to_string().lines().count();
+        assert_eq!(cells, 13);
+    }
+
+    #[test]
+    fn conflicts_name_both_productions() {
+        let conflicts = table("S => 'a' 'b' | 'a' 'c' ;").unwrap_err();
+        let expected = "FIRST/FIRST conflict in S on 'a':\n    S => 'a' 'b'\n    S => 'a' 'c'";
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].to_string(), expected);
+        // the dangling else: `else` both starts the optional part and follows it
+        let conflicts = table("S => 'if' S E | 'x' ; E => 'else' S | ε ;").unwrap_err();
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].kind, LlConflictKind::FirstFollow);
+        assert_eq!(conflicts[0].non_terminal, "E");
+        assert_eq!(conflicts[0].lookahead, terminal("else"));
+        // left recursion always conflicts
+        let conflicts = table("E => E '+' 'x' | 'x' ;").unwrap_err();
+        assert_eq!(conflicts[0].kind, LlConflictKind::FirstFirst);
+    }
+
+    fn tokens(source: &str) -> Vec<Token> {
+        let tokens = Lexer::from_source(source).map(|token| token.unwrap().node);
+        tokens.collect()
+    }
+
+    fn leaves(tree: &ParseTree) -> Vec<String> {
+        match tree {
+            ParseTree::Leaf(token) => vec![token.to_string()],
+            ParseTree::Node { children, .. } => children.iter().flat_map(leaves).collect(),
+        }
+    }
+
+    #[test]
+    fn parses_into_trees_with_empty_nodes() {
+        let table = table(EXPRESSIONS).unwrap();
+        let tree = table.parse(tokens("a * (b + c)")).unwrap();
+        assert_eq!(leaves(&tree), ["a", "*", "(", "b", "+", "c", ")"]);
+        let ParseTree::Node {
+            non_terminal,
+            children,
+        } = &tree
+        else {
+            panic!("the root is a leaf");
+        };
+        assert_eq!(non_terminal, "E");
+        // E => T Ep where Ep derived nothing
+        let empty = ParseTree::Node {
+            non_terminal: "Ep".to_string(),
+            children: Vec::new(),
+        };
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[1], empty);
+        // an explicit end of input stops the parse
+        let mut input = tokens("x");
+        input.extend([Token::EOF, Token::Plus]);
+        assert!(table.parse(input).is_ok());
+    }
+
+    #[test]
+    fn errors_give_position_and_expected_terminals() {
+        let table = table(EXPRESSIONS).unwrap();
+        let error = table.parse(tokens("a + * b")).unwrap_err();
+        assert_eq!(error.position, 2);
+        assert_eq!(error.found, Token::Star);
+        assert_eq!(error.expected, [terminal("id"), terminal("(")]);
+        let error = table.parse(tokens("(a")).unwrap_err();
+        assert_eq!((error.position, &error.found), (2, &Token::EOF));
+        assert!(error.expected.contains(&terminal(")")));
+        let error = table.parse(tokens("a b")).unwrap_err();
+        assert_eq!(error.position, 1);
+        let message = table.parse(Vec::new()).unwrap_err().to_string();
+        assert!(message.starts_with("syntax error at token 0: found"));
+    }
+}
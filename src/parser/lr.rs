@@ -0,0 +1,712 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque},
+    error, fmt,
+};
+
+use super::{
+    first_of_sequence, sorted, Associativity, Grammar, NonTerminal, ParseTree, Precedence,
+    Production, Symbol, SyntaxError, Terminal, TokenClass,
+};
+use crate::lexer::Token;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LrKind {
+    Lr0,
+    Slr1,
+    Lalr1,
+    Lr1,
+}
+
+impl LrKind {
+    fn lookaheads(self) -> bool {
+        matches!(self, LrKind::Lalr1 | LrKind::Lr1)
+    }
+}
+
+impl fmt::Display for LrKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LrKind::Lr0 => "LR(0)",
+            LrKind::Slr1 => "SLR(1)",
+            LrKind::Lalr1 => "LALR(1)",
+            LrKind::Lr1 => "LR(1)",
+        };
+        write!(f, "{name}")
+    }
+}
+
+// items are (production, dot) pairs, each with the indices of its lookahead
+// terminals; LR(0) and SLR(1) items carry no lookaheads
+type ItemSet = BTreeMap<(usize, usize), BTreeSet<usize>>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LrItem {
+    pub non_terminal: NonTerminal,
+    pub production: Production,
+    pub dot: usize,
+    pub lookaheads: Vec<Terminal>,
+}
+
+impl fmt::Display for LrItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} =>", self.non_terminal)?;
+        for (i, symbol) in self.production.iter().enumerate() {
+            match i == self.dot {
+                true => write!(f, " . {symbol}")?,
+                false => write!(f, " {symbol}")?,
+            }
+        }
+        if self.dot == self.production.len() {
+            write!(f, " .")?;
+        }
+        if let Some((first, rest)) = self.lookaheads.split_first() {
+            write!(f, " [{first}")?;
+            for t in rest {
+                write!(f, " {t}")?;
+            }
+            write!(f, "]")?;
+        }
+        Ok(())
+    }
+}
+
+// the item sets and goto graph of an augmented grammar
+#[derive(Debug, Clone)]
+pub struct LrAutomaton {
+    kind: LrKind,
+    terminals: Vec<Terminal>,
+    // production 0 is the augmented start `S' => S`, epsilon is left out of
+    // right hand sides so a vanishing production is empty
+    productions: Vec<(NonTerminal, Production)>,
+    production_precedence: Vec<Option<Precedence>>,
+    precedence: HashMap<Terminal, Precedence>,
+    by_lhs: HashMap<NonTerminal, Vec<usize>>,
+    first: HashMap<Symbol, HashSet<Terminal>>,
+    follow: HashMap<Symbol, HashSet<Terminal>>,
+    states: Vec<ItemSet>,
+    gotos: Vec<Vec<(Symbol, usize)>>,
+}
+
+impl LrAutomaton {
+    pub fn new(grammar: &Grammar, kind: LrKind) -> Self {
+        let mut start = format!("{}'", grammar.start());
+        while grammar.non_terminals.contains(&start) {
+            start.push('\'');
+        }
+        let mut productions = vec![(start, vec![Symbol::NonTerminal(grammar.start().clone())])];
+        let mut production_precedence = vec![None];
+        for nt in grammar.non_terminals() {
+            for (i, production) in grammar.productions(nt).iter().enumerate() {
+                production_precedence.push(grammar.production_precedence(nt, i));
+                let production = production
+                    .iter()
+                    .filter(|s| **s != Symbol::Terminal(Terminal::Epsilon))
+                    .cloned()
+                    .collect();
+                productions.push((nt.clone(), production));
+            }
+        }
+        let mut by_lhs: HashMap<NonTerminal, Vec<usize>> = HashMap::new();
+        for (i, (lhs, _)) in productions.iter().enumerate() {
+            by_lhs.entry(lhs.clone()).or_default().push(i);
+        }
+        let terminals = grammar
+            .terminals()
+            .iter()
+            .filter(|t| **t != Terminal::Epsilon)
+            .cloned()
+            .chain([Terminal::Token(Token::EOF)]);
+        let mut automaton = LrAutomaton {
+            kind,
+            terminals: sorted(terminals.collect::<HashSet<_>>()),
+            productions,
+            production_precedence,
+            precedence: grammar.precedence.clone(),
+            by_lhs,
+            first: grammar.first_set(),
+            follow: grammar.follow_set(),
+            states: Vec::new(),
+            gotos: Vec::new(),
+        };
+        automaton.build();
+        automaton
+    }
+    fn terminal_index(&self, t: &Terminal) -> usize {
+        self.terminals.iter().position(|u| u == t).unwrap()
+    }
+    fn closure(&self, kernel: &ItemSet) -> ItemSet {
+        let mut items = kernel.clone();
+        let mut work: Vec<_> = items.keys().copied().collect();
+        while let Some((p, dot)) = work.pop() {
+            let rhs = &self.productions[p].1;
+            let Some(Symbol::NonTerminal(b)) = rhs.get(dot) else {
+                continue;
+            };
+            let mut lookaheads = BTreeSet::new();
+            if self.kind.lookaheads() {
+                let mut first = first_of_sequence(&self.first, &rhs[dot + 1..]);
+                if first.remove(&Terminal::Epsilon) {
+                    lookaheads.extend(items[&(p, dot)].iter().copied());
+                }
+                lookaheads.extend(first.iter().map(|t| self.terminal_index(t)));
+            }
+            for &q in self.by_lhs.get(b).into_iter().flatten() {
+                let new = !items.contains_key(&(q, 0));
+                let entry = items.entry((q, 0)).or_default();
+                let before = entry.len();
+                entry.extend(&lookaheads);
+                if new || entry.len() != before {
+                    work.push((q, 0));
+                }
+            }
+        }
+        items
+    }
+    // canonical LR(1) states are told apart by their lookaheads, every other
+    // kind by the items alone; LALR(1) merges the lookaheads of states that
+    // share their items and revisits a state whenever its lookaheads grow
+    fn build(&mut self) {
+        let mut start = ItemSet::new();
+        let eof = self.terminal_index(&Terminal::Token(Token::EOF));
+        start.insert(
+            (0, 0),
+            match self.kind.lookaheads() {
+                true => BTreeSet::from([eof]),
+                false => BTreeSet::new(),
+            },
+        );
+        let key = |kernel: &ItemSet| match self.kind {
+            LrKind::Lr1 => kernel.clone(),
+            _ => kernel.keys().map(|item| (*item, BTreeSet::new())).collect(),
+        };
+        let mut kernels = vec![start.clone()];
+        let mut index = HashMap::from([(key(&start), 0)]);
+        let mut gotos = vec![Vec::new()];
+        let mut work = VecDeque::from([0]);
+        while let Some(s) = work.pop_front() {
+            let mut transitions: Vec<(Symbol, ItemSet)> = Vec::new();
+            for ((p, dot), lookaheads) in self.closure(&kernels[s]) {
+                let Some(x) = self.productions[p].1.get(dot) else {
+                    continue;
+                };
+                let kernel = match transitions.iter_mut().find(|(y, _)| y == x) {
+                    Some((_, kernel)) => kernel,
+                    None => {
+                        transitions.push((x.clone(), ItemSet::new()));
+                        &mut transitions.last_mut().unwrap().1
+                    }
+                };
+                kernel.entry((p, dot + 1)).or_default().extend(lookaheads);
+            }
+            gotos[s].clear();
+            for (x, kernel) in transitions {
+                let k = key(&kernel);
+                let t = match index.get(&k) {
+                    Some(&t) => {
+                        let mut grown = false;
+                        for (item, lookaheads) in kernel {
+                            let entry = kernels[t].entry(item).or_default();
+                            let before = entry.len();
+                            entry.extend(lookaheads);
+                            grown |= entry.len() != before;
+                        }
+                        if grown && !work.contains(&t) {
+                            work.push_back(t);
+                        }
+                        t
+                    }
+                    None => {
+                        kernels.push(kernel);
+                        gotos.push(Vec::new());
+                        index.insert(k, kernels.len() - 1);
+                        work.push_back(kernels.len() - 1);
+                        kernels.len() - 1
+                    }
+                };
+                gotos[s].push((x, t));
+            }
+        }
+        self.states = kernels.iter().map(|kernel| self.closure(kernel)).collect();
+        self.gotos = gotos;
+    }
+    pub fn kind(&self) -> LrKind {
+        self.kind
+    }
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+    pub fn production(&self, index: usize) -> (&NonTerminal, &Production) {
+        let (lhs, rhs) = &self.productions[index];
+        (lhs, rhs)
+    }
+    pub fn goto(&self, state: usize, symbol: &Symbol) -> Option<usize> {
+        let (_, t) = self.gotos[state].iter().find(|(x, _)| x == symbol)?;
+        Some(*t)
+    }
+    fn item(&self, (p, dot): (usize, usize), lookaheads: &BTreeSet<usize>) -> LrItem {
+        let (lhs, rhs) = &self.productions[p];
+        LrItem {
+            non_terminal: lhs.clone(),
+            production: rhs.clone(),
+            dot,
+            lookaheads: lookaheads
+                .iter()
+                .map(|&t| self.terminals[t].clone())
+                .collect(),
+        }
+    }
+    pub fn items(&self, state: usize) -> Vec<LrItem> {
+        self.states[state]
+            .iter()
+            .map(|(item, lookaheads)| self.item(*item, lookaheads))
+            .collect()
+    }
+    // terminals on which a completed item reduces
+    fn reduce_on(&self, (p, _): (usize, usize), lookaheads: &BTreeSet<usize>) -> Vec<usize> {
+        match self.kind {
+            LrKind::Lr0 => (0..self.terminals.len()).collect(),
+            LrKind::Slr1 => {
+                let lhs = Symbol::NonTerminal(self.productions[p].0.clone());
+                let follow = self.follow.get(&lhs).into_iter().flatten();
+                follow
+                    .filter(|t| **t != Terminal::Epsilon)
+                    .map(|t| self.terminal_index(t))
+                    .collect()
+            }
+            LrKind::Lalr1 | LrKind::Lr1 => lookaheads.iter().copied().collect(),
+        }
+    }
+    pub fn table(&self) -> Result<LrTable, Vec<LrConflict>> {
+        let mut table = LrTable {
+            kind: self.kind,
+            productions: self.productions.clone(),
+            action: Vec::new(),
+            goto: Vec::new(),
+        };
+        let mut conflicts = Vec::new();
+        for (s, items) in self.states.iter().enumerate() {
+            let mut cells: BTreeMap<usize, Vec<Action>> = BTreeMap::new();
+            let mut goto = HashMap::new();
+            for (x, t) in &self.gotos[s] {
+                match x {
+                    Symbol::Terminal(terminal) => {
+                        let cell = cells.entry(self.terminal_index(terminal)).or_default();
+                        cell.push(Action::Shift(*t));
+                    }
+                    Symbol::NonTerminal(nt) => {
+                        goto.insert(nt.clone(), *t);
+                    }
+                }
+            }
+            for (&(p, dot), lookaheads) in items {
+                if dot != self.productions[p].1.len() {
+                    continue;
+                }
+                if p == 0 {
+                    let eof = self.terminal_index(&Terminal::Token(Token::EOF));
+                    cells.entry(eof).or_default().push(Action::Accept);
+                    continue;
+                }
+                for t in self.reduce_on((p, dot), lookaheads) {
+                    let cell = cells.entry(t).or_default();
+                    if !cell.contains(&Action::Reduce(p)) {
+                        cell.push(Action::Reduce(p));
+                    }
+                }
+            }
+            let mut action = HashMap::new();
+            for (t, cell) in cells {
+                let lookahead = self.terminals[t].clone();
+                let resolved = match cell[..] {
+                    [single] => Some(Some(single)),
+                    _ => self.resolve(t, &cell),
+                };
+                match resolved {
+                    Some(Some(single)) => {
+                        action.insert(lookahead, single);
+                    }
+                    // a non-associative operator next to itself is an error
+                    Some(None) => {}
+                    None => conflicts.push(self.conflict(s, t, &cell)),
+                }
+            }
+            table.action.push(action);
+            table.goto.push(goto);
+        }
+        match conflicts.is_empty() {
+            true => Ok(table),
+            false => Err(conflicts),
+        }
+    }
+    // settles a shift/reduce conflict the way yacc does: the higher
+    // precedence wins and on a tie the associativity decides, left reducing
+    // and right shifting; None if either side has no precedence
+    fn resolve(&self, t: usize, cell: &[Action]) -> Option<Option<Action>> {
+        let [shift @ Action::Shift(_), reduce @ Action::Reduce(p)] = *cell else {
+            return None;
+        };
+        let token = self.precedence.get(&self.terminals[t])?;
+        let rule = self.production_precedence[p]?;
+        Some(match rule.level.cmp(&token.level) {
+            Ordering::Greater => Some(reduce),
+            Ordering::Less => Some(shift),
+            Ordering::Equal => match token.associativity {
+                Associativity::Left => Some(reduce),
+                Associativity::Right => Some(shift),
+                Associativity::NonAssoc => None,
+            },
+        })
+    }
+    fn conflict(&self, state: usize, t: usize, cell: &[Action]) -> LrConflict {
+        let lookahead = &self.terminals[t];
+        let mut items = Vec::new();
+        for (&(p, dot), lookaheads) in &self.states[state] {
+            let rhs = &self.productions[p].1;
+            let involved = match rhs.get(dot) {
+                Some(Symbol::Terminal(x)) => x == lookahead,
+                Some(Symbol::NonTerminal(_)) => false,
+                None => {
+                    (p == 0 && cell.contains(&Action::Accept))
+                        || (cell.contains(&Action::Reduce(p))
+                            && self.reduce_on((p, dot), lookaheads).contains(&t))
+                }
+            };
+            if involved {
+                items.push(self.item((p, dot), lookaheads));
+            }
+        }
+        let kind = match cell.iter().any(|a| matches!(a, Action::Shift(_))) {
+            true => LrConflictKind::ShiftReduce,
+            false => LrConflictKind::ReduceReduce,
+        };
+        LrConflict {
+            kind,
+            state,
+            lookahead: lookahead.clone(),
+            items,
+        }
+    }
+}
+
+impl fmt::Display for LrAutomaton {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for state in 0..self.len() {
+            writeln!(f, "state {state}")?;
+            for item in self.items(state) {
+                writeln!(f, "    {item}")?;
+            }
+            for (x, t) in &self.gotos[state] {
+                writeln!(f, "    on {x} goto {t}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    Shift(usize),
+    // index of the production to reduce by
+    Reduce(usize),
+    Accept,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Action::Shift(state) => write!(f, "s{state}"),
+            Action::Reduce(production) => write!(f, "r{production}"),
+            Action::Accept => write!(f, "acc"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LrConflictKind {
+    ShiftReduce,
+    ReduceReduce,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LrConflict {
+    pub kind: LrConflictKind,
+    pub state: usize,
+    pub lookahead: Terminal,
+    pub items: Vec<LrItem>,
+}
+
+impl fmt::Display for LrConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            LrConflictKind::ShiftReduce => "shift/reduce",
+            LrConflictKind::ReduceReduce => "reduce/reduce",
+        };
+        write!(
+            f,
+            "{kind} conflict in state {} on {}:",
+            self.state, self.lookahead
+        )?;
+        for item in &self.items {
+            write!(f, "\n    {item}")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for LrConflict {}
+
+// ACTION and GOTO tables of an LR automaton
+#[derive(Debug, Clone)]
+pub struct LrTable {
+    kind: LrKind,
+    productions: Vec<(NonTerminal, Production)>,
+    action: Vec<HashMap<Terminal, Action>>,
+    goto: Vec<HashMap<NonTerminal, usize>>,
+}
+
+impl LrTable {
+    pub fn new(grammar: &Grammar, kind: LrKind) -> Result<Self, Vec<LrConflict>> {
+        LrAutomaton::new(grammar, kind).table()
+    }
+    pub fn kind(&self) -> LrKind {
+        self.kind
+    }
+    pub fn len(&self) -> usize {
+        self.action.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.action.is_empty()
+    }
+    pub fn production(&self, index: usize) -> (&NonTerminal, &Production) {
+        let (lhs, rhs) = &self.productions[index];
+        (lhs, rhs)
+    }
+    pub fn action(&self, state: usize, lookahead: &Terminal) -> Option<Action> {
+        self.action[state].get(lookahead).copied()
+    }
+    pub fn goto(&self, state: usize, non_terminal: &str) -> Option<usize> {
+        self.goto[state].get(non_terminal).copied()
+    }
+    // exact tokens take precedence over the class they belong to
+    fn lookup(&self, state: usize, token: &Token) -> Option<Action> {
+        let row = &self.action[state];
+        let action = row.get(&Terminal::Token(token.clone())).or_else(|| {
+            let class = TokenClass::of(token)?;
+            row.get(&Terminal::Class(class))
+        });
+        action.copied()
+    }
+    // shift-reduce parse, the input ends at the first `Token::EOF` or when
+    // the iterator runs out
+    pub fn parse(&self, tokens: impl IntoIterator<Item = Token>) -> Result<ParseTree, SyntaxError> {
+        let mut tokens = tokens.into_iter().chain(std::iter::repeat(Token::EOF));
+        let mut lookahead = tokens.next().unwrap_or(Token::EOF);
+        let mut position = 0;
+        let mut states = vec![0];
+        let mut trees = Vec::new();
+        loop {
+            let state = *states.last().unwrap();
+            let Some(action) = self.lookup(state, &lookahead) else {
+                return Err(SyntaxError {
+                    position,
+                    found: lookahead,
+                    expected: sorted(self.action[state].keys().cloned()),
+                });
+            };
+            match action {
+                Action::Shift(next) => {
+                    states.push(next);
+                    let token = tokens.next().unwrap_or(Token::EOF);
+                    trees.push(ParseTree::Leaf(std::mem::replace(&mut lookahead, token)));
+                    position += 1;
+                }
+                Action::Reduce(p) => {
+                    let (lhs, rhs) = &self.productions[p];
+                    states.truncate(states.len() - rhs.len());
+                    let children = trees.split_off(trees.len() - rhs.len());
+                    let state = *states.last().unwrap();
+                    let next = self.goto(state, lhs).expect("goto after a reduction");
+                    states.push(next);
+                    trees.push(ParseTree::Node {
+                        non_terminal: lhs.clone(),
+                        children,
+                    });
+                }
+                Action::Accept => return Ok(trees.pop().unwrap()),
+            }
+        }
+    }
+}
+
+impl Grammar {
+    pub fn lr_automaton(&self, kind: LrKind) -> LrAutomaton {
+        LrAutomaton::new(self, kind)
+    }
+    pub fn lr_table(&self, kind: LrKind) -> Result<LrTable, Vec<LrConflict>> {
+        LrTable::new(self, kind)
+    }
+}
+
+impl fmt::Display for LrTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, (lhs, rhs)) in self.productions.iter().enumerate() {
+            write!(f, "r{i}: {lhs} =>")?;
+            for symbol in rhs {
+                write!(f, " {symbol}")?;
+            }
+            writeln!(f)?;
+        }
+        for (state, (action, goto)) in self.action.iter().zip(&self.goto).enumerate() {
+            writeln!(f, "state {state}")?;
+            for t in sorted(action.keys().cloned()) {
+                writeln!(f, "    {t} => {}", action[&t])?;
+            }
+            let mut goto: Vec<_> = goto.iter().collect();
+            goto.sort();
+            for (nt, target) in goto {
+                writeln!(f, "    {nt} goto {target}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LrConflictKind, LrKind};
+    use crate::{
+        lexer::{Lexer, Token},
+        parser::{Grammar, ParseTree, Terminal, TokenClass},
+    };
+
+    const KINDS: [LrKind; 4] = [LrKind::Lr0, LrKind::Slr1, LrKind::Lalr1, LrKind::Lr1];
+
+    const EXPRESSIONS: &str = "
+        E => E '+' T | T ;
+        T => T '*' F | F ;
+        F => '(' E ')' | %identifier ;
+    ";
+
+    // needs SLR(1) lookaheads but is not LR(0)
+    const POINTERS: &str = "S => L '=' R | R ; L => '*' R | %identifier ; R => L ;";
+
+    // LR(1), but merging the states that reduce `c` gives LALR(1) a
+    // reduce/reduce conflict
+    const MERGED: &str = "
+        S => 'a' A 'd' | 'b' B 'd' | 'a' B 'e' | 'b' A 'e' ;
+        A => 'c' ;
+        B => 'c' ;
+    ";
+
+    fn grammar(source: &str) -> Grammar {
+        source.parse().unwrap()
+    }
+
+    #[test]
+    fn each_kind_accepts_what_it_should() {
+        // whether LR(0), SLR(1), LALR(1) and LR(1) tables have no conflicts
+        let expected = [
+            (EXPRESSIONS, [false, true, true, true]),
+            (POINTERS, [false, false, true, true]),
+            (MERGED, [false, false, false, true]),
+            ("S => 'a' S 'b' | 'c' ;", [true, true, true, true]),
+        ];
+        for (source, accepted) in expected {
+            let grammar = grammar(source);
+            for (kind, accepted) in KINDS.into_iter().zip(accepted) {
+                let table = grammar.lr_table(kind);
+                assert_eq!(table.is_ok(), accepted, "{kind} of {source}");
+            }
+        }
+        let conflicts = grammar(MERGED).lr_table(LrKind::Lalr1).unwrap_err();
+        assert!(conflicts
+            .iter()
+            .all(|c| c.kind == LrConflictKind::ReduceReduce));
+        let conflicts = grammar(POINTERS).lr_table(LrKind::Slr1).unwrap_err();
+        assert_eq!(conflicts.len(), 1);
+        let expected = "shift/reduce conflict in state 2 on '=':\n    S => L . '=' R\n    R => L .";
+        assert_eq!(conflicts[0].to_string(), expected);
+    }
+
+    #[test]
+    fn state_counts_follow_the_construction() {
+        // LALR(1) has the LR(0) states, canonical LR(1) splits them by lookahead
+        let counts = [(EXPRESSIONS, [12, 12, 12, 22]), (MERGED, [13, 13, 13, 14])];
+        for (source, counts) in counts {
+            let grammar = grammar(source);
+            for (kind, count) in KINDS.into_iter().zip(counts) {
+                let automaton = grammar.lr_automaton(kind);
+                assert_eq!(automaton.len(), count, "{kind} of {source}");
+                assert_eq!(automaton.kind(), kind);
+            }
+        }
+        let automaton = grammar(POINTERS).lr_automaton(LrKind::Lalr1);
+        let items: Vec<_> = automaton.items(0).iter().map(|i| i.to_string()).collect();
+        assert_eq!(items[0], "S' => . S ['<EOF>']");
+        assert!(items.contains(&"L => . '*' R ['=' '<EOF>']".to_string()));
+    }
+
+    const OPERATORS: &str = "
+        %nonassoc '<'
+        %left '+' '-'
+        %left '*'
+        %right '^'
+        %right '!'
+        E => E '<' E | E '+' E | E '-' E | E '*' E | E '^' E | '-' E %prec '!'
+           | '(' E ')' | %identifier ;
+    ";
+
+    fn tokens(source: &str) -> Vec<Token> {
+        let tokens = Lexer::from_source(source).map(|token| token.unwrap().node);
+        tokens.collect()
+    }
+
+    // the tree with every operation in parentheses
+    fn grouped(tree: &ParseTree) -> String {
+        match tree {
+            ParseTree::Leaf(token) => token.to_string(),
+            ParseTree::Node { children, .. } => match &children[..] {
+                [single] => grouped(single),
+                [ParseTree::Leaf(Token::LeftParen), inner, _] => grouped(inner),
+                _ => {
+                    let parts: Vec<_> = children.iter().map(grouped).collect();
+                    format!("({})", parts.join(" "))
+                }
+            },
+        }
+    }
+
+    #[test]
+    fn precedence_and_associativity_settle_conflicts() {
+        assert!(grammar("E => E '+' E | %identifier ;")
+            .lr_table(LrKind::Lalr1)
+            .is_err());
+        let table = grammar(OPERATORS).lr_table(LrKind::Lalr1).unwrap();
+        let parse = |source: &str| table.parse(tokens(source)).map(|tree| grouped(&tree));
+        let expected = [
+            ("a + b * c", "(a + (b * c))"),
+            ("a * b + c", "((a * b) + c)"),
+            ("a - b - c", "((a - b) - c)"),
+            ("a ^ b ^ c", "(a ^ (b ^ c))"),
+            ("- a * b", "((- a) * b)"),
+            ("- a ^ b", "((- a) ^ b)"),
+            ("a < b + c", "(a < (b + c))"),
+            ("(a + b) * c", "((a + b) * c)"),
+        ];
+        for (source, expected) in expected {
+            assert_eq!(parse(source).as_deref(), Ok(expected), "{source}");
+        }
+        // a non-associative operator can't be chained
+        let error = parse("a < b < c").unwrap_err();
+        assert_eq!((error.position, error.found), (3, Token::LessThan));
+        let error = parse("a + ").unwrap_err();
+        assert_eq!((error.position, error.found), (2, Token::EOF));
+        assert!(error
+            .expected
+            .contains(&Terminal::Class(TokenClass::Identifier)));
+    }
+}
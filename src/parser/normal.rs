@@ -0,0 +1,548 @@
+use std::{collections::HashMap, fmt, iter};
+
+use super::{Grammar, NonTerminal, Production, Symbol, Terminal};
+
+// why a normal form conversion introduced a non-terminal
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    // new start symbol so the old one never appears on a right hand side
+    Start(NonTerminal),
+    // stands for a terminal inside a longer production
+    Terminal(Terminal),
+    // stands for the tail of a long production of a non-terminal
+    Tail {
+        non_terminal: NonTerminal,
+        symbols: Production,
+    },
+    // repetition left over from removing left recursion of a non-terminal
+    Recursion(NonTerminal),
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Origin::Start(start) => write!(f, "new start symbol replacing {start}"),
+            Origin::Terminal(t) => write!(f, "stands for {t}"),
+            Origin::Tail {
+                non_terminal,
+                symbols,
+            } => {
+                write!(f, "stands for")?;
+                for symbol in symbols {
+                    write!(f, " {symbol}")?;
+                }
+                write!(f, " in a production of {non_terminal}")
+            }
+            Origin::Recursion(nt) => write!(f, "repetition from left recursion of {nt}"),
+        }
+    }
+}
+
+// a converted grammar with the origin of every non-terminal it introduced
+#[derive(Debug, Clone)]
+pub struct NormalForm {
+    pub grammar: Grammar,
+    pub origins: Vec<(NonTerminal, Origin)>,
+}
+
+// prints the grammar followed by the origins as comments, so the output
+// still reads back as a grammar
+impl fmt::Display for NormalForm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.grammar)?;
+        for (nt, origin) in &self.origins {
+            writeln!(f, "# {nt}: {origin}")?;
+        }
+        Ok(())
+    }
+}
+
+// rules under construction, in the order they should be printed
+struct Rules {
+    order: Vec<NonTerminal>,
+    rules: HashMap<NonTerminal, Vec<Production>>,
+    origins: Vec<(NonTerminal, Origin)>,
+}
+
+impl Rules {
+    fn new(grammar: &Grammar) -> Self {
+        Rules {
+            order: grammar.non_terminals().into_iter().cloned().collect(),
+            rules: grammar.rules.clone(),
+            origins: Vec::new(),
+        }
+    }
+    fn fresh(&mut self, base: &str, origin: Origin) -> NonTerminal {
+        let name = (1..)
+            .map(|n| format!("{base}_{n}"))
+            .find(|name| !self.order.contains(name))
+            .unwrap_or_default();
+        self.order.push(name.clone());
+        self.origins.push((name.clone(), origin));
+        name
+    }
+    fn productions(&self, nt: &str) -> Vec<Production> {
+        self.rules.get(nt).cloned().unwrap_or_default()
+    }
+    // ways `nt` derives %empty, leaving out derivations that loop back to a
+    // non-terminal they are already inside
+    fn empty_derivations(&self, nt: &str, inside: &mut Vec<NonTerminal>) -> u128 {
+        if inside.iter().any(|n| n == nt) {
+            return 0;
+        }
+        inside.push(nt.to_string());
+        let mut count: u128 = 0;
+        for production in self.productions(nt) {
+            let mut ways: u128 = 1;
+            for symbol in &production {
+                ways = match symbol {
+                    Symbol::Terminal(Terminal::Epsilon) => ways,
+                    Symbol::Terminal(_) => 0,
+                    Symbol::NonTerminal(n) => {
+                        ways.saturating_mul(self.empty_derivations(n, inside))
+                    }
+                };
+                if ways == 0 {
+                    break;
+                }
+            }
+            count = count.saturating_add(ways);
+        }
+        inside.pop();
+        count
+    }
+    fn grammar(&self, like: &Grammar, start: &str) -> Grammar {
+        let mut grammar = like.with_rules(&self.order, &self.rules);
+        grammar.start = start.to_string();
+        grammar
+    }
+}
+
+fn is_epsilon(production: &[Symbol]) -> bool {
+    production
+        .iter()
+        .all(|s| *s == Symbol::Terminal(Terminal::Epsilon))
+}
+
+impl Grammar {
+    // Chomsky normal form: every production is `A => B C` or `A => a`, and
+    // only the start may derive %empty; done by the usual START, TERM, BIN,
+    // DEL and UNIT steps followed by `reduce`
+    pub fn to_cnf(&self) -> NormalForm {
+        let mut rules = Rules::new(self);
+        let mut start = self.start.clone();
+        let mut on_right = rules.rules.values().flatten().flatten();
+        if on_right.any(|s| *s == Symbol::NonTerminal(start.clone())) {
+            let mut new = self.fresh(&start, &rules.rules);
+            while rules.order.contains(&new) {
+                new.push('\'');
+            }
+            rules.order.insert(0, new.clone());
+            rules
+                .origins
+                .push((new.clone(), Origin::Start(start.clone())));
+            let production = vec![Symbol::NonTerminal(start)];
+            rules.rules.insert(new.clone(), vec![production]);
+            start = new;
+        }
+        // TERM: terminals in longer productions get a non-terminal of their own
+        let mut terminals: Vec<(Terminal, NonTerminal)> = Vec::new();
+        for nt in rules.order.clone() {
+            let mut productions = rules.productions(&nt);
+            for production in productions.iter_mut().filter(|p| p.len() > 1) {
+                for symbol in production.iter_mut() {
+                    let Symbol::Terminal(t) = symbol.clone() else {
+                        continue;
+                    };
+                    let name = match terminals.iter().find(|(u, _)| *u == t) {
+                        Some((_, name)) => name.clone(),
+                        None => {
+                            let name = rules.fresh("T", Origin::Terminal(t.clone()));
+                            let production = vec![Symbol::Terminal(t.clone())];
+                            rules.rules.insert(name.clone(), vec![production]);
+                            terminals.push((t, name.clone()));
+                            name
+                        }
+                    };
+                    *symbol = Symbol::NonTerminal(name);
+                }
+            }
+            rules.rules.insert(nt, productions);
+        }
+        // BIN: A => X Y Z becomes A => X A_1 and A_1 => Y Z
+        for nt in rules.order.clone() {
+            let mut productions = rules.productions(&nt);
+            let mut tails = Vec::new();
+            for production in productions.iter_mut().filter(|p| p.len() > 2) {
+                let origin = |symbols: &Production| Origin::Tail {
+                    non_terminal: nt.clone(),
+                    symbols: symbols.clone(),
+                };
+                let mut rest = production.split_off(1);
+                let mut tail = rules.fresh(&nt, origin(&rest));
+                production.push(Symbol::NonTerminal(tail.clone()));
+                while rest.len() > 2 {
+                    let more = rest.split_off(1);
+                    let next = rules.fresh(&nt, origin(&more));
+                    rest.push(Symbol::NonTerminal(next.clone()));
+                    tails.push((tail, rest));
+                    (tail, rest) = (next, more);
+                }
+                tails.push((tail, rest));
+            }
+            rules.rules.insert(nt, productions);
+            for (tail, production) in tails {
+                rules.rules.insert(tail, vec![production]);
+            }
+        }
+        // DEL: every nullable symbol may also be left out, then only the
+        // start keeps an empty production; a variant is listed once for each
+        // way the symbols left out derive %empty, so no derivation is lost
+        let nullable = rules.grammar(self, &start).nullable();
+        let empty: HashMap<NonTerminal, u128> = nullable
+            .into_iter()
+            .map(|nt| {
+                let ways = rules.empty_derivations(&nt, &mut Vec::new());
+                (nt, ways)
+            })
+            .collect();
+        for nt in rules.order.clone() {
+            let mut expanded: Vec<Production> = Vec::new();
+            for production in rules.productions(&nt) {
+                if is_epsilon(&production) {
+                    continue;
+                }
+                let mut variants: Vec<(Production, u128)> = vec![(vec![], 1)];
+                for symbol in &production {
+                    let left_out = match symbol {
+                        Symbol::NonTerminal(n) => empty.get(n),
+                        Symbol::Terminal(_) => None,
+                    };
+                    let mut next = Vec::new();
+                    for (mut variant, ways) in variants {
+                        if let Some(n) = left_out {
+                            next.push((variant.clone(), ways.saturating_mul(*n)));
+                        }
+                        variant.push(symbol.clone());
+                        next.push((variant, ways));
+                    }
+                    variants = next;
+                }
+                for (variant, ways) in variants {
+                    if !variant.is_empty() {
+                        expanded.extend(iter::repeat_n(variant, ways as usize));
+                    }
+                }
+            }
+            if let Some(ways) = empty.get(&nt).filter(|_| nt == start) {
+                let epsilon = vec![Symbol::Terminal(Terminal::Epsilon)];
+                expanded.extend(iter::repeat_n(epsilon, *ways as usize));
+            }
+            rules.rules.insert(nt, expanded);
+        }
+        // UNIT: A => B is replaced by the productions B reaches without units
+        let unit = |production: &Production| match production[..] {
+            [Symbol::NonTerminal(ref b)] => Some(b.clone()),
+            _ => None,
+        };
+        // every chain of units that does not loop counts as a derivation of
+        // its own, as in the Earley forest
+        let mut replaced = HashMap::new();
+        for nt in &rules.order {
+            let mut chains = vec![vec![nt.clone()]];
+            let mut productions: Vec<Production> = Vec::new();
+            let mut i = 0;
+            while i < chains.len() {
+                let chain = chains[i].clone();
+                for production in rules.productions(&chain[chain.len() - 1]) {
+                    match unit(&production) {
+                        Some(b) if !chain.contains(&b) => {
+                            let mut longer = chain.clone();
+                            longer.push(b);
+                            chains.push(longer);
+                        }
+                        Some(_) => {}
+                        None => productions.push(production),
+                    }
+                }
+                i += 1;
+            }
+            replaced.insert(nt.clone(), productions);
+        }
+        rules.rules = replaced;
+        rules.normal_form(self, &start)
+    }
+    // Greibach normal form: every production is `A => a B C ...` and only
+    // the start may derive %empty; built from the Chomsky normal form by
+    // ordering the non-terminals, removing left recursion with tails that
+    // never vanish and substituting back until every production starts with
+    // a terminal
+    pub fn to_gnf(&self) -> NormalForm {
+        let cnf = self.to_cnf();
+        let mut rules = Rules::new(&cnf.grammar);
+        rules.origins = cnf.origins;
+        let start = cnf.grammar.start.clone();
+        let epsilon = vec![Symbol::Terminal(Terminal::Epsilon)];
+        let empty = rules.productions(&start).contains(&epsilon);
+        if let Some(productions) = rules.rules.get_mut(&start) {
+            productions.retain(|p| *p != epsilon);
+        }
+        let order = rules.order.clone();
+        let position = |symbol: &Symbol| match symbol {
+            Symbol::NonTerminal(nt) => order.iter().position(|n| n == nt),
+            Symbol::Terminal(_) => None,
+        };
+        // replaces a leading non-terminal accepted by `replace` with its
+        // productions
+        let substitute = |rules: &Rules, nt: &str, replace: &dyn Fn(usize) -> bool| {
+            let mut substituted: Vec<Production> = Vec::new();
+            for production in rules.productions(nt) {
+                match position(&production[0]) {
+                    Some(j) if replace(j) => {
+                        for delta in rules.productions(&order[j]) {
+                            let mut new = delta.clone();
+                            new.extend_from_slice(&production[1..]);
+                            if !substituted.contains(&new) {
+                                substituted.push(new);
+                            }
+                        }
+                    }
+                    _ => substituted.push(production),
+                }
+            }
+            substituted
+        };
+        let mut tails = Vec::new();
+        for (i, ai) in order.iter().enumerate() {
+            for j in 0..i {
+                let substituted = substitute(&rules, ai, &|k| k == j);
+                rules.rules.insert(ai.clone(), substituted);
+            }
+            let recursive = |p: &Production| position(&p[0]) == Some(i);
+            let (recursive, rest): (Vec<_>, Vec<_>) =
+                rules.productions(ai).into_iter().partition(recursive);
+            if recursive.is_empty() {
+                continue;
+            }
+            // A => A a | b becomes A => b | b Z and Z => a | a Z
+            let tail = rules.fresh(ai, Origin::Recursion(ai.clone()));
+            let z = Symbol::NonTerminal(tail.clone());
+            let mut betas = rest.clone();
+            betas.extend(rest.into_iter().map(|mut beta| {
+                beta.push(z.clone());
+                beta
+            }));
+            let alphas: Vec<Production> = recursive.iter().map(|p| p[1..].to_vec()).collect();
+            let mut repeated = alphas.clone();
+            repeated.extend(alphas.into_iter().map(|mut alpha| {
+                alpha.push(z.clone());
+                alpha
+            }));
+            rules.rules.insert(ai.clone(), betas);
+            rules.rules.insert(tail.clone(), repeated);
+            tails.push(tail);
+        }
+        // leading non-terminals now only point further down the order
+        for (i, ai) in order.iter().enumerate().rev() {
+            let substituted = substitute(&rules, ai, &|j| j > i);
+            rules.rules.insert(ai.clone(), substituted);
+        }
+        for tail in tails {
+            let substituted = substitute(&rules, &tail, &|_| true);
+            rules.rules.insert(tail, substituted);
+        }
+        if empty {
+            rules.rules.entry(start.clone()).or_default().push(epsilon);
+        }
+        rules.normal_form(self, &start)
+    }
+}
+
+impl Rules {
+    // drops useless symbols and the origins of whatever was removed; the
+    // substitutions of GNF can leave a stand-in for a terminal unused, so
+    // the origins mentioning it get the terminal back
+    fn normal_form(self, like: &Grammar, start: &str) -> NormalForm {
+        let (grammar, _) = self.grammar(like, start).reduce();
+        let kept = |nt: &NonTerminal| grammar.rules.contains_key(nt);
+        let terminals: HashMap<&NonTerminal, &Terminal> = self
+            .origins
+            .iter()
+            .filter_map(|(nt, origin)| match origin {
+                Origin::Terminal(t) if !kept(nt) => Some((nt, t)),
+                _ => None,
+            })
+            .collect();
+        let origins = self
+            .origins
+            .iter()
+            .filter(|(nt, _)| kept(nt))
+            .map(|(nt, origin)| {
+                let mut origin = origin.clone();
+                if let Origin::Tail { symbols, .. } = &mut origin {
+                    for symbol in symbols.iter_mut() {
+                        let Symbol::NonTerminal(n) = symbol else {
+                            continue;
+                        };
+                        if let Some(t) = terminals.get(n) {
+                            *symbol = Symbol::Terminal((*t).clone());
+                        }
+                    }
+                }
+                (nt.clone(), origin)
+            })
+            .collect();
+        NormalForm { grammar, origins }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{NormalForm, Origin};
+    use crate::{
+        lexer::{Lexer, Token},
+        parser::{Grammar, Symbol, Terminal},
+    };
+
+    fn origins(form: &NormalForm) -> Vec<String> {
+        let origins = form.origins.iter();
+        origins
+            .map(|(nt, origin)| format!("{nt}: {origin}"))
+            .collect()
+    }
+
+    // every non-terminal an origin names, or mentions in a production it
+    // stands for, is still in the grammar
+    fn consistent(form: &NormalForm) -> bool {
+        let defined = |nt: &String| form.grammar.rules.contains_key(nt);
+        form.origins.iter().all(|(nt, origin)| {
+            let symbols = match origin {
+                Origin::Tail { symbols, .. } => symbols.clone(),
+                _ => Vec::new(),
+            };
+            defined(nt)
+                && symbols.iter().all(|symbol| match symbol {
+                    Symbol::NonTerminal(n) => defined(n),
+                    Symbol::Terminal(_) => true,
+                })
+        })
+    }
+
+    #[test]
+    fn gnf_origins_follow_the_substitutions() {
+        let grammar: Grammar = "S => A 'a' 'b' ; A => 'x' ;".parse().unwrap();
+        let cnf = grammar.to_cnf();
+        let expected = [
+            "T_1: stands for 'a'",
+            "T_2: stands for 'b'",
+            "S_1: stands for T_1 T_2 in a production of S",
+        ];
+        assert_eq!(origins(&cnf), expected);
+        let gnf = grammar.to_gnf();
+        let expected = [
+            "T_2: stands for 'b'",
+            "S_1: stands for 'a' T_2 in a production of S",
+        ];
+        assert_eq!(origins(&gnf), expected);
+        assert!(consistent(&cnf) && consistent(&gnf));
+        let grammar: Grammar = "E => E '+' T | T ; T => T '*' 'x' | 'x' ;".parse().unwrap();
+        let gnf = grammar.to_gnf();
+        assert!(consistent(&gnf));
+        let recursion = origins(&gnf)
+            .into_iter()
+            .filter(|o| o.contains("recursion"));
+        assert_eq!(recursion.count(), 2);
+    }
+
+    const GRAMMARS: [&str; 4] = [
+        "E => E '+' T | T ; T => T '*' F | F ; F => '(' E ')' | %identifier ;",
+        "S => 'a' S 'b' | ε ;",
+        "S => A S A | 'a' B ; A => B | S ; B => 'b' | ε ;",
+        "S => S S | '(' S ')' | '(' ')' ;",
+    ];
+
+    const INPUTS: [&str; 10] = [
+        "",
+        "a",
+        "b",
+        "a b",
+        "a a b b",
+        "x + y * z",
+        "( x )",
+        "( ) ( )",
+        "( ( ) )",
+        "a b a",
+    ];
+
+    fn tokens(source: &str) -> Vec<Token> {
+        let tokens = Lexer::from_source(source).map(|token| token.unwrap().node);
+        tokens.collect()
+    }
+
+    fn on_right(grammar: &Grammar, nt: &str) -> bool {
+        let symbols = grammar.rules.values().flatten().flatten();
+        symbols
+            .into_iter()
+            .any(|s| *s == Symbol::NonTerminal(nt.to_string()))
+    }
+
+    #[test]
+    fn cnf_has_the_shape_and_language() {
+        let epsilon = vec![Symbol::Terminal(Terminal::Epsilon)];
+        for source in GRAMMARS {
+            let grammar: Grammar = source.parse().unwrap();
+            let cnf = grammar.to_cnf().grammar;
+            let start = cnf.start().clone();
+            assert!(!on_right(&cnf, &start), "{source}");
+            for nt in cnf.non_terminals() {
+                for production in cnf.productions(nt) {
+                    let shaped = match &production[..] {
+                        [Symbol::NonTerminal(_), Symbol::NonTerminal(_)] => true,
+                        [Symbol::Terminal(t)] => *t != Terminal::Epsilon || *nt == start,
+                        _ => false,
+                    };
+                    assert!(shaped, "{nt} => {production:?} in the CNF of {source}");
+                }
+            }
+            let nullable = grammar.nullable().contains(grammar.start());
+            assert_eq!(cnf.productions(&start).contains(&epsilon), nullable);
+            for input in INPUTS {
+                let expected = grammar.recognizes(tokens(input));
+                assert_eq!(
+                    cnf.recognizes(tokens(input)),
+                    expected,
+                    "{source} on {input:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn gnf_has_the_shape_and_language() {
+        for source in GRAMMARS {
+            let grammar: Grammar = source.parse().unwrap();
+            let gnf = grammar.to_gnf().grammar;
+            let start = gnf.start().clone();
+            for nt in gnf.non_terminals() {
+                for production in gnf.productions(nt) {
+                    let shaped = match &production[..] {
+                        [Symbol::Terminal(Terminal::Epsilon)] => *nt == start,
+                        [Symbol::Terminal(_), rest @ ..] => {
+                            rest.iter().all(|s| matches!(s, Symbol::NonTerminal(_)))
+                        }
+                        _ => false,
+                    };
+                    assert!(shaped, "{nt} => {production:?} in the GNF of {source}");
+                }
+            }
+            for input in INPUTS {
+                let expected = grammar.recognizes(tokens(input));
+                assert_eq!(
+                    gnf.recognizes(tokens(input)),
+                    expected,
+                    "{source} on {input:?}"
+                );
+            }
+        }
+    }
+}
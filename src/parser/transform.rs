@@ -0,0 +1,348 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{sorted, Grammar, GrammarWarning, NonTerminal, Production, Symbol, Terminal};
+
+// joins two sentential forms, keeping epsilon only for an empty result
+fn concat(a: &[Symbol], b: &[Symbol]) -> Production {
+    let epsilon = Symbol::Terminal(Terminal::Epsilon);
+    let production: Production = a
+        .iter()
+        .chain(b)
+        .filter(|s| **s != epsilon)
+        .cloned()
+        .collect();
+    match production.is_empty() {
+        true => vec![epsilon],
+        false => production,
+    }
+}
+
+// whether `from` can derive a sentential form starting with `to`
+fn left_derives(rules: &HashMap<NonTerminal, Vec<Production>>, from: &str, to: &str) -> bool {
+    let mut seen = vec![from];
+    let mut work = vec![from];
+    while let Some(nt) = work.pop() {
+        for production in rules.get(nt).into_iter().flatten() {
+            let Some(Symbol::NonTerminal(next)) = production.first() else {
+                continue;
+            };
+            if next == to {
+                return true;
+            }
+            if !seen.contains(&next.as_str()) {
+                seen.push(next);
+                work.push(next);
+            }
+        }
+    }
+    false
+}
+
+impl Grammar {
+    // a name not yet used by the grammar, `A'`, `A''` and so on
+    pub(super) fn fresh(
+        &self,
+        base: &str,
+        taken: &HashMap<NonTerminal, Vec<Production>>,
+    ) -> NonTerminal {
+        let mut name = format!("{base}'");
+        while self.non_terminals.contains(&name) || taken.contains_key(&name) {
+            name.push('\'');
+        }
+        name
+    }
+    // a copy with the same start and precedence but new rules, `%prec`
+    // overrides are dropped since production indices no longer line up
+    pub(super) fn with_rules(
+        &self,
+        order: &[NonTerminal],
+        rules: &HashMap<NonTerminal, Vec<Production>>,
+    ) -> Grammar {
+        let mut grammar = Grammar {
+            start: self.start.clone(),
+            precedence: self.precedence.clone(),
+            ..Default::default()
+        };
+        for nt in order {
+            grammar.non_terminals.insert(nt.clone());
+            for production in rules.get(nt).into_iter().flatten() {
+                grammar.add_rule(nt.clone(), production.clone());
+            }
+        }
+        grammar
+    }
+    // removes immediate and indirect left recursion by the usual ordering
+    // method: productions of earlier non-terminals that lead back to a later
+    // one are substituted into it, then immediate recursion `A => A a | b` becomes
+    // `A => b A'` and `A' => a A' | %empty`; cycles `A => A` are dropped and
+    // recursion hidden behind a nullable prefix is left alone
+    pub fn eliminate_left_recursion(&self) -> Grammar {
+        let mut order: Vec<NonTerminal> = self.non_terminals().into_iter().cloned().collect();
+        let mut rules: HashMap<NonTerminal, Vec<Production>> = self.rules.clone();
+        let original = order.len();
+        for i in 0..original {
+            let ai = order[i].clone();
+            rules.entry(ai.clone()).or_default();
+            for aj in &order[..i] {
+                if !left_derives(&rules, aj, &ai) {
+                    continue;
+                }
+                let mut substituted = Vec::new();
+                for production in &rules[&ai] {
+                    match production.first() {
+                        Some(Symbol::NonTerminal(nt)) if nt == aj => {
+                            for delta in rules.get(aj).into_iter().flatten() {
+                                substituted.push(concat(delta, &production[1..]));
+                            }
+                        }
+                        _ => substituted.push(production.clone()),
+                    }
+                }
+                rules.insert(ai.clone(), substituted);
+            }
+            let (recursive, rest): (Vec<_>, Vec<_>) = rules[&ai]
+                .iter()
+                .cloned()
+                .partition(|p| p.first() == Some(&Symbol::NonTerminal(ai.clone())));
+            if recursive.is_empty() {
+                continue;
+            }
+            let alphas: Vec<&[Symbol]> = recursive
+                .iter()
+                .map(|p| &p[1..])
+                .filter(|alpha| !alpha.is_empty())
+                .collect();
+            if alphas.is_empty() {
+                rules.insert(ai, rest);
+                continue;
+            }
+            let tail = self.fresh(&ai, &rules);
+            let next = [Symbol::NonTerminal(tail.clone())];
+            let betas = rest.iter().map(|beta| concat(beta, &next)).collect();
+            let mut tails: Vec<Production> =
+                alphas.iter().map(|alpha| concat(alpha, &next)).collect();
+            tails.push(vec![Symbol::Terminal(Terminal::Epsilon)]);
+            rules.insert(ai, betas);
+            rules.insert(tail.clone(), tails);
+            order.push(tail);
+        }
+        self.with_rules(&order, &rules)
+    }
+    // repeatedly pulls the longest prefix shared by alternatives starting
+    // with the same symbol into a fresh non-terminal, `A => a b | a c`
+    // becomes `A => a A'` and `A' => b | c`
+    pub fn left_factor(&self) -> Grammar {
+        let mut order: Vec<NonTerminal> = self.non_terminals().into_iter().cloned().collect();
+        let mut rules: HashMap<NonTerminal, Vec<Production>> = self.rules.clone();
+        let epsilon = Symbol::Terminal(Terminal::Epsilon);
+        let mut i = 0;
+        while i < order.len() {
+            let nt = order[i].clone();
+            loop {
+                let productions = rules.get(&nt).cloned().unwrap_or_default();
+                // empty productions, written with or without ε, share no
+                // prefix with anything and are left alone
+                let group: Vec<&Production> = productions
+                    .iter()
+                    .enumerate()
+                    .filter_map(|(j, p)| Some((j, p.first().filter(|s| **s != epsilon)?)))
+                    .find_map(|(j, first)| {
+                        let group: Vec<_> = productions[j..]
+                            .iter()
+                            .filter(|q| q.first() == Some(first))
+                            .collect();
+                        (group.len() > 1).then_some(group)
+                    })
+                    .unwrap_or_default();
+                let Some((first, rest)) = group.split_first() else {
+                    break;
+                };
+                let mut prefix = first.len();
+                for production in rest {
+                    let common = first.iter().zip(*production).take_while(|(a, b)| a == b);
+                    prefix = prefix.min(common.count());
+                }
+                let tail = self.fresh(&nt, &rules);
+                let mut factored = Vec::new();
+                let mut suffixes = Vec::new();
+                for production in &productions {
+                    if !group.contains(&production) {
+                        factored.push(production.clone());
+                        continue;
+                    }
+                    if suffixes.is_empty() {
+                        let tail = Symbol::NonTerminal(tail.clone());
+                        factored.push(concat(&production[..prefix], &[tail]));
+                    }
+                    let suffix = concat(&production[prefix..], &[]);
+                    if !suffixes.contains(&suffix) {
+                        suffixes.push(suffix);
+                    }
+                }
+                rules.insert(nt.clone(), factored);
+                rules.insert(tail.clone(), suffixes);
+                order.push(tail);
+            }
+            i += 1;
+        }
+        self.with_rules(&order, &rules)
+    }
+    // removes useless symbols: first the non-terminals deriving no string
+    // of terminals along with every production using them, then whatever is
+    // left unreachable from the start; the start itself is always kept
+    pub fn reduce(&self) -> (Grammar, Vec<GrammarWarning>) {
+        let mut warnings = Vec::new();
+        let undefined = self.undefined();
+        let productive = self.productive();
+        let order: Vec<NonTerminal> = self.non_terminals().into_iter().cloned().collect();
+        for nt in &order {
+            if undefined.contains(nt) {
+                warnings.push(GrammarWarning::Undefined(nt.clone()));
+            } else if !productive.contains(nt) {
+                warnings.push(GrammarWarning::Unproductive(nt.clone()));
+            }
+        }
+        let useful = |symbol: &Symbol| match symbol {
+            Symbol::Terminal(_) => true,
+            Symbol::NonTerminal(nt) => productive.contains(nt),
+        };
+        let mut rules: HashMap<NonTerminal, Vec<Production>> = HashMap::new();
+        for nt in order.iter().filter(|nt| productive.contains(*nt)) {
+            let productions = self.productions(nt).iter();
+            let productions = productions.filter(|p| p.iter().all(useful)).cloned();
+            rules.insert(nt.clone(), productions.collect());
+        }
+        let order: Vec<_> = order
+            .into_iter()
+            .filter(|nt| productive.contains(nt) || *nt == self.start)
+            .collect();
+        let reachable = self.with_rules(&order, &rules).reachable();
+        for nt in order.iter().filter(|nt| !reachable.contains(*nt)) {
+            warnings.push(GrammarWarning::Unreachable(nt.clone()));
+        }
+        let order: Vec<_> = order
+            .into_iter()
+            .filter(|nt| reachable.contains(nt))
+            .collect();
+        let reduced = self.with_rules(&order, &rules);
+        let unused: HashSet<_> = self
+            .terminals
+            .iter()
+            .filter(|t| **t != Terminal::Epsilon && !reduced.terminals.contains(*t))
+            .chain(&reduced.unused_terminals())
+            .cloned()
+            .collect();
+        for t in sorted(unused) {
+            warnings.push(GrammarWarning::UnusedTerminal(t));
+        }
+        (reduced, warnings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{lexer::Token, parser::Terminal};
+
+    fn terminal(name: &str) -> Symbol {
+        Symbol::Terminal(Terminal::Token(Token::Identifier(name.to_string())))
+    }
+
+    #[test]
+    fn left_factoring_keeps_empty_productions_apart() {
+        let grammar: Grammar = "A => 'a' 'b' | 'a' 'c' | ε ;".parse().unwrap();
+        let (a, b, c) = (terminal("a"), terminal("b"), terminal("c"));
+        let mut rules = grammar.rules.clone();
+        // an empty right hand side, as a transformation may leave behind
+        rules.get_mut("A").unwrap().push(vec![]);
+        let grammar = grammar.with_rules(&["A".to_string()], &rules);
+        let factored = grammar.left_factor();
+        let tail = Symbol::NonTerminal("A'".to_string());
+        let epsilon = vec![Symbol::Terminal(Terminal::Epsilon)];
+        assert_eq!(factored.productions("A"), [vec![a, tail], epsilon, vec![]]);
+        assert_eq!(factored.productions("A'"), [vec![b], vec![c]]);
+    }
+
+    fn rules(grammar: &Grammar) -> String {
+        let text = grammar.to_string();
+        let lines = text
+            .lines()
+            .filter(|line| !line.starts_with('%') && !line.is_empty());
+        lines.map(str::trim).collect::<Vec<_>>().join("\n")
+    }
+
+    #[test]
+    fn left_recursion_becomes_right_recursion() {
+        let grammar: Grammar = "E => E '+' T | T ; T => T '*' 'x' | 'x' ;".parse().unwrap();
+        let expected =
+            "E => T E'\nE' => '+' T E'\n| %empty\nT => 'x' T'\nT' => '*' 'x' T'\n| %empty";
+        assert_eq!(rules(&grammar.eliminate_left_recursion()), expected);
+        // S reaches itself through A, so A's productions are substituted first
+        let grammar: Grammar = "S => A 'a' | 'b' ; A => S 'c' | 'd' ;".parse().unwrap();
+        let eliminated = grammar.eliminate_left_recursion();
+        let expected = "S => A 'a'\n| 'b'\nA => 'b' 'c' A'\n| 'd' A'\nA' => 'a' 'c' A'\n| %empty";
+        assert_eq!(rules(&eliminated), expected);
+        // a cycle A => A derives nothing new and is dropped
+        let grammar: Grammar = "A => A | 'a' ;".parse().unwrap();
+        assert_eq!(rules(&grammar.eliminate_left_recursion()), "A => 'a'");
+        let grammar: Grammar = "S => 'a' S | 'b' ;".parse().unwrap();
+        assert_eq!(grammar.eliminate_left_recursion().rules, grammar.rules);
+    }
+
+    #[test]
+    fn left_factoring_pulls_out_the_longest_prefix() {
+        let grammar: Grammar = "S => 'if' 'e' 'then' S | 'if' 'e' 'then' S 'else' S | 'x' ;"
+            .parse()
+            .unwrap();
+        let expected = "S => 'if' 'e' 'then' S S'\n| 'x'\nS' => %empty\n| 'else' S";
+        assert_eq!(rules(&grammar.left_factor()), expected);
+        // prefixes nested in the suffixes are factored again
+        let grammar: Grammar = "A => 'a' 'b' 'c' | 'a' 'b' 'd' | 'a' 'e' ;"
+            .parse()
+            .unwrap();
+        let expected = "A => 'a' A'\nA' => 'b' A''\n| 'e'\nA'' => 'c'\n| 'd'";
+        assert_eq!(rules(&grammar.left_factor()), expected);
+        let factored = grammar.left_factor();
+        assert!(factored.ll1_table().is_ok());
+        let grammar: Grammar = "A => 'a' | 'b' ;".parse().unwrap();
+        assert_eq!(grammar.left_factor().rules, grammar.rules);
+    }
+
+    #[test]
+    fn reduce_drops_useless_symbols_with_warnings() {
+        let grammar: Grammar = "
+            %left '+'
+            S => 'a' S | 'a' | B | E ;
+            B => B 'b' ;
+            C => 'c' | D ;
+            E => ε | F ;
+            F => E ;
+        "
+        .parse()
+        .unwrap();
+        let set = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<HashSet<_>>();
+        assert_eq!(grammar.productive(), set(&["S", "C", "E", "F"]));
+        assert_eq!(grammar.nullable(), set(&["S", "E", "F"]));
+        assert_eq!(grammar.reachable(), set(&["S", "B", "E", "F"]));
+        assert_eq!(grammar.undefined(), ["D"]);
+        let (reduced, warnings) = grammar.reduce();
+        let warnings: Vec<_> = warnings.iter().map(|w| w.to_string()).collect();
+        let expected = [
+            "B derives no string of terminals",
+            "D is used but has no rules",
+            "C is unreachable from the start",
+            "terminal '+' is never used",
+            "terminal 'b' is never used",
+            "terminal 'c' is never used",
+        ];
+        assert_eq!(warnings, expected);
+        assert_eq!(
+            rules(&reduced),
+            "S => 'a' S\n| 'a'\n| E\nE => %empty\n| F\nF => E"
+        );
+        // nothing derives from the start, which is kept with no rules
+        let (reduced, _) = "S => S 'a' ;".parse::<Grammar>().unwrap().reduce();
+        assert_eq!(reduced.non_terminals(), ["S"]);
+        assert!(reduced.productions("S").is_empty());
+    }
+}
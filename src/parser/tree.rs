@@ -0,0 +1,73 @@
+use std::{error, fmt};
+
+use super::{NonTerminal, Terminal};
+use crate::lexer::Token;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseTree {
+    Leaf(Token),
+    // a non-terminal that derived epsilon has no children
+    Node {
+        non_terminal: NonTerminal,
+        children: Vec<ParseTree>,
+    },
+}
+
+impl ParseTree {
+    fn write(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        match self {
+            ParseTree::Leaf(token) => writeln!(f, "{:indent$}{token}", "", indent = depth * 2),
+            ParseTree::Node {
+                non_terminal,
+                children,
+            } => {
+                writeln!(f, "{:indent$}{non_terminal}", "", indent = depth * 2)?;
+                for child in children {
+                    child.write(f, depth + 1)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, 0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    // index of the offending token in the input
+    pub position: usize,
+    pub found: Token,
+    pub expected: Vec<Terminal>,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "syntax error at token {}: found {}, expected ",
+            self.position,
+            Terminal::Token(self.found.clone())
+        )?;
+        match &self.expected[..] {
+            [] => write!(f, "nothing"),
+            [t] => write!(f, "{t}"),
+            expected => {
+                write!(f, "one of")?;
+                for (i, t) in expected.iter().enumerate() {
+                    match i {
+                        0 => write!(f, " {t}")?,
+                        _ => write!(f, ", {t}")?,
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl error::Error for SyntaxError {}
@@ -0,0 +1,1208 @@
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet, VecDeque},
+    error, fmt,
+    fs::File,
+    io::{self, BufRead, Cursor},
+    path::{Path, PathBuf},
+};
+
+use crate::lexer::{
+    Dialect, Integer, IntegerSuffix, LexError, LexErrorKind, Lexer, Span, Spanned, Token,
+};
+
+const MAX_INCLUDE_DEPTH: usize = 200;
+
+// headers shipped with rem, for the parts of the standard library the
+// system headers declare with compiler extensions
+pub const INCLUDE_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/include");
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PreprocessErrorKind {
+    Lex(LexErrorKind),
+    UnknownDirective(String),
+    MalformedDirective(String),
+    IncludeNotFound(String),
+    UnterminatedConditional,
+    UnmatchedConditional(String),
+    UnterminatedInvocation(String),
+    ArgumentCount {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    InvalidPaste(String),
+    InvalidExpression(String),
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct PreprocessError {
+    pub kind: PreprocessErrorKind,
+    pub file: String,
+    pub span: Span,
+}
+
+impl fmt::Display for PreprocessErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreprocessErrorKind::Lex(kind) => write!(f, "{kind}"),
+            PreprocessErrorKind::UnknownDirective(d) => write!(f, "unknown directive #{d}"),
+            PreprocessErrorKind::MalformedDirective(m) => write!(f, "{m}"),
+            PreprocessErrorKind::IncludeNotFound(p) => write!(f, "include file {p:?} not found"),
+            PreprocessErrorKind::UnterminatedConditional => write!(f, "unterminated conditional"),
+            PreprocessErrorKind::UnmatchedConditional(d) => write!(f, "#{d} without #if"),
+            PreprocessErrorKind::UnterminatedInvocation(m) => {
+                write!(f, "unterminated argument list invoking macro {m}")
+            }
+            PreprocessErrorKind::ArgumentCount {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "macro {name} expects {expected} arguments, but {found} were given"
+            ),
+            PreprocessErrorKind::InvalidPaste(t) => {
+                write!(f, "pasting does not give a valid token: {t}")
+            }
+            PreprocessErrorKind::InvalidExpression(e) => write!(f, "invalid #if expression: {e}"),
+            PreprocessErrorKind::Error(m) => write!(f, "#error {m}"),
+        }
+    }
+}
+
+impl fmt::Display for PreprocessError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.span, self.kind)
+    }
+}
+
+impl error::Error for PreprocessError {}
+
+// a token along with the names of the macros it was produced by, those
+// are not expanded again while rescanning it
+#[derive(Debug, Clone)]
+struct PpToken {
+    node: Token,
+    span: Span,
+    hide: HashSet<String>,
+}
+
+impl PpToken {
+    fn new(node: Token, span: Span) -> Self {
+        PpToken {
+            node,
+            span,
+            hide: HashSet::new(),
+        }
+    }
+}
+
+// stands for an empty macro argument next to `##`
+const PLACEMARKER: Token = Token::EOF;
+
+#[derive(Debug, Clone)]
+struct Macro {
+    params: Option<Vec<String>>,
+    variadic: bool,
+    body: Vec<PpToken>,
+    // where the body was written, expansions are reported against it
+    file: String,
+}
+
+#[derive(Debug)]
+struct Condition {
+    active: bool,
+    taken: bool,
+    else_seen: bool,
+    span: Span,
+}
+
+struct Source {
+    file: String,
+    tokens: Box<dyn Iterator<Item = Result<Spanned<Token>, LexError>>>,
+    conditions: Vec<Condition>,
+    done: bool,
+    origin: u32,
+}
+
+pub struct Preprocessor {
+    include_paths: Vec<PathBuf>,
+    macros: HashMap<String, Macro>,
+    dialect: Dialect,
+    sources: Vec<Source>,
+    pending: VecDeque<PpToken>,
+    contexts: Vec<VecDeque<PpToken>>,
+    errors: VecDeque<PreprocessError>,
+    at_line_start: bool,
+    // the last `Span::origin` handed out
+    origins: u32,
+    // the file the spans of each origin point into
+    files: HashMap<u32, String>,
+    // the line of the outermost invocation behind each expansion origin
+    invocations: HashMap<u32, usize>,
+}
+
+impl Default for Preprocessor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// macro names are identifiers, but keywords can be defined as well
+fn name(token: &Token) -> Option<String> {
+    match token {
+        Token::Identifier(name) => Some(name.clone()),
+        Token::Integer(_)
+        | Token::Float(_)
+        | Token::String(_)
+        | Token::Character(_)
+        | Token::Directive(_)
+        | Token::EOF => None,
+        token => {
+            let spelling = token.to_string();
+            spelling
+                .starts_with(|ch: char| ch.is_ascii_alphabetic() || ch == '_')
+                .then_some(spelling)
+        }
+    }
+}
+
+fn spell(tokens: &[PpToken]) -> String {
+    let mut text = String::new();
+    for (i, token) in tokens.iter().enumerate() {
+        if i > 0 && tokens[i - 1].span.end() < token.span.offset {
+            text.push(' ');
+        }
+        text += &token.node.to_string();
+    }
+    text
+}
+
+impl Preprocessor {
+    pub fn new() -> Self {
+        let mut preprocessor = Preprocessor {
+            include_paths: vec![],
+            macros: HashMap::new(),
+            dialect: Default::default(),
+            sources: vec![],
+            pending: VecDeque::new(),
+            contexts: vec![],
+            errors: VecDeque::new(),
+            at_line_start: true,
+            origins: 0,
+            files: HashMap::new(),
+            invocations: HashMap::new(),
+        };
+        preprocessor.predefine();
+        preprocessor
+    }
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self.predefine();
+        self
+    }
+    pub fn with_include_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.include_paths.push(path.into());
+        self
+    }
+    fn predefine(&mut self) {
+        self.define("__STDC__", "1");
+        self.define("__STDC_HOSTED__", "1");
+        match self.dialect {
+            Dialect::C89 => self.undefine("__STDC_VERSION__"),
+            Dialect::C99 => self.define("__STDC_VERSION__", "199901L"),
+            Dialect::C11 | Dialect::Rem => self.define("__STDC_VERSION__", "201112L"),
+        }
+    }
+    pub fn define(&mut self, name: &str, body: &str) {
+        let body = Lexer::from_source(body)
+            .with_dialect(self.dialect)
+            .filter_map(Result::ok)
+            .map(|token| PpToken::new(token.node, token.span))
+            .collect();
+        let m = Macro {
+            params: None,
+            variadic: false,
+            body,
+            file: "<command line>".to_string(),
+        };
+        self.macros.insert(name.to_string(), m);
+    }
+    pub fn undefine(&mut self, name: &str) {
+        self.macros.remove(name);
+    }
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.macros.contains_key(name) || name == "__LINE__" || name == "__FILE__"
+    }
+    pub fn push_file(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let path = path.as_ref();
+        let lexer = Lexer::from(File::open(path)?);
+        self.push_lexer(&path.display().to_string(), lexer);
+        Ok(())
+    }
+    // the file a span points into, for the spans of the tokens produced
+    pub fn file_of(&self, origin: u32) -> Option<&str> {
+        self.files.get(&origin).map(String::as_str)
+    }
+    pub fn push_source(&mut self, name: &str, source: impl Into<String>) {
+        let source = Cursor::new(source.into().into_bytes());
+        self.push_lexer(name, Lexer::new(source));
+    }
+    pub fn push_lexer<R: BufRead + 'static>(&mut self, name: &str, lexer: Lexer<R>) {
+        let tokens = lexer.with_dialect(self.dialect).with_newlines(true);
+        // the first file keeps the origin of plain lexer spans
+        let origin = match self.sources.is_empty() && self.origins == 0 {
+            true => 0,
+            false => self.origin(),
+        };
+        self.files.insert(origin, name.to_string());
+        self.sources.push(Source {
+            file: name.to_string(),
+            tokens: Box::new(tokens),
+            conditions: vec![],
+            done: false,
+            origin,
+        });
+        self.at_line_start = true;
+    }
+
+    fn origin(&mut self) -> u32 {
+        self.origins += 1;
+        self.origins
+    }
+    fn file(&self) -> String {
+        self.sources
+            .last()
+            .map(|source| source.file.clone())
+            .unwrap_or_default()
+    }
+    // the source line a token stands on, for expanded tokens that of the
+    // invocation they came from
+    fn line(&self, span: Span) -> usize {
+        self.invocations
+            .get(&span.origin)
+            .copied()
+            .unwrap_or(span.line)
+    }
+    fn error(&mut self, kind: PreprocessErrorKind, span: Span) {
+        let file = self.file();
+        self.errors.push_back(PreprocessError { kind, file, span });
+    }
+    fn skipping(&self) -> bool {
+        self.sources
+            .last()
+            .and_then(|source| source.conditions.last())
+            .is_some_and(|condition| !condition.active)
+    }
+
+    fn read(&mut self) -> Option<PpToken> {
+        if let Some(context) = self.contexts.last_mut() {
+            return context.pop_front();
+        }
+        if let Some(token) = self.pending.pop_front() {
+            return Some(token);
+        }
+        loop {
+            let source = self.sources.last_mut()?;
+            if source.done {
+                let source = self.sources.pop()?;
+                for condition in source.conditions {
+                    self.errors.push_back(PreprocessError {
+                        kind: PreprocessErrorKind::UnterminatedConditional,
+                        file: source.file.clone(),
+                        span: condition.span,
+                    });
+                }
+                continue;
+            }
+            let origin = source.origin;
+            match source.tokens.next() {
+                Some(Ok(token)) => {
+                    let span = Span {
+                        origin,
+                        ..token.span
+                    };
+                    return Some(PpToken::new(token.node, span));
+                }
+                Some(Err(e)) => {
+                    if !self.skipping() {
+                        let span = Span { origin, ..e.span };
+                        self.error(PreprocessErrorKind::Lex(e.kind), span);
+                    }
+                }
+                None => {
+                    // the last line of a file never continues into the includer
+                    source.done = true;
+                    let span = Span {
+                        origin,
+                        ..Default::default()
+                    };
+                    return Some(PpToken::new(Token::Newline, span));
+                }
+            }
+        }
+    }
+    fn unread(&mut self, tokens: Vec<PpToken>) {
+        let input = match self.contexts.last_mut() {
+            Some(context) => context,
+            None => &mut self.pending,
+        };
+        for token in tokens.into_iter().rev() {
+            input.push_front(token);
+        }
+    }
+
+    fn directive(&mut self) {
+        let mut line = vec![];
+        while let Some(token) = self.read() {
+            if token.node == Token::Newline {
+                break;
+            }
+            line.push(token);
+        }
+        self.at_line_start = true;
+        if line.is_empty() {
+            return;
+        }
+        let first = line.remove(0);
+        let Some(directive) = name(&first.node) else {
+            if !self.skipping() {
+                let message = format!("invalid preprocessing directive {}", first.node);
+                self.error(PreprocessErrorKind::MalformedDirective(message), first.span);
+            }
+            return;
+        };
+        let span = first.span;
+        match directive.as_str() {
+            "if" | "ifdef" | "ifndef" => self.open_condition(&directive, line, span),
+            "elif" => self.elif(line, span),
+            "else" => self.else_(span),
+            "endif" => {
+                let source = self.sources.last_mut();
+                if source.and_then(|source| source.conditions.pop()).is_none() {
+                    self.error(PreprocessErrorKind::UnmatchedConditional(directive), span);
+                }
+            }
+            _ if self.skipping() => {}
+            "define" => self.define_directive(line, span),
+            "undef" => match line.first().and_then(|token| name(&token.node)) {
+                Some(name) => self.undefine(&name),
+                None => {
+                    let message = "#undef expects a macro name".to_string();
+                    self.error(PreprocessErrorKind::MalformedDirective(message), span);
+                }
+            },
+            "include" => self.include(line, span),
+            "error" => self.error(PreprocessErrorKind::Error(spell(&line)), span),
+            "pragma" => {
+                let pragma = Token::Directive(format!("pragma {}", spell(&line)));
+                let newline = PpToken::new(Token::Newline, span);
+                self.unread(vec![PpToken::new(pragma, span), newline]);
+            }
+            _ => self.error(PreprocessErrorKind::UnknownDirective(directive), span),
+        }
+    }
+
+    fn open_condition(&mut self, directive: &str, line: Vec<PpToken>, span: Span) {
+        let condition = if self.skipping() {
+            // nothing inside a skipped group is ever taken
+            Condition {
+                active: false,
+                taken: true,
+                else_seen: false,
+                span,
+            }
+        } else {
+            let active = match directive {
+                "if" => self.evaluate(line, span),
+                _ => {
+                    let defined = match &line[..] {
+                        [token] => name(&token.node).map(|name| self.is_defined(&name)),
+                        _ => None,
+                    };
+                    let Some(defined) = defined else {
+                        let message = format!("#{directive} expects a macro name");
+                        self.error(PreprocessErrorKind::MalformedDirective(message), span);
+                        return self.open_condition("if", vec![], span);
+                    };
+                    defined == (directive == "ifdef")
+                }
+            };
+            Condition {
+                active,
+                taken: active,
+                else_seen: false,
+                span,
+            }
+        };
+        if let Some(source) = self.sources.last_mut() {
+            source.conditions.push(condition);
+        }
+    }
+    fn elif(&mut self, line: Vec<PpToken>, span: Span) {
+        let top = self.sources.last().and_then(|s| s.conditions.last());
+        let Some(&Condition {
+            taken, else_seen, ..
+        }) = top
+        else {
+            let kind = PreprocessErrorKind::UnmatchedConditional("elif".to_string());
+            return self.error(kind, span);
+        };
+        if else_seen {
+            let message = "#elif after #else".to_string();
+            self.error(PreprocessErrorKind::MalformedDirective(message), span);
+        }
+        let active = !taken && self.evaluate(line, span);
+        if let Some(condition) = self
+            .sources
+            .last_mut()
+            .and_then(|s| s.conditions.last_mut())
+        {
+            condition.active = active;
+            condition.taken = taken || active;
+        }
+    }
+    fn else_(&mut self, span: Span) {
+        let top = self
+            .sources
+            .last_mut()
+            .and_then(|s| s.conditions.last_mut());
+        let Some(condition) = top else {
+            let kind = PreprocessErrorKind::UnmatchedConditional("else".to_string());
+            return self.error(kind, span);
+        };
+        let repeated = condition.else_seen;
+        condition.else_seen = true;
+        condition.active = !condition.taken;
+        condition.taken = true;
+        if repeated {
+            let message = "#else after #else".to_string();
+            self.error(PreprocessErrorKind::MalformedDirective(message), span);
+        }
+    }
+
+    fn evaluate(&mut self, line: Vec<PpToken>, span: Span) -> bool {
+        // `defined` has to be resolved before the line is macro expanded
+        let mut tokens = vec![];
+        let mut line = line.into_iter();
+        while let Some(token) = line.next() {
+            if name(&token.node).as_deref() != Some("defined") {
+                tokens.push(token);
+                continue;
+            }
+            let mut operand = line.next();
+            let parenthesized = operand.as_ref().is_some_and(|t| t.node == Token::LeftParen);
+            if parenthesized {
+                operand = line.next();
+            }
+            let operand = operand.and_then(|t| name(&t.node));
+            if parenthesized && line.next().map(|t| t.node) != Some(Token::RightParen) {
+                let message = "missing ')' after \"defined\"".to_string();
+                self.error(PreprocessErrorKind::InvalidExpression(message), span);
+                return false;
+            }
+            let Some(operand) = operand else {
+                let message = "\"defined\" expects a macro name".to_string();
+                self.error(PreprocessErrorKind::InvalidExpression(message), span);
+                return false;
+            };
+            let value = self.is_defined(&operand) as u64;
+            tokens.push(PpToken::new(integer(value), token.span));
+        }
+        let tokens: Vec<Token> = self
+            .expand_list(tokens)
+            .into_iter()
+            .map(|token| match name(&token.node) {
+                // identifiers left after expansion evaluate to zero
+                Some(_) => integer(0),
+                None => token.node,
+            })
+            .collect();
+        let mut evaluator = Evaluator {
+            tokens: &tokens,
+            pos: 0,
+            skip: 0,
+        };
+        let value = evaluator
+            .expression()
+            .and_then(|value| match tokens.get(evaluator.pos) {
+                Some(token) => Err(format!("unexpected {token}")),
+                None => Ok(value),
+            });
+        match value {
+            Ok(value) => value.is_true(),
+            Err(message) => {
+                self.error(PreprocessErrorKind::InvalidExpression(message), span);
+                false
+            }
+        }
+    }
+
+    fn define_directive(&mut self, line: Vec<PpToken>, span: Span) {
+        let malformed = |message: &str| PreprocessErrorKind::MalformedDirective(message.into());
+        let mut line = line.into_iter().peekable();
+        let macro_name = line.next();
+        let Some(name_span) = macro_name.as_ref().map(|t| t.span) else {
+            return self.error(malformed("#define expects a macro name"), span);
+        };
+        let Some(macro_name) = macro_name.and_then(|t| name(&t.node)) else {
+            return self.error(malformed("#define expects a macro name"), span);
+        };
+        if macro_name == "defined" {
+            return self.error(
+                malformed("\"defined\" cannot be used as a macro name"),
+                span,
+            );
+        }
+        // only a parenthesis right after the name starts a parameter list
+        let function_like = line
+            .peek()
+            .is_some_and(|t| t.node == Token::LeftParen && t.span.offset == name_span.end());
+        let mut params = None;
+        let mut variadic = false;
+        if function_like {
+            line.next();
+            let mut names = vec![];
+            loop {
+                let param = line.next().map(|t| t.node);
+                match param {
+                    Some(Token::RightParen) if names.is_empty() => break,
+                    Some(Token::Ellipsis) => {
+                        variadic = true;
+                        names.push("__VA_ARGS__".to_string());
+                    }
+                    Some(ref token) if name(token).is_some() => names.extend(name(token)),
+                    _ => return self.error(malformed("expected parameter name"), span),
+                }
+                match line.next().map(|t| t.node) {
+                    Some(Token::Comma) if !variadic => {}
+                    Some(Token::RightParen) => break,
+                    _ => {
+                        return self.error(malformed("expected ',' or ')' in parameter list"), span)
+                    }
+                }
+            }
+            params = Some(names);
+        }
+        let body: Vec<PpToken> = line.collect();
+        if body.first().is_some_and(|t| t.node == Token::HashHash)
+            || body.last().is_some_and(|t| t.node == Token::HashHash)
+        {
+            let message = "'##' cannot appear at either end of a macro expansion";
+            return self.error(malformed(message), span);
+        }
+        if let Some(params) = &params {
+            let stringizes_param = |(i, token): (usize, &PpToken)| {
+                token.node != Token::Hash
+                    || body
+                        .get(i + 1)
+                        .and_then(|next| name(&next.node))
+                        .is_some_and(|next| params.contains(&next))
+            };
+            if !body.iter().enumerate().all(stringizes_param) {
+                return self.error(malformed("'#' is not followed by a macro parameter"), span);
+            }
+        }
+        let m = Macro {
+            params,
+            variadic,
+            body,
+            file: self.file(),
+        };
+        self.macros.insert(macro_name, m);
+    }
+
+    fn include(&mut self, line: Vec<PpToken>, span: Span) {
+        let line = match line.first().map(|t| &t.node) {
+            Some(Token::String(_) | Token::LessThan) => line,
+            _ => self.expand_list(line),
+        };
+        let target = match &line[..] {
+            [PpToken {
+                node: Token::String(path),
+                ..
+            }] => Some((String::from_utf8_lossy(path).into_owned(), false)),
+            [first, inner @ .., last]
+                if first.node == Token::LessThan && last.node == Token::GreaterThan =>
+            {
+                Some((spell(inner), true))
+            }
+            _ => None,
+        };
+        let Some((path, angled)) = target else {
+            let message = "#include expects \"FILE\" or <FILE>".to_string();
+            return self.error(PreprocessErrorKind::MalformedDirective(message), span);
+        };
+        if self.sources.len() >= MAX_INCLUDE_DEPTH {
+            let message = "#include nested too deeply".to_string();
+            return self.error(PreprocessErrorKind::MalformedDirective(message), span);
+        }
+        let mut candidates = vec![];
+        if !angled {
+            let file = self.file();
+            let dir = Path::new(&file).parent().unwrap_or(Path::new(""));
+            candidates.push(dir.join(&path));
+        }
+        candidates.extend(self.include_paths.iter().map(|dir| dir.join(&path)));
+        let found = candidates.into_iter().find(|candidate| candidate.is_file());
+        if found.is_none_or(|found| self.push_file(found).is_err()) {
+            self.error(PreprocessErrorKind::IncludeNotFound(path), span);
+        }
+    }
+
+    fn expand_list(&mut self, tokens: Vec<PpToken>) -> Vec<PpToken> {
+        self.contexts.push(tokens.into());
+        let mut expanded = vec![];
+        while let Some(token) = self.read() {
+            expanded.extend(self.expand(token));
+        }
+        self.contexts.pop();
+        expanded
+    }
+
+    // returns the token if it is not a macro invocation, otherwise the
+    // replacement is pushed back onto the input to be rescanned
+    fn expand(&mut self, token: PpToken) -> Option<PpToken> {
+        let Some(macro_name) = name(&token.node) else {
+            return Some(token);
+        };
+        if token.hide.contains(&macro_name) {
+            return Some(token);
+        }
+        let Some(m) = self.macros.get(&macro_name).cloned() else {
+            let node = match macro_name.as_str() {
+                "__LINE__" => integer(self.line(token.span) as u64),
+                "__FILE__" => Token::String(self.file().into_bytes()),
+                _ => return Some(token),
+            };
+            return Some(PpToken { node, ..token });
+        };
+        let mut hide = token.hide.clone();
+        let mut args = vec![];
+        if m.params.is_some() {
+            let mut skipped = vec![];
+            let paren = loop {
+                match self.read() {
+                    Some(t) if t.node == Token::Newline => skipped.push(t),
+                    next => break next,
+                }
+            };
+            if !paren.as_ref().is_some_and(|t| t.node == Token::LeftParen) {
+                // a function-like macro name without arguments is left alone
+                skipped.extend(paren);
+                self.unread(skipped);
+                return Some(token);
+            }
+            let close;
+            (args, close) = self.arguments(&macro_name, &m, token.span)?;
+            hide.retain(|name| close.hide.contains(name));
+        }
+        hide.insert(macro_name);
+        let origin = self.origin();
+        self.files.insert(origin, m.file.clone());
+        self.invocations.insert(origin, self.line(token.span));
+        let expansion = self.substitute(&m, &args, &hide, token.span, origin);
+        self.unread(expansion);
+        None
+    }
+
+    fn arguments(
+        &mut self,
+        macro_name: &str,
+        m: &Macro,
+        span: Span,
+    ) -> Option<(Vec<Vec<PpToken>>, PpToken)> {
+        let params = m.params.as_ref().map_or(0, Vec::len);
+        let mut args = vec![vec![]];
+        let mut depth = 0;
+        let close = loop {
+            let Some(token) = self.read() else {
+                let kind = PreprocessErrorKind::UnterminatedInvocation(macro_name.to_string());
+                self.error(kind, span);
+                return None;
+            };
+            match token.node {
+                Token::Newline => continue,
+                Token::LeftParen => depth += 1,
+                Token::RightParen if depth == 0 => break token,
+                Token::RightParen => depth -= 1,
+                // the variadic argument swallows the remaining commas
+                Token::Comma if depth == 0 && !(m.variadic && args.len() == params) => {
+                    args.push(vec![]);
+                    continue;
+                }
+                _ => {}
+            }
+            args.last_mut()?.push(token);
+        };
+        if params == 0 && args.len() == 1 && args[0].is_empty() {
+            args.clear();
+        }
+        if m.variadic && args.len() + 1 == params {
+            args.push(vec![]);
+        }
+        if args.len() != params {
+            let kind = PreprocessErrorKind::ArgumentCount {
+                name: macro_name.to_string(),
+                expected: params,
+                found: args.len(),
+            };
+            self.error(kind, span);
+            return None;
+        }
+        Some((args, close))
+    }
+
+    fn substitute(
+        &mut self,
+        m: &Macro,
+        args: &[Vec<PpToken>],
+        hide: &HashSet<String>,
+        span: Span,
+        origin: u32,
+    ) -> Vec<PpToken> {
+        // tokens of the body keep their place in the definition, marked as
+        // coming from this expansion; `span` is the invocation's, for errors
+        let at = |token: &PpToken| Span {
+            origin,
+            ..token.span
+        };
+        let param = |token: &PpToken| {
+            let name = name(&token.node)?;
+            m.params.as_ref()?.iter().position(|param| *param == name)
+        };
+        let mut expansion: Vec<PpToken> = vec![];
+        let mut i = 0;
+        while i < m.body.len() {
+            let token = &m.body[i];
+            let next = m.body.get(i + 1);
+            match (&token.node, next.and_then(param)) {
+                (Token::Hash, Some(p)) if m.params.is_some() => {
+                    let string = Token::String(spell(&args[p]).into_bytes());
+                    let hash = at(token).to(next.map_or(span, at));
+                    expansion.push(PpToken::new(string, hash));
+                    i += 2;
+                    continue;
+                }
+                (Token::HashHash, p) => {
+                    let rhs = match p {
+                        Some(p) => args[p].clone(),
+                        None => next.into_iter().cloned().collect(),
+                    };
+                    let rhs = rhs.into_iter().map(|t| match p {
+                        Some(_) => t,
+                        None => PpToken { span: at(&t), ..t },
+                    });
+                    self.paste(&mut expansion, rhs.collect(), span);
+                    i += 2;
+                    continue;
+                }
+                _ => {}
+            }
+            match param(token) {
+                // operands of `##` are not expanded
+                Some(p) if next.is_some_and(|next| next.node == Token::HashHash) => {
+                    if args[p].is_empty() {
+                        expansion.push(PpToken::new(PLACEMARKER, at(token)));
+                    }
+                    expansion.extend(args[p].iter().cloned());
+                }
+                Some(p) => expansion.extend(self.expand_list(args[p].clone())),
+                None => expansion.push(PpToken {
+                    span: at(token),
+                    ..token.clone()
+                }),
+            }
+            i += 1;
+        }
+        expansion.retain(|token| token.node != PLACEMARKER);
+        for token in &mut expansion {
+            token.hide.extend(hide.iter().cloned());
+        }
+        expansion
+    }
+
+    fn paste(&mut self, expansion: &mut Vec<PpToken>, rhs: Vec<PpToken>, span: Span) {
+        let mut rhs = rhs.into_iter();
+        let Some(first) = rhs.next() else {
+            return;
+        };
+        match expansion.pop() {
+            Some(lhs) if lhs.node != PLACEMARKER => {
+                let text = format!("{}{}", lhs.node, first.node);
+                let mut tokens = Lexer::from_source(&text).with_dialect(self.dialect);
+                match (tokens.next(), tokens.next()) {
+                    (Some(Ok(token)), None) => expansion.push(PpToken {
+                        node: token.node,
+                        span: lhs.span.to(first.span),
+                        hide: lhs.hide,
+                    }),
+                    _ => {
+                        self.error(PreprocessErrorKind::InvalidPaste(text), span);
+                        expansion.extend([lhs, first]);
+                    }
+                }
+            }
+            _ => expansion.push(first),
+        }
+        expansion.extend(rhs);
+    }
+}
+
+fn integer(value: u64) -> Token {
+    Token::Integer(Integer {
+        value,
+        radix: 10,
+        suffix: IntegerSuffix::None,
+    })
+}
+
+// a value of `#if` arithmetic, which is done in intmax_t or uintmax_t
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Value {
+    bits: u64,
+    unsigned: bool,
+}
+
+impl Value {
+    fn signed(value: i64) -> Self {
+        Value {
+            bits: value as u64,
+            unsigned: false,
+        }
+    }
+    fn truth(value: bool) -> Self {
+        Value::signed(value as i64)
+    }
+    fn is_true(self) -> bool {
+        self.bits != 0
+    }
+}
+
+// constant expressions of `#if` and `#elif`
+struct Evaluator<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    // inside the unevaluated operand of `&&`, `||` or `?:`
+    skip: usize,
+}
+
+impl Evaluator<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn expect(&mut self, token: Token) -> Result<(), String> {
+        match self.peek() {
+            Some(t) if *t == token => {
+                self.pos += 1;
+                Ok(())
+            }
+            Some(t) => Err(format!("expected {token}, found {t}")),
+            None => Err(format!("expected {token}")),
+        }
+    }
+    fn expression(&mut self) -> Result<Value, String> {
+        let condition = self.binary(1)?;
+        if self.peek() != Some(&Token::Question) {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        let taken = condition.is_true();
+        self.skip += !taken as usize;
+        let then = self.expression();
+        self.skip -= !taken as usize;
+        self.expect(Token::Colon)?;
+        self.skip += taken as usize;
+        let otherwise = self.expression();
+        self.skip -= taken as usize;
+        let (then, otherwise) = (then?, otherwise?);
+        // the result has the common type of both operands
+        let value = if taken { then } else { otherwise };
+        Ok(Value {
+            unsigned: then.unsigned || otherwise.unsigned,
+            ..value
+        })
+    }
+    fn precedence(token: &Token) -> Option<usize> {
+        Some(match token {
+            Token::Or => 1,
+            Token::And => 2,
+            Token::BitOr => 3,
+            Token::Xor => 4,
+            Token::BitAnd => 5,
+            Token::Equal | Token::NotEqual => 6,
+            Token::LessThan
+            | Token::LessThanEqual
+            | Token::GreaterThan
+            | Token::GreaterThanEqual => 7,
+            Token::ShiftLeft | Token::ShiftRight => 8,
+            Token::Plus | Token::Minus => 9,
+            Token::Star | Token::Div | Token::Mod => 10,
+            _ => return None,
+        })
+    }
+    fn binary(&mut self, min: usize) -> Result<Value, String> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.peek().cloned() {
+            let Some(precedence) = Self::precedence(&op).filter(|&p| p >= min) else {
+                break;
+            };
+            self.pos += 1;
+            let short_circuit = match op {
+                Token::And => !lhs.is_true(),
+                Token::Or => lhs.is_true(),
+                _ => false,
+            };
+            self.skip += short_circuit as usize;
+            let rhs = self.binary(precedence + 1);
+            self.skip -= short_circuit as usize;
+            lhs = self.apply(&op, lhs, rhs?)?;
+        }
+        Ok(lhs)
+    }
+    // the usual arithmetic conversions make the operation unsigned when
+    // either operand is; shifts keep the type of their left operand
+    fn apply(&self, op: &Token, lhs: Value, rhs: Value) -> Result<Value, String> {
+        let unsigned = lhs.unsigned || rhs.unsigned;
+        let (a, b) = (lhs.bits, rhs.bits);
+        let (x, y) = (a as i64, b as i64);
+        let compare = |ordering: Ordering| match unsigned {
+            true => a.cmp(&b) == ordering,
+            false => x.cmp(&y) == ordering,
+        };
+        let bits = match op {
+            Token::Or => return Ok(Value::truth(lhs.is_true() || rhs.is_true())),
+            Token::And => return Ok(Value::truth(lhs.is_true() && rhs.is_true())),
+            Token::Equal => return Ok(Value::truth(a == b)),
+            Token::NotEqual => return Ok(Value::truth(a != b)),
+            Token::LessThan => return Ok(Value::truth(compare(Ordering::Less))),
+            Token::GreaterThan => return Ok(Value::truth(compare(Ordering::Greater))),
+            Token::LessThanEqual => return Ok(Value::truth(!compare(Ordering::Greater))),
+            Token::GreaterThanEqual => return Ok(Value::truth(!compare(Ordering::Less))),
+            Token::ShiftLeft => {
+                let bits = a.wrapping_shl(b as u32);
+                return Ok(Value { bits, ..lhs });
+            }
+            Token::ShiftRight => {
+                let bits = match lhs.unsigned {
+                    true => a.wrapping_shr(b as u32),
+                    false => x.wrapping_shr(b as u32) as u64,
+                };
+                return Ok(Value { bits, ..lhs });
+            }
+            Token::BitOr => a | b,
+            Token::Xor => a ^ b,
+            Token::BitAnd => a & b,
+            Token::Plus => a.wrapping_add(b),
+            Token::Minus => a.wrapping_sub(b),
+            Token::Star => a.wrapping_mul(b),
+            Token::Div | Token::Mod if b == 0 => match self.skip {
+                0 => return Err("division by zero".to_string()),
+                _ => 0,
+            },
+            Token::Div if unsigned => a / b,
+            Token::Div => x.wrapping_div(y) as u64,
+            _ if unsigned => a % b,
+            _ => x.wrapping_rem(y) as u64,
+        };
+        Ok(Value { bits, unsigned })
+    }
+    fn unary(&mut self) -> Result<Value, String> {
+        let Some(token) = self.peek().cloned() else {
+            return Err("expected an expression".to_string());
+        };
+        self.pos += 1;
+        match token {
+            Token::Plus => self.unary(),
+            Token::Minus => {
+                let value = self.unary()?;
+                let bits = value.bits.wrapping_neg();
+                Ok(Value { bits, ..value })
+            }
+            Token::Not => Ok(Value::truth(!self.unary()?.is_true())),
+            Token::BitNot => {
+                let value = self.unary()?;
+                Ok(Value {
+                    bits: !value.bits,
+                    ..value
+                })
+            }
+            Token::LeftParen => {
+                let value = self.expression()?;
+                self.expect(Token::RightParen)?;
+                Ok(value)
+            }
+            // a literal too large for intmax_t can only be a uintmax_t
+            Token::Integer(i) => {
+                let suffixed = matches!(
+                    i.suffix,
+                    IntegerSuffix::Unsigned
+                        | IntegerSuffix::UnsignedLong
+                        | IntegerSuffix::UnsignedLongLong
+                );
+                Ok(Value {
+                    bits: i.value,
+                    unsigned: suffixed || i.value > i64::MAX as u64,
+                })
+            }
+            // plain char is signed
+            Token::Character(ch) => Ok(Value::signed(ch as i64)),
+            token => Err(format!("unexpected {token}")),
+        }
+    }
+}
+
+impl Iterator for Preprocessor {
+    type Item = Result<Spanned<Token>, PreprocessError>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(error) = self.errors.pop_front() {
+                return Some(Err(error));
+            }
+            let Some(token) = self.read() else {
+                return self.errors.pop_front().map(Err);
+            };
+            if token.node == Token::Newline {
+                self.at_line_start = true;
+                continue;
+            }
+            let line_start = std::mem::replace(&mut self.at_line_start, false);
+            // tokens produced by macros never start a directive
+            if token.node == Token::Hash && line_start && token.hide.is_empty() {
+                self.directive();
+                continue;
+            }
+            if self.skipping() {
+                continue;
+            }
+            if let Some(token) = self.expand(token) {
+                return Some(Ok(Spanned {
+                    node: token.node,
+                    span: token.span,
+                }));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+
+    // the expanded tokens of a source, separated by spaces
+    fn expand(source: &str) -> String {
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.push_source("test.c", source);
+        let tokens: Vec<String> = preprocessor
+            .map(|token| token.unwrap().node.to_string())
+            .collect();
+        tokens.join(" ")
+    }
+
+    fn errors(source: &str) -> Vec<PreprocessErrorKind> {
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.push_source("test.c", source);
+        preprocessor
+            .filter_map(|token| token.err().map(|e| e.kind))
+            .collect()
+    }
+
+    #[test]
+    fn hide_sets_stop_recursion() {
+        assert_eq!(expand("#define x x + 1\nx"), "x + 1");
+        assert_eq!(expand("#define a b\n#define b a\na b"), "a b");
+        // f is painted blue inside its own expansion, but not after it
+        let source = "#define f(a) a*g\n#define g(a) f(a)\nf(2)(9)";
+        assert_eq!(expand(source), "2 * 9 * g");
+    }
+
+    #[test]
+    fn stringizing_and_pasting() {
+        let source = "#define str(x) #x\nstr(a  +  \"b\\n\")";
+        assert_eq!(expand(source), r#""a + \"b\\n\"""#);
+        assert_eq!(
+            expand("#define cat(a, b) a ## b\ncat(x, 1) cat(+, =)"),
+            "x1 +="
+        );
+        // operands of ## are not expanded first
+        assert_eq!(
+            expand("#define N 2\n#define cat(a, b) a ## b\ncat(N, N)"),
+            "NN"
+        );
+        assert_eq!(
+            errors("#define cat(a, b) a ## b\ncat(+, -)"),
+            [PreprocessErrorKind::InvalidPaste("+-".to_string())]
+        );
+    }
+
+    #[test]
+    fn empty_arguments_become_placemarkers() {
+        let source = "#define cat(a, b) [a ## b]\ncat(, y) cat(x, ) cat(, )";
+        assert_eq!(expand(source), "[ y ] [ x ] [ ]");
+        let source = "#define f(fmt, ...) g(fmt, __VA_ARGS__)\nf(1) f(1, 2, 3)";
+        assert_eq!(expand(source), "g ( 1 , ) g ( 1 , 2 , 3 )");
+    }
+
+    #[test]
+    fn line_is_that_of_the_outermost_invocation() {
+        let source = "#define L __LINE__\n#define F(x) x + L\n\nL\nF(__LINE__)\n__LINE__";
+        assert_eq!(expand(source), "4 5 + 5 6");
+        assert_eq!(expand("__FILE__"), "\"test.c\"");
+    }
+
+    #[test]
+    fn conditionals_use_intmax_arithmetic() {
+        let holds = |condition: &str| expand(&format!("#if {condition}\nyes\n#endif")) == "yes";
+        assert!(holds("0xFFFFFFFFFFFFFFFF > 0"));
+        assert!(!holds("-1 < 0u"));
+        assert!(holds("-1 < 0"));
+        assert!(holds("(0 ? 1u : -1) > 0"));
+        assert!(holds("-1 >> 1 == -1"));
+        assert!(holds("-7 / 2 == -3 && -7 % 2 == -1"));
+        assert!(holds("1 || 1 / 0") && !holds("0 && 1 / 0"));
+        assert!(holds("defined(__STDC__) && !defined __nothing__"));
+        assert!(holds("__STDC_VERSION__ >= 201112L"));
+        assert!(!holds("undefined_name"));
+        assert_eq!(
+            errors("#if 1 / 0\n#endif"),
+            [PreprocessErrorKind::InvalidExpression(
+                "division by zero".to_string()
+            )]
+        );
+        let source = "#if 0\na\n#elif 1\nb\n#else\nc\n#endif";
+        assert_eq!(expand(source), "b");
+    }
+
+    #[test]
+    fn includes_search_the_includer_then_the_paths() {
+        let dir = env::temp_dir().join(format!("rem-preprocessor-{}", std::process::id()));
+        let (local, system) = (dir.join("local"), dir.join("system"));
+        fs::create_dir_all(&local).unwrap();
+        fs::create_dir_all(&system).unwrap();
+        fs::write(local.join("a.h"), "local_a\n").unwrap();
+        fs::write(system.join("a.h"), "system_a\n").unwrap();
+        fs::write(system.join("b.h"), "#include \"a.h\"\nsystem_b\n").unwrap();
+        fs::write(
+            local.join("main.c"),
+            "#include \"a.h\"\n#include <a.h>\n#include \"b.h\"\n",
+        )
+        .unwrap();
+        let mut preprocessor = Preprocessor::new().with_include_path(&system);
+        preprocessor.push_file(local.join("main.c")).unwrap();
+        let tokens: Vec<String> = preprocessor
+            .map(|token| token.unwrap().node.to_string())
+            .collect();
+        assert_eq!(tokens, ["local_a", "system_a", "system_a", "system_b"]);
+        assert_eq!(
+            errors("#include \"nowhere.h\""),
+            [PreprocessErrorKind::IncludeNotFound(
+                "nowhere.h".to_string()
+            )]
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
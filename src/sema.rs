@@ -0,0 +1,711 @@
+use std::{collections::HashMap, error, fmt};
+
+use crate::{
+    ast::{
+        Block, BlockItem, Declaration, DeclarationSpecifiers, Declarator, DerivedDeclarator,
+        Designator, Expr, ExprKind, ExternalDeclaration, ForInit, FunctionDefinition, Initializer,
+        StaticAssert, Stmt, StmtKind, StorageClass, TranslationUnit, TypeName, TypeSpecifier,
+    },
+    lexer::{Span, Spanned},
+    types::{IntegerKind, QualifiedType, Type, TypeEnv, TypeError, TypeErrorKind},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum SemaErrorKind {
+    Undeclared(String),
+    NotAValue(String),
+    Redefinition { name: String, previous: Span },
+    ConflictingTypes { name: String, previous: Span },
+    UndefinedLabel(String),
+    DuplicateLabel { name: String, previous: Span },
+    Type(TypeErrorKind),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SemaError {
+    pub kind: SemaErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for SemaErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SemaErrorKind::Undeclared(name) => write!(f, "use of undeclared identifier '{name}'"),
+            SemaErrorKind::NotAValue(name) => write!(f, "'{name}' is a type, not a value"),
+            SemaErrorKind::Redefinition { name, previous } => {
+                write!(
+                    f,
+                    "redefinition of '{name}', previously declared at {previous}"
+                )
+            }
+            SemaErrorKind::ConflictingTypes { name, previous } => {
+                write!(
+                    f,
+                    "conflicting types for '{name}', previously declared at {previous}"
+                )
+            }
+            SemaErrorKind::UndefinedLabel(name) => write!(f, "use of undeclared label '{name}'"),
+            SemaErrorKind::DuplicateLabel { name, previous } => {
+                write!(
+                    f,
+                    "redefinition of label '{name}', previously defined at {previous}"
+                )
+            }
+            SemaErrorKind::Type(kind) => write!(f, "{kind}"),
+        }
+    }
+}
+
+impl fmt::Display for SemaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.kind)
+    }
+}
+
+impl error::Error for SemaError {}
+
+impl From<TypeError> for SemaError {
+    fn from(e: TypeError) -> Self {
+        SemaError {
+            kind: SemaErrorKind::Type(e.kind),
+            span: e.span,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Object,
+    Function,
+    Parameter,
+    Typedef,
+    Constant(i64),
+}
+
+// an entity of the ordinary namespace; redeclarations share the symbol of
+// the first declaration
+#[derive(Debug, Clone, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub ty: QualifiedType,
+    // the first declaration
+    pub span: Span,
+    // has a body or an initializer
+    pub defined: bool,
+    // 0 at file scope
+    pub depth: usize,
+}
+
+// what the analysis found; uses and declarations are keyed by the span of
+// the identifier
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    pub symbols: Vec<Symbol>,
+    pub uses: HashMap<Span, usize>,
+    pub declarations: HashMap<Span, usize>,
+    // the types of casts and `sizeof(type)`, by the span of the type name
+    pub type_names: HashMap<Span, QualifiedType>,
+    // owns every struct, union and enum of the translation unit
+    pub env: TypeEnv,
+}
+
+impl Analysis {
+    pub fn symbol(&self, id: usize) -> &Symbol {
+        &self.symbols[id]
+    }
+    // the symbol an identifier expression or declarator name refers to
+    pub fn resolve(&self, span: Span) -> Option<&Symbol> {
+        let id = self
+            .uses
+            .get(&span)
+            .or_else(|| self.declarations.get(&span))?;
+        Some(&self.symbols[*id])
+    }
+}
+
+// enumerators declared by the specifiers, inside struct members included
+fn enumerators(specifiers: &DeclarationSpecifiers) -> Vec<&Spanned<String>> {
+    let mut names = Vec::new();
+    for specifier in &specifiers.types {
+        match &specifier.node {
+            TypeSpecifier::Enum(enumeration) => {
+                let enumerators = enumeration.enumerators.iter().flatten();
+                names.extend(enumerators.map(|e| &e.name));
+            }
+            TypeSpecifier::Record(record) => {
+                for member in record.members.iter().flatten() {
+                    names.extend(enumerators(&member.specifiers));
+                }
+            }
+            _ => {}
+        }
+    }
+    names
+}
+
+struct Sema {
+    analysis: Analysis,
+    // ordinary identifiers of each open scope
+    scopes: Vec<HashMap<String, usize>>,
+    // labels of the current function, which are in scope all through it
+    labels: HashMap<String, Span>,
+    errors: Vec<SemaError>,
+}
+
+impl Sema {
+    fn error(&mut self, kind: SemaErrorKind, span: Span) {
+        self.errors.push(SemaError { kind, span });
+    }
+    fn push(&mut self) {
+        self.scopes.push(HashMap::new());
+        self.analysis.env.push();
+    }
+    fn pop(&mut self) {
+        self.scopes.pop();
+        self.analysis.env.pop();
+    }
+    fn lookup(&self, name: &str) -> Option<usize> {
+        let mut scopes = self.scopes.iter().rev();
+        scopes.find_map(|scope| scope.get(name)).copied()
+    }
+    // `linked` is set for declarations that may be repeated in one scope,
+    // those at file scope and those declared `extern`
+    fn declare(
+        &mut self,
+        name: &Spanned<String>,
+        kind: SymbolKind,
+        ty: QualifiedType,
+        defined: bool,
+        linked: bool,
+    ) -> usize {
+        let env = &mut self.analysis.env;
+        match kind {
+            SymbolKind::Typedef => env.define_typedef(name.node.clone(), ty.clone()),
+            // the environment already holds the value
+            SymbolKind::Constant(_) => {}
+            _ => env.define_object(name.node.clone(), ty.clone()),
+        }
+        let scope = self.scopes.len() - 1;
+        if let Some(&id) = self.scopes[scope].get(&name.node) {
+            self.analysis.declarations.insert(name.span, id);
+            let previous = &self.analysis.symbols[id];
+            let redeclarable = match (previous.kind, kind) {
+                (SymbolKind::Function, SymbolKind::Function) => true,
+                (SymbolKind::Object, SymbolKind::Object) => linked,
+                (SymbolKind::Typedef, SymbolKind::Typedef) => previous.ty == ty,
+                _ => false,
+            };
+            let (previous, was_defined) = (previous.span, previous.defined);
+            let node = name.node.clone();
+            let error = if !redeclarable || (defined && was_defined) {
+                SemaErrorKind::Redefinition {
+                    name: node,
+                    previous,
+                }
+            } else if !self.analysis.symbols[id].ty.is_compatible(&ty) {
+                SemaErrorKind::ConflictingTypes {
+                    name: node,
+                    previous,
+                }
+            } else {
+                // the later declaration may complete the type
+                let symbol = &mut self.analysis.symbols[id];
+                symbol.defined |= defined;
+                if defined || matches!(symbol.ty.ty, Type::Array(_, None)) {
+                    symbol.ty = ty;
+                }
+                if kind != SymbolKind::Typedef {
+                    let ty = symbol.ty.clone();
+                    self.analysis.env.define_object(name.node.clone(), ty);
+                }
+                return id;
+            };
+            self.error(error, name.span);
+            return id;
+        }
+        self.analysis.symbols.push(Symbol {
+            name: name.node.clone(),
+            kind,
+            ty,
+            span: name.span,
+            defined,
+            depth: scope,
+        });
+        let id = self.analysis.symbols.len() - 1;
+        self.scopes[scope].insert(name.node.clone(), id);
+        self.analysis.declarations.insert(name.span, id);
+        id
+    }
+    fn declare_enumerators(&mut self, specifiers: &DeclarationSpecifiers) {
+        let int = QualifiedType::from(Type::Integer(IntegerKind::Int));
+        for name in enumerators(specifiers) {
+            let value = self.analysis.env.constant(&name.node).unwrap_or(0);
+            let kind = SymbolKind::Constant(value);
+            self.declare(name, kind, int.clone(), true, false);
+        }
+    }
+    fn kind_of(ty: &QualifiedType, storage: Option<StorageClass>) -> SymbolKind {
+        match (storage, &ty.ty) {
+            (Some(StorageClass::Typedef), _) => SymbolKind::Typedef,
+            (_, Type::Function(_)) => SymbolKind::Function,
+            _ => SymbolKind::Object,
+        }
+    }
+
+    fn declaration(&mut self, declaration: &Declaration) {
+        let specifiers = &declaration.specifiers;
+        if declaration.declarators.is_empty() {
+            // tags and enumerators only, `struct S;` included
+            match self.analysis.env.declare(declaration) {
+                Ok(_) => self.declare_enumerators(specifiers),
+                Err(e) => self.errors.push(e.into()),
+            }
+            return;
+        }
+        let base = match self.analysis.env.specified(specifiers) {
+            Ok(base) => base,
+            Err(e) => return self.errors.push(e.into()),
+        };
+        self.declare_enumerators(specifiers);
+        let linked = self.scopes.len() == 1 || specifiers.storage == Some(StorageClass::Extern);
+        for init in &declaration.declarators {
+            let ty = match self.analysis.env.declared(&base, &init.declarator) {
+                Ok(ty) => ty,
+                Err(e) => {
+                    self.errors.push(e.into());
+                    continue;
+                }
+            };
+            if let Err(e) = self.analysis.env.alignment(specifiers, &ty) {
+                self.errors.push(e.into());
+            }
+            // in scope from the end of its declarator, initializer included
+            if let Some(name) = &init.declarator.name {
+                let kind = Sema::kind_of(&ty, specifiers.storage);
+                let defined = init.initializer.is_some();
+                self.declare(name, kind, ty, defined, linked);
+            }
+            if let Some(initializer) = &init.initializer {
+                self.initializer(initializer);
+            }
+        }
+    }
+    fn initializer(&mut self, initializer: &Initializer) {
+        match initializer {
+            Initializer::Expression(expr) => self.expression(expr),
+            Initializer::List { items, .. } => {
+                for (designators, initializer) in items {
+                    for designator in designators {
+                        if let Designator::Index(index) = designator {
+                            self.expression(index);
+                        }
+                    }
+                    self.initializer(initializer);
+                }
+            }
+        }
+    }
+    fn function(&mut self, function: &FunctionDefinition) {
+        let ty = self
+            .analysis
+            .env
+            .specified(&function.specifiers)
+            .and_then(|base| self.analysis.env.declared(&base, &function.declarator));
+        let ty = match ty {
+            Ok(ty) => ty,
+            Err(e) => return self.errors.push(e.into()),
+        };
+        if let Err(e) = self.analysis.env.alignment(&function.specifiers, &ty) {
+            self.errors.push(e.into());
+        }
+        if let Some(name) = &function.declarator.name {
+            self.declare(name, SymbolKind::Function, ty.clone(), true, true);
+        }
+        // parameters share the scope of the body's outermost block
+        self.push();
+        self.parameters(&function.declarator, &ty);
+        self.labels.clear();
+        for item in &function.body.items {
+            if let BlockItem::Statement(stmt) = item {
+                self.collect_labels(stmt);
+            }
+        }
+        self.items(&function.body);
+        self.pop();
+    }
+    fn parameters(&mut self, declarator: &Declarator, ty: &QualifiedType) {
+        let (Some(DerivedDeclarator::Function { parameters, .. }), Type::Function(function)) =
+            (declarator.derived.first(), &ty.ty)
+        else {
+            return;
+        };
+        // `(void)` declares no parameters and has no types
+        for (i, parameter) in parameters.iter().enumerate() {
+            let (Some(name), Some(ty)) = (&parameter.declarator.name, function.parameters.get(i))
+            else {
+                continue;
+            };
+            self.declare(name, SymbolKind::Parameter, ty.clone(), true, false);
+        }
+    }
+    fn collect_labels(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Labeled { label, body } => {
+                match self.labels.get(&label.node) {
+                    Some(&previous) => self.error(
+                        SemaErrorKind::DuplicateLabel {
+                            name: label.node.clone(),
+                            previous,
+                        },
+                        label.span,
+                    ),
+                    None => {
+                        self.labels.insert(label.node.clone(), label.span);
+                    }
+                }
+                self.collect_labels(body);
+            }
+            StmtKind::Compound(block) => {
+                for item in &block.items {
+                    if let BlockItem::Statement(stmt) = item {
+                        self.collect_labels(stmt);
+                    }
+                }
+            }
+            StmtKind::If {
+                then, otherwise, ..
+            } => {
+                self.collect_labels(then);
+                if let Some(otherwise) = otherwise {
+                    self.collect_labels(otherwise);
+                }
+            }
+            StmtKind::While { body, .. }
+            | StmtKind::DoWhile { body, .. }
+            | StmtKind::For { body, .. }
+            | StmtKind::Switch { body, .. }
+            | StmtKind::Case { body, .. }
+            | StmtKind::Default(body) => self.collect_labels(body),
+            _ => {}
+        }
+    }
+    fn items(&mut self, block: &Block) {
+        for item in &block.items {
+            match item {
+                BlockItem::Declaration(declaration) => self.declaration(declaration),
+                BlockItem::Statement(stmt) => self.statement(stmt),
+                BlockItem::StaticAssert(assertion) => self.static_assert(assertion),
+            }
+        }
+    }
+    // the condition is only evaluated once its names resolve
+    fn static_assert(&mut self, assertion: &StaticAssert) {
+        let errors = self.errors.len();
+        self.expression(&assertion.condition);
+        if self.errors.len() == errors {
+            if let Err(e) = self.analysis.env.static_assert(assertion) {
+                self.errors.push(e.into());
+            }
+        }
+    }
+    fn statement(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Compound(block) => {
+                self.push();
+                self.items(block);
+                self.pop();
+            }
+            StmtKind::Expression(expr) | StmtKind::Return(expr) => {
+                if let Some(expr) = expr {
+                    self.expression(expr);
+                }
+            }
+            StmtKind::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.expression(condition);
+                self.statement(then);
+                if let Some(otherwise) = otherwise {
+                    self.statement(otherwise);
+                }
+            }
+            StmtKind::While { condition, body }
+            | StmtKind::DoWhile { body, condition }
+            | StmtKind::Switch { condition, body }
+            | StmtKind::Case {
+                value: condition,
+                body,
+            } => {
+                self.expression(condition);
+                self.statement(body);
+            }
+            StmtKind::For {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                self.push();
+                match init.as_deref() {
+                    Some(ForInit::Declaration(declaration)) => self.declaration(declaration),
+                    Some(ForInit::Expression(expr)) => self.expression(expr),
+                    None => {}
+                }
+                for expr in [condition, step].into_iter().flatten() {
+                    self.expression(expr);
+                }
+                self.statement(body);
+                self.pop();
+            }
+            StmtKind::Default(body) | StmtKind::Labeled { body, .. } => self.statement(body),
+            StmtKind::Goto(label) => {
+                if !self.labels.contains_key(&label.node) {
+                    let kind = SemaErrorKind::UndefinedLabel(label.node.clone());
+                    self.error(kind, label.span);
+                }
+            }
+            StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+    fn type_name(&mut self, name: &TypeName) {
+        match self.analysis.env.type_name(name) {
+            Ok(ty) => {
+                self.analysis.type_names.insert(name.span, ty);
+            }
+            Err(e) => self.errors.push(e.into()),
+        }
+    }
+    fn expression(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Identifier(name) => match self.lookup(name) {
+                Some(id) if self.analysis.symbols[id].kind == SymbolKind::Typedef => {
+                    self.error(SemaErrorKind::NotAValue(name.clone()), expr.span)
+                }
+                Some(id) => {
+                    self.analysis.uses.insert(expr.span, id);
+                }
+                None => self.error(SemaErrorKind::Undeclared(name.clone()), expr.span),
+            },
+            ExprKind::Integer(_)
+            | ExprKind::Float(_)
+            | ExprKind::Character(_)
+            | ExprKind::String(_) => {}
+            ExprKind::Unary(_, operand) | ExprKind::SizeofExpr(operand) => self.expression(operand),
+            ExprKind::Member { base, .. } => self.expression(base),
+            ExprKind::Binary(_, lhs, rhs)
+            | ExprKind::Assign(_, lhs, rhs)
+            | ExprKind::Comma(lhs, rhs)
+            | ExprKind::Index(lhs, rhs) => {
+                self.expression(lhs);
+                self.expression(rhs);
+            }
+            ExprKind::Conditional(condition, then, otherwise) => {
+                self.expression(condition);
+                self.expression(then);
+                self.expression(otherwise);
+            }
+            ExprKind::Call(callee, arguments) => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            ExprKind::Cast(name, operand) => {
+                self.type_name(name);
+                self.expression(operand);
+            }
+            ExprKind::CompoundLiteral(name, initializer) => {
+                self.type_name(name);
+                self.initializer(initializer);
+            }
+            ExprKind::SizeofType(name) | ExprKind::AlignofType(name) => self.type_name(name),
+            ExprKind::Generic {
+                control,
+                associations,
+            } => {
+                self.expression(control);
+                for association in associations {
+                    if let Some(name) = &association.ty {
+                        self.type_name(name);
+                    }
+                    self.expression(&association.expr);
+                }
+            }
+        }
+    }
+}
+
+// resolves every identifier of a translation unit to its declaration;
+// errors are collected over the whole unit, in source order
+pub fn analyze(unit: &TranslationUnit) -> Result<Analysis, Vec<SemaError>> {
+    let mut sema = Sema {
+        analysis: Analysis {
+            symbols: Vec::new(),
+            uses: HashMap::new(),
+            declarations: HashMap::new(),
+            type_names: HashMap::new(),
+            env: TypeEnv::new(),
+        },
+        scopes: vec![HashMap::new()],
+        labels: HashMap::new(),
+        errors: Vec::new(),
+    };
+    for item in &unit.items {
+        match item {
+            ExternalDeclaration::Declaration(declaration) => sema.declaration(declaration),
+            ExternalDeclaration::Function(function) => sema.function(function),
+            ExternalDeclaration::StaticAssert(assertion) => sema.static_assert(assertion),
+        }
+    }
+    match sema.errors.is_empty() {
+        true => Ok(sema.analysis),
+        false => {
+            sema.errors.sort_by_key(|e| e.span.offset);
+            Err(sema.errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{analyze, Analysis, SemaErrorKind};
+    use crate::{
+        syntax,
+        types::{IntegerKind, Type},
+    };
+
+    fn analysis(source: &str) -> Analysis {
+        analyze(&syntax::parse(source).unwrap()).unwrap()
+    }
+
+    // each error with the line it was found on
+    fn errors(source: &str) -> Vec<(SemaErrorKind, usize)> {
+        let unit = syntax::parse(source).unwrap();
+        let errors = analyze(&unit).unwrap_err().into_iter();
+        errors.map(|e| (e.kind, e.span.line)).collect()
+    }
+
+    #[test]
+    fn uses_resolve_to_the_innermost_declaration() {
+        let source = "int x;
+            int f(int x) {
+                {
+                    int x;
+                    x = 1;
+                }
+                return x;
+            }
+            int g(void) { return x; }";
+        let analysis = analysis(source);
+        let mut uses: Vec<_> = analysis.uses.keys().copied().collect();
+        uses.sort_by_key(|span| span.offset);
+        let declared = uses.iter().filter(|span| span.len == 1).map(|&span| {
+            let symbol = analysis.resolve(span).unwrap();
+            (span.line, symbol.span.line, symbol.depth)
+        });
+        let declared: Vec<_> = declared.collect();
+        assert_eq!(declared, [(5, 4, 2), (7, 2, 1), (9, 1, 0)]);
+    }
+
+    #[test]
+    fn redeclarations_must_agree_and_define_once() {
+        let source = "int x; int x; extern int x;
+            int f(void); int f(void) { return 0; }
+            typedef int t; typedef int t;
+            int a[]; int a[3];";
+        let analysis = analysis(source);
+        let names: Vec<_> = analysis.symbols.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["x", "f", "t", "a"]);
+        assert!(analysis.symbols[1].defined);
+        let int = Box::new(Type::Integer(IntegerKind::Int).into());
+        assert_eq!(analysis.symbols[3].ty.ty, Type::Array(int, Some(3)));
+
+        let source = "int x = 1;
+            int x = 2;
+            typedef int t;
+            typedef long t;
+            int h(void);
+            long h(void);
+            void g(int p) {
+                int y;
+                double y;
+                int p;
+            }";
+        let found: Vec<_> = errors(source)
+            .into_iter()
+            .map(|(kind, line)| match kind {
+                SemaErrorKind::Redefinition { name, previous } => {
+                    ("redefinition", name, previous.line, line)
+                }
+                SemaErrorKind::ConflictingTypes { name, previous } => {
+                    ("conflict", name, previous.line, line)
+                }
+                kind => panic!("unexpected {kind}"),
+            })
+            .collect();
+        let expected = [
+            ("redefinition", "x", 1, 2),
+            ("redefinition", "t", 3, 4),
+            ("conflict", "h", 5, 6),
+            ("redefinition", "y", 8, 9),
+            // parameters share the scope of the function body
+            ("redefinition", "p", 7, 10),
+        ];
+        let expected =
+            expected.map(|(kind, name, previous, line)| (kind, name.to_string(), previous, line));
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn undeclared_identifiers_are_reported_in_source_order() {
+        let source = "int f(void) {
+                return y;
+            }
+            int g(void) {
+                { int z; }
+                return z + w;
+            }";
+        let expected = [
+            (SemaErrorKind::Undeclared("y".to_string()), 2),
+            (SemaErrorKind::Undeclared("z".to_string()), 6),
+            (SemaErrorKind::Undeclared("w".to_string()), 6),
+        ];
+        assert_eq!(errors(source), expected);
+    }
+
+    #[test]
+    fn labels_are_in_scope_through_their_function() {
+        let source = "void f(int n) {
+                goto end;
+                if (n) { again: n--; }
+                if (n) goto again;
+                end: ;
+            }";
+        analysis(source);
+        let source = "void f(void) {
+                a: ;
+                { a: ; }
+                goto b;
+            }
+            void g(void) { goto a; }";
+        let found: Vec<_> = errors(source)
+            .into_iter()
+            .map(|(kind, line)| match kind {
+                SemaErrorKind::DuplicateLabel { name, previous } => {
+                    (name, Some(previous.line), line)
+                }
+                SemaErrorKind::UndefinedLabel(name) => (name, None, line),
+                kind => panic!("unexpected {kind}"),
+            })
+            .collect();
+        let expected = [
+            ("a".to_string(), Some(2), 3),
+            ("b".to_string(), None, 4),
+            ("a".to_string(), None, 6),
+        ];
+        assert_eq!(found, expected);
+    }
+}
@@ -0,0 +1,1442 @@
+use std::{collections::HashMap, error, fmt, path::Path};
+
+use crate::{
+    ast::{
+        Alignment, BinaryOp, Block, BlockItem, Declaration, DeclarationSpecifiers, Declarator,
+        DerivedDeclarator, Designator, EnumSpecifier, Enumerator, Expr, ExprKind,
+        ExternalDeclaration, ForInit, FunctionDefinition, GenericAssociation, InitDeclarator,
+        Initializer, MemberDeclaration, MemberDeclarator, ParameterDeclaration, Qualifiers,
+        RecordKind, RecordSpecifier, StaticAssert, Stmt, StmtKind, StorageClass, TranslationUnit,
+        TypeName, TypeSpecifier, UnaryOp,
+    },
+    lexer::{LexError, LexErrorKind, Span, Spanned, Token},
+    preprocessor::{PreprocessError, PreprocessErrorKind, Preprocessor},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum ParseErrorKind {
+    Lex(LexErrorKind),
+    Preprocess(PreprocessErrorKind),
+    Expected { expected: String, found: Token },
+    DuplicateStorageClass,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ParseError {
+    pub kind: ParseErrorKind,
+    // the file the span points into, when it is known
+    pub file: Option<String>,
+    pub span: Span,
+}
+
+impl fmt::Display for ParseErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseErrorKind::Lex(kind) => write!(f, "{kind}"),
+            ParseErrorKind::Preprocess(kind) => write!(f, "{kind}"),
+            ParseErrorKind::Expected {
+                expected,
+                found: Token::EOF,
+            } => write!(f, "expected {expected}, found end of input"),
+            ParseErrorKind::Expected { expected, found } => {
+                write!(f, "expected {expected}, found '{found}'")
+            }
+            ParseErrorKind::DuplicateStorageClass => {
+                write!(f, "more than one storage class in declaration")
+            }
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{file}:")?;
+        }
+        // lines count from 1, so line 0 means the error has no position
+        match self.span.line {
+            0 => write!(f, "{}", self.kind),
+            _ => write!(f, "{}: {}", self.span, self.kind),
+        }
+    }
+}
+
+impl error::Error for ParseError {}
+
+impl From<LexError> for ParseError {
+    fn from(e: LexError) -> Self {
+        ParseError {
+            kind: ParseErrorKind::Lex(e.kind),
+            file: None,
+            span: e.span,
+        }
+    }
+}
+
+impl From<PreprocessError> for ParseError {
+    fn from(e: PreprocessError) -> Self {
+        ParseError {
+            kind: ParseErrorKind::Preprocess(e.kind),
+            file: Some(e.file),
+            span: e.span,
+        }
+    }
+}
+
+fn binary_op(token: &Token) -> Option<(BinaryOp, u8)> {
+    Some(match token {
+        Token::Or => (BinaryOp::Or, 1),
+        Token::And => (BinaryOp::And, 2),
+        Token::BitOr => (BinaryOp::BitOr, 3),
+        Token::Xor => (BinaryOp::Xor, 4),
+        Token::BitAnd => (BinaryOp::BitAnd, 5),
+        Token::Equal => (BinaryOp::Equal, 6),
+        Token::NotEqual => (BinaryOp::NotEqual, 6),
+        Token::LessThan => (BinaryOp::Less, 7),
+        Token::GreaterThan => (BinaryOp::Greater, 7),
+        Token::LessThanEqual => (BinaryOp::LessEqual, 7),
+        Token::GreaterThanEqual => (BinaryOp::GreaterEqual, 7),
+        Token::ShiftLeft => (BinaryOp::ShiftLeft, 8),
+        Token::ShiftRight => (BinaryOp::ShiftRight, 8),
+        Token::Plus => (BinaryOp::Add, 9),
+        Token::Minus => (BinaryOp::Sub, 9),
+        Token::Star => (BinaryOp::Mul, 10),
+        Token::Div => (BinaryOp::Div, 10),
+        Token::Mod => (BinaryOp::Mod, 10),
+        _ => return None,
+    })
+}
+
+// `None` for plain `=`
+fn assignment_op(token: &Token) -> Option<Option<BinaryOp>> {
+    Some(match token {
+        Token::Assign => None,
+        Token::PlusAssign => Some(BinaryOp::Add),
+        Token::MinusAssign => Some(BinaryOp::Sub),
+        Token::StarAssign => Some(BinaryOp::Mul),
+        Token::DivAssign => Some(BinaryOp::Div),
+        Token::ModAssign => Some(BinaryOp::Mod),
+        Token::BitAndAssign => Some(BinaryOp::BitAnd),
+        Token::BitOrAssign => Some(BinaryOp::BitOr),
+        Token::XorAssign => Some(BinaryOp::Xor),
+        Token::ShiftLeftAssign => Some(BinaryOp::ShiftLeft),
+        Token::ShiftRightAssign => Some(BinaryOp::ShiftRight),
+        _ => return None,
+    })
+}
+
+fn storage_class(token: &Token) -> Option<StorageClass> {
+    Some(match token {
+        Token::Typedef => StorageClass::Typedef,
+        Token::Extern => StorageClass::Extern,
+        Token::Static => StorageClass::Static,
+        Token::Auto => StorageClass::Auto,
+        Token::Register => StorageClass::Register,
+        Token::ThreadLocal => StorageClass::ThreadLocal,
+        _ => return None,
+    })
+}
+
+fn type_specifier(token: &Token) -> Option<TypeSpecifier> {
+    Some(match token {
+        Token::Void => TypeSpecifier::Void,
+        Token::Char => TypeSpecifier::Char,
+        Token::Short => TypeSpecifier::Short,
+        Token::Int => TypeSpecifier::Int,
+        Token::Long => TypeSpecifier::Long,
+        Token::FloatKeyword => TypeSpecifier::Float,
+        Token::Double => TypeSpecifier::Double,
+        Token::Signed => TypeSpecifier::Signed,
+        Token::Unsigned => TypeSpecifier::Unsigned,
+        Token::Bool => TypeSpecifier::Bool,
+        Token::Complex => TypeSpecifier::Complex,
+        _ => return None,
+    })
+}
+
+// applies a qualifier keyword, false if the token is not one
+fn qualify(qualifiers: &mut Qualifiers, token: &Token) -> bool {
+    match token {
+        Token::Const => qualifiers.is_const = true,
+        Token::Volatile => qualifiers.is_volatile = true,
+        Token::Restrict => qualifiers.is_restrict = true,
+        Token::Atomic => qualifiers.is_atomic = true,
+        _ => return false,
+    }
+    true
+}
+
+// whether a declarator must, may or must not name what it declares
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Names {
+    Required,
+    Optional,
+    Forbidden,
+}
+
+// recursive descent over a whole token stream; binary operators are parsed
+// by precedence climbing
+pub struct Parser {
+    tokens: Vec<Spanned<Token>>,
+    pos: usize,
+    // every name declared in each enclosing scope and whether it is a
+    // typedef, which decides if `T * x;` declares x or multiplies
+    scopes: Vec<HashMap<String, bool>>,
+    // the file behind each span origin, named in errors
+    files: HashMap<u32, String>,
+}
+
+impl Parser {
+    // expects preprocessed tokens; pragmas and newline tokens are skipped
+    pub fn new<E: Into<ParseError>>(
+        tokens: impl IntoIterator<Item = Result<Spanned<Token>, E>>,
+    ) -> Result<Self, ParseError> {
+        let mut kept: Vec<Spanned<Token>> = Vec::new();
+        for token in tokens {
+            let token = token.map_err(Into::into)?;
+            match token.node {
+                Token::EOF => break,
+                Token::Newline | Token::Directive(_) => {}
+                _ => kept.push(token),
+            }
+        }
+        let end = kept.last().map_or(Span::default(), |t| Span {
+            offset: t.span.end(),
+            column: t.span.column + t.span.len,
+            len: 0,
+            ..t.span
+        });
+        kept.push(Spanned {
+            node: Token::EOF,
+            span: end,
+        });
+        Ok(Parser {
+            tokens: kept,
+            pos: 0,
+            scopes: vec![HashMap::new()],
+            files: HashMap::new(),
+        })
+    }
+    pub fn from_source(source: &str) -> Result<Self, ParseError> {
+        let mut preprocessor = Preprocessor::new();
+        preprocessor.push_source("<source>", source);
+        Parser::preprocess(preprocessor)
+    }
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, ParseError> {
+        Parser::with_preprocessor(Preprocessor::new(), path)
+    }
+    // parses a file with a preprocessor set up by the caller, for example
+    // with extra include directories
+    pub fn with_preprocessor(
+        mut preprocessor: Preprocessor,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, ParseError> {
+        let path = path.as_ref();
+        preprocessor.push_file(path).map_err(|e| ParseError {
+            kind: ParseErrorKind::Lex(LexErrorKind::Io(e.kind())),
+            file: Some(path.display().to_string()),
+            span: Span::default(),
+        })?;
+        Parser::preprocess(preprocessor)
+    }
+    fn preprocess(mut preprocessor: Preprocessor) -> Result<Self, ParseError> {
+        let mut parser = Parser::new(preprocessor.by_ref())?;
+        for token in &parser.tokens {
+            let origin = token.span.origin;
+            if let Some(file) = preprocessor.file_of(origin) {
+                parser
+                    .files
+                    .entry(origin)
+                    .or_insert_with(|| file.to_string());
+            }
+        }
+        Ok(parser)
+    }
+    pub fn at_end(&self) -> bool {
+        self.peek() == &Token::EOF
+    }
+    // a typedef from outside the parsed source, such as `size_t` from a
+    // header that was never included
+    pub fn declare_typedef(&mut self, name: impl Into<String>) {
+        self.scopes[0].insert(name.into(), true);
+    }
+
+    // the innermost declaration decides, so a variable hides a typedef of
+    // an enclosing scope
+    fn is_typedef_name(&self, name: &str) -> bool {
+        let mut scopes = self.scopes.iter().rev();
+        scopes
+            .find_map(|scope| scope.get(name))
+            .copied()
+            .unwrap_or(false)
+    }
+    fn declare(&mut self, declarator: &Declarator, is_typedef: bool) {
+        if let (Some(name), Some(scope)) = (&declarator.name, self.scopes.last_mut()) {
+            scope.insert(name.node.clone(), is_typedef);
+        }
+    }
+
+    fn peek(&self) -> &Token {
+        self.peek_at(0)
+    }
+    fn peek_at(&self, n: usize) -> &Token {
+        let last = self.tokens.len() - 1;
+        &self.tokens[(self.pos + n).min(last)].node
+    }
+    fn span(&self) -> Span {
+        self.tokens[self.pos].span
+    }
+    // span of the most recently consumed token
+    fn previous(&self) -> Span {
+        self.tokens[self.pos.saturating_sub(1)].span
+    }
+    fn since(&self, start: Span) -> Span {
+        start.to(self.previous())
+    }
+    fn bump(&mut self) -> Spanned<Token> {
+        let token = self.tokens[self.pos].clone();
+        if self.pos < self.tokens.len() - 1 {
+            self.pos += 1;
+        }
+        token
+    }
+    fn eat(&mut self, token: &Token) -> bool {
+        let found = self.peek() == token;
+        if found {
+            self.bump();
+        }
+        found
+    }
+    fn fail(&self, kind: ParseErrorKind, span: Span) -> ParseError {
+        ParseError {
+            kind,
+            file: self.files.get(&span.origin).cloned(),
+            span,
+        }
+    }
+    fn error<T>(&self, expected: impl Into<String>) -> Result<T, ParseError> {
+        let kind = ParseErrorKind::Expected {
+            expected: expected.into(),
+            found: self.peek().clone(),
+        };
+        Err(self.fail(kind, self.span()))
+    }
+    fn expect(&mut self, token: &Token) -> Result<Span, ParseError> {
+        match self.peek() == token {
+            true => Ok(self.bump().span),
+            false => self.error(format!("'{token}'")),
+        }
+    }
+    fn identifier(&mut self) -> Result<Spanned<String>, ParseError> {
+        match self.peek().clone() {
+            Token::Identifier(name) => Ok(Spanned {
+                node: name,
+                span: self.bump().span,
+            }),
+            _ => self.error("an identifier"),
+        }
+    }
+
+    pub fn translation_unit(&mut self) -> Result<TranslationUnit, ParseError> {
+        let mut items = Vec::new();
+        while !self.at_end() {
+            if self.eat(&Token::Semicolon) {
+                continue;
+            }
+            if self.peek() == &Token::StaticAssert {
+                items.push(ExternalDeclaration::StaticAssert(self.static_assert()?));
+                continue;
+            }
+            items.push(self.external_declaration()?);
+        }
+        Ok(TranslationUnit { items })
+    }
+    fn external_declaration(&mut self) -> Result<ExternalDeclaration, ParseError> {
+        let start = self.span();
+        let specifiers = self.specifiers()?;
+        if self.eat(&Token::Semicolon) {
+            return Ok(ExternalDeclaration::Declaration(Declaration {
+                specifiers,
+                declarators: Vec::new(),
+                span: self.since(start),
+            }));
+        }
+        let declarator = self.declarator(Names::Required)?;
+        let parameters = match declarator.derived.first() {
+            Some(DerivedDeclarator::Function { parameters, .. }) => Some(parameters),
+            _ => None,
+        };
+        if let (Some(parameters), Token::LeftBrace) = (parameters, self.peek()) {
+            // parameters are in scope for the whole body
+            let names = parameters.iter().filter_map(|p| p.declarator.name.as_ref());
+            let scope = names.map(|name| (name.node.clone(), false)).collect();
+            self.declare(&declarator, false);
+            self.scopes.push(scope);
+            let body = self.block()?;
+            self.scopes.pop();
+            return Ok(ExternalDeclaration::Function(FunctionDefinition {
+                specifiers,
+                declarator,
+                body,
+                span: self.since(start),
+            }));
+        }
+        let declaration = self.init_declarators(start, specifiers, declarator)?;
+        Ok(ExternalDeclaration::Declaration(declaration))
+    }
+
+    // whether the `n`th token ahead can begin a type name
+    fn starts_type(&self, n: usize) -> bool {
+        match self.peek_at(n) {
+            Token::Identifier(name) => self.is_typedef_name(name),
+            Token::Struct | Token::Union | Token::Enum => true,
+            token => type_specifier(token).is_some() || qualify(&mut Qualifiers::default(), token),
+        }
+    }
+    fn is_declaration_start(&self) -> bool {
+        let token = self.peek();
+        match token {
+            // `T:` is a label even when T is a typedef
+            Token::Identifier(_) => self.starts_type(0) && self.peek_at(1) != &Token::Colon,
+            _ => {
+                self.starts_type(0)
+                    || storage_class(token).is_some()
+                    || matches!(token, Token::Inline | Token::Noreturn | Token::Alignas)
+            }
+        }
+    }
+    fn specifiers(&mut self) -> Result<DeclarationSpecifiers, ParseError> {
+        let (start, first) = (self.span(), self.pos);
+        let mut specifiers = DeclarationSpecifiers::default();
+        loop {
+            let token = self.peek().clone();
+            // a typedef name only counts while no other type was specified,
+            // so `int T;` redeclares T
+            let typedef = match &token {
+                Token::Identifier(name) if specifiers.types.is_empty() => {
+                    self.is_typedef_name(name).then(|| name.clone())
+                }
+                _ => None,
+            };
+            if matches!(token, Token::Struct | Token::Union | Token::Enum) {
+                let span = self.span();
+                let specifier = self.tagged()?;
+                specifiers.types.push(Spanned {
+                    node: specifier,
+                    span: self.since(span),
+                });
+                continue;
+            }
+            if let Some(storage) = storage_class(&token) {
+                if specifiers.storage.is_some() {
+                    let kind = ParseErrorKind::DuplicateStorageClass;
+                    return Err(self.fail(kind, self.span()));
+                }
+                specifiers.storage = Some(storage);
+            } else if token == Token::Alignas {
+                self.bump();
+                self.expect(&Token::LeftParen)?;
+                let alignment = match self.starts_type(0) {
+                    true => Alignment::Type(self.type_name()?),
+                    false => Alignment::Expr(self.conditional()?),
+                };
+                self.expect(&Token::RightParen)?;
+                specifiers.alignments.push(alignment);
+                continue;
+            } else if let Some(specifier) = type_specifier(&token) {
+                specifiers.types.push(Spanned {
+                    node: specifier,
+                    span: self.span(),
+                });
+            } else if qualify(&mut specifiers.qualifiers, &token) {
+            } else if token == Token::Inline {
+                specifiers.is_inline = true;
+            } else if token == Token::Noreturn {
+                specifiers.is_noreturn = true;
+            } else if let Some(name) = typedef {
+                specifiers.types.push(Spanned {
+                    node: TypeSpecifier::TypedefName(name),
+                    span: self.span(),
+                });
+            } else {
+                break;
+            }
+            self.bump();
+        }
+        if self.pos == first {
+            return self.error("declaration specifiers");
+        }
+        specifiers.span = self.since(start);
+        Ok(specifiers)
+    }
+    // `struct`, `union` or `enum` followed by a tag, a body or both
+    fn tagged(&mut self) -> Result<TypeSpecifier, ParseError> {
+        let start = self.span();
+        let keyword = self.bump().node;
+        let tag = match self.peek() {
+            Token::Identifier(_) => Some(self.identifier()?),
+            _ => None,
+        };
+        if tag.is_none() && self.peek() != &Token::LeftBrace {
+            return self.error("a tag or '{'");
+        }
+        if keyword == Token::Enum {
+            let enumerators = match self.eat(&Token::LeftBrace) {
+                true => Some(self.enumerators()?),
+                false => None,
+            };
+            return Ok(TypeSpecifier::Enum(EnumSpecifier {
+                tag,
+                enumerators,
+                span: self.since(start),
+            }));
+        }
+        let kind = match keyword {
+            Token::Union => RecordKind::Union,
+            _ => RecordKind::Struct,
+        };
+        let (members, assertions) = match self.eat(&Token::LeftBrace) {
+            true => {
+                let (members, assertions) = self.members()?;
+                (Some(members), assertions)
+            }
+            false => (None, Vec::new()),
+        };
+        Ok(TypeSpecifier::Record(RecordSpecifier {
+            kind,
+            tag,
+            members,
+            assertions,
+            span: self.since(start),
+        }))
+    }
+    // enumerators after the opening brace, a trailing comma is allowed
+    fn enumerators(&mut self) -> Result<Vec<Enumerator>, ParseError> {
+        let mut enumerators = Vec::new();
+        loop {
+            let name = self.identifier()?;
+            let value = match self.eat(&Token::Assign) {
+                true => Some(self.conditional()?),
+                false => None,
+            };
+            // constants are ordinary identifiers and hide typedefs
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.insert(name.node.clone(), false);
+            }
+            enumerators.push(Enumerator { name, value });
+            if !self.eat(&Token::Comma) || self.peek() == &Token::RightBrace {
+                break;
+            }
+        }
+        self.expect(&Token::RightBrace)?;
+        Ok(enumerators)
+    }
+    // member declarations after the opening brace, and the static
+    // assertions among them
+    fn members(&mut self) -> Result<(Vec<MemberDeclaration>, Vec<StaticAssert>), ParseError> {
+        let mut members = Vec::new();
+        let mut assertions = Vec::new();
+        while !self.eat(&Token::RightBrace) {
+            if self.peek() == &Token::StaticAssert {
+                assertions.push(self.static_assert()?);
+                continue;
+            }
+            let start = self.span();
+            let specifiers = self.specifiers()?;
+            let mut declarators = Vec::new();
+            while self.peek() != &Token::Semicolon {
+                let declarator = match self.peek() {
+                    Token::Colon => Declarator {
+                        name: None,
+                        derived: Vec::new(),
+                        span: Span {
+                            len: 0,
+                            ..self.span()
+                        },
+                    },
+                    _ => self.declarator(Names::Required)?,
+                };
+                let width = match self.eat(&Token::Colon) {
+                    true => Some(self.conditional()?),
+                    false => None,
+                };
+                declarators.push(MemberDeclarator { declarator, width });
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(&Token::Semicolon)?;
+            members.push(MemberDeclaration {
+                specifiers,
+                declarators,
+                span: self.since(start),
+            });
+        }
+        Ok((members, assertions))
+    }
+    // `_Static_assert` up to and including its semicolon
+    fn static_assert(&mut self) -> Result<StaticAssert, ParseError> {
+        let start = self.expect(&Token::StaticAssert)?;
+        self.expect(&Token::LeftParen)?;
+        let condition = self.conditional()?;
+        self.expect(&Token::Comma)?;
+        if !matches!(self.peek(), Token::String(_)) {
+            return self.error("a string literal");
+        }
+        let message = String::from_utf8_lossy(&self.string()).into_owned();
+        self.expect(&Token::RightParen)?;
+        self.expect(&Token::Semicolon)?;
+        Ok(StaticAssert {
+            condition,
+            message,
+            span: self.since(start),
+        })
+    }
+    fn declaration(&mut self) -> Result<Declaration, ParseError> {
+        let start = self.span();
+        let specifiers = self.specifiers()?;
+        if self.eat(&Token::Semicolon) {
+            return Ok(Declaration {
+                specifiers,
+                declarators: Vec::new(),
+                span: self.since(start),
+            });
+        }
+        let declarator = self.declarator(Names::Required)?;
+        self.init_declarators(start, specifiers, declarator)
+    }
+    // the rest of a declaration after its first declarator
+    fn init_declarators(
+        &mut self,
+        start: Span,
+        specifiers: DeclarationSpecifiers,
+        first: Declarator,
+    ) -> Result<Declaration, ParseError> {
+        let mut declarators = Vec::new();
+        let mut declarator = first;
+        let is_typedef = specifiers.storage == Some(StorageClass::Typedef);
+        loop {
+            // in scope from the end of its declarator, initializer included
+            self.declare(&declarator, is_typedef);
+            let initializer = match self.eat(&Token::Assign) {
+                true => Some(self.initializer()?),
+                false => None,
+            };
+            declarators.push(InitDeclarator {
+                declarator,
+                initializer,
+            });
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+            declarator = self.declarator(Names::Required)?;
+        }
+        self.expect(&Token::Semicolon)?;
+        Ok(Declaration {
+            specifiers,
+            declarators,
+            span: self.since(start),
+        })
+    }
+    // pointers, then a name or a parenthesized declarator, then array and
+    // function suffixes; derivations read outwards from the name, so
+    // `(*fp[3])(char *)` is an array, then a pointer, then a function
+    fn declarator(&mut self, names: Names) -> Result<Declarator, ParseError> {
+        let start = self.span();
+        let mut pointers = Vec::new();
+        while self.eat(&Token::Star) {
+            let mut qualifiers = Qualifiers::default();
+            while qualify(&mut qualifiers, self.peek()) {
+                self.bump();
+            }
+            pointers.push(DerivedDeclarator::Pointer(qualifiers));
+        }
+        let (name, mut derived) = match self.peek() {
+            Token::Identifier(_) if names != Names::Forbidden => {
+                (Some(self.identifier()?), Vec::new())
+            }
+            Token::LeftParen if self.is_nested(names) => {
+                self.bump();
+                let inner = self.declarator(names)?;
+                self.expect(&Token::RightParen)?;
+                (inner.name, inner.derived)
+            }
+            _ if names != Names::Required => (None, Vec::new()),
+            _ => return self.error("a declarator"),
+        };
+        loop {
+            if self.eat(&Token::LeftBracket) {
+                let size = match self.peek() {
+                    Token::RightBracket => None,
+                    _ => Some(Box::new(self.assignment()?)),
+                };
+                self.expect(&Token::RightBracket)?;
+                derived.push(DerivedDeclarator::Array(size));
+            } else if self.eat(&Token::LeftParen) {
+                derived.push(self.parameters()?);
+            } else {
+                break;
+            }
+        }
+        derived.extend(pointers.into_iter().rev());
+        Ok(Declarator {
+            name,
+            derived,
+            span: self.since(start),
+        })
+    }
+    // whether a parenthesis groups a declarator rather than starting the
+    // parameters of an unnamed function, as in `int (*)(int)`
+    fn is_nested(&self, names: Names) -> bool {
+        match self.peek_at(1) {
+            Token::Star | Token::LeftParen | Token::LeftBracket => true,
+            Token::Identifier(name) => names != Names::Forbidden && !self.is_typedef_name(name),
+            _ => false,
+        }
+    }
+    // parameter list after its opening parenthesis, the names are scoped to
+    // the list itself
+    fn parameters(&mut self) -> Result<DerivedDeclarator, ParseError> {
+        let mut parameters = Vec::new();
+        let mut variadic = false;
+        self.scopes.push(HashMap::new());
+        if !self.eat(&Token::RightParen) {
+            loop {
+                if self.eat(&Token::Ellipsis) {
+                    variadic = true;
+                    break;
+                }
+                let start = self.span();
+                let specifiers = self.specifiers()?;
+                let declarator = self.declarator(Names::Optional)?;
+                self.declare(&declarator, false);
+                parameters.push(ParameterDeclaration {
+                    specifiers,
+                    declarator,
+                    span: self.since(start),
+                });
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(&Token::RightParen)?;
+        }
+        self.scopes.pop();
+        Ok(DerivedDeclarator::Function {
+            parameters,
+            variadic,
+        })
+    }
+    pub fn type_name(&mut self) -> Result<TypeName, ParseError> {
+        let start = self.span();
+        let specifiers = self.specifiers()?;
+        let declarator = self.declarator(Names::Forbidden)?;
+        Ok(TypeName {
+            specifiers,
+            declarator,
+            span: self.since(start),
+        })
+    }
+    fn initializer(&mut self) -> Result<Initializer, ParseError> {
+        let start = self.span();
+        if !self.eat(&Token::LeftBrace) {
+            return Ok(Initializer::Expression(self.assignment()?));
+        }
+        let mut items = Vec::new();
+        while self.peek() != &Token::RightBrace {
+            let mut designators = Vec::new();
+            loop {
+                if self.eat(&Token::LeftBracket) {
+                    designators.push(Designator::Index(self.conditional()?));
+                    self.expect(&Token::RightBracket)?;
+                } else if self.eat(&Token::Dot) {
+                    designators.push(Designator::Member(self.identifier()?));
+                } else {
+                    break;
+                }
+            }
+            if !designators.is_empty() {
+                self.expect(&Token::Assign)?;
+            }
+            items.push((designators, self.initializer()?));
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightBrace)?;
+        Ok(Initializer::List {
+            items,
+            span: self.since(start),
+        })
+    }
+
+    fn block(&mut self) -> Result<Block, ParseError> {
+        let start = self.expect(&Token::LeftBrace)?;
+        let mut items = Vec::new();
+        self.scopes.push(HashMap::new());
+        while !self.eat(&Token::RightBrace) {
+            if self.at_end() {
+                return self.error("'}'");
+            }
+            if self.peek() == &Token::StaticAssert {
+                items.push(BlockItem::StaticAssert(self.static_assert()?));
+                continue;
+            }
+            match self.is_declaration_start() {
+                true => items.push(BlockItem::Declaration(self.declaration()?)),
+                false => items.push(BlockItem::Statement(self.statement()?)),
+            }
+        }
+        self.scopes.pop();
+        Ok(Block {
+            items,
+            span: self.since(start),
+        })
+    }
+    fn parenthesized(&mut self) -> Result<Expr, ParseError> {
+        self.expect(&Token::LeftParen)?;
+        let expr = self.expression()?;
+        self.expect(&Token::RightParen)?;
+        Ok(expr)
+    }
+    pub fn statement(&mut self) -> Result<Stmt, ParseError> {
+        let start = self.span();
+        let kind = match self.peek().clone() {
+            Token::LeftBrace => StmtKind::Compound(self.block()?),
+            Token::Semicolon => {
+                self.bump();
+                StmtKind::Expression(None)
+            }
+            Token::If => {
+                self.bump();
+                let condition = self.parenthesized()?;
+                let then = Box::new(self.statement()?);
+                let otherwise = match self.eat(&Token::Else) {
+                    true => Some(Box::new(self.statement()?)),
+                    false => None,
+                };
+                StmtKind::If {
+                    condition,
+                    then,
+                    otherwise,
+                }
+            }
+            Token::While => {
+                self.bump();
+                let condition = self.parenthesized()?;
+                let body = Box::new(self.statement()?);
+                StmtKind::While { condition, body }
+            }
+            Token::Do => {
+                self.bump();
+                let body = Box::new(self.statement()?);
+                self.expect(&Token::While)?;
+                let condition = self.parenthesized()?;
+                self.expect(&Token::Semicolon)?;
+                StmtKind::DoWhile { body, condition }
+            }
+            Token::For => {
+                self.bump();
+                self.expect(&Token::LeftParen)?;
+                self.scopes.push(HashMap::new());
+                let init = if self.eat(&Token::Semicolon) {
+                    None
+                } else if self.is_declaration_start() {
+                    Some(Box::new(ForInit::Declaration(self.declaration()?)))
+                } else {
+                    let init = self.expression()?;
+                    self.expect(&Token::Semicolon)?;
+                    Some(Box::new(ForInit::Expression(init)))
+                };
+                let condition = match self.peek() {
+                    Token::Semicolon => None,
+                    _ => Some(self.expression()?),
+                };
+                self.expect(&Token::Semicolon)?;
+                let step = match self.peek() {
+                    Token::RightParen => None,
+                    _ => Some(self.expression()?),
+                };
+                self.expect(&Token::RightParen)?;
+                let body = Box::new(self.statement()?);
+                self.scopes.pop();
+                StmtKind::For {
+                    init,
+                    condition,
+                    step,
+                    body,
+                }
+            }
+            Token::Switch => {
+                self.bump();
+                let condition = self.parenthesized()?;
+                let body = Box::new(self.statement()?);
+                StmtKind::Switch { condition, body }
+            }
+            Token::Case => {
+                self.bump();
+                let value = self.conditional()?;
+                self.expect(&Token::Colon)?;
+                let body = Box::new(self.statement()?);
+                StmtKind::Case { value, body }
+            }
+            Token::Default => {
+                self.bump();
+                self.expect(&Token::Colon)?;
+                StmtKind::Default(Box::new(self.statement()?))
+            }
+            Token::Identifier(_) if self.peek_at(1) == &Token::Colon => {
+                let label = self.identifier()?;
+                self.bump();
+                let body = Box::new(self.statement()?);
+                StmtKind::Labeled { label, body }
+            }
+            Token::Goto => {
+                self.bump();
+                let label = self.identifier()?;
+                self.expect(&Token::Semicolon)?;
+                StmtKind::Goto(label)
+            }
+            Token::Break | Token::Continue => {
+                let token = self.bump().node;
+                self.expect(&Token::Semicolon)?;
+                match token {
+                    Token::Break => StmtKind::Break,
+                    _ => StmtKind::Continue,
+                }
+            }
+            Token::Return => {
+                self.bump();
+                let value = match self.peek() {
+                    Token::Semicolon => None,
+                    _ => Some(self.expression()?),
+                };
+                self.expect(&Token::Semicolon)?;
+                StmtKind::Return(value)
+            }
+            _ => {
+                let expr = self.expression()?;
+                self.expect(&Token::Semicolon)?;
+                StmtKind::Expression(Some(expr))
+            }
+        };
+        Ok(Stmt {
+            kind,
+            span: self.since(start),
+        })
+    }
+
+    fn node(&self, kind: ExprKind, start: Span) -> Expr {
+        Expr {
+            kind,
+            span: self.since(start),
+        }
+    }
+    pub fn expression(&mut self) -> Result<Expr, ParseError> {
+        let start = self.span();
+        let mut expr = self.assignment()?;
+        while self.eat(&Token::Comma) {
+            let rhs = self.assignment()?;
+            expr = self.node(ExprKind::Comma(Box::new(expr), Box::new(rhs)), start);
+        }
+        Ok(expr)
+    }
+    // whether the left operand is assignable is left to later passes
+    fn assignment(&mut self) -> Result<Expr, ParseError> {
+        let start = self.span();
+        let lhs = self.conditional()?;
+        let Some(op) = assignment_op(self.peek()) else {
+            return Ok(lhs);
+        };
+        self.bump();
+        let rhs = self.assignment()?;
+        Ok(self.node(ExprKind::Assign(op, Box::new(lhs), Box::new(rhs)), start))
+    }
+    fn conditional(&mut self) -> Result<Expr, ParseError> {
+        let start = self.span();
+        let condition = self.binary(1)?;
+        if !self.eat(&Token::Question) {
+            return Ok(condition);
+        }
+        let then = self.expression()?;
+        self.expect(&Token::Colon)?;
+        let otherwise = self.conditional()?;
+        let kind = ExprKind::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise));
+        Ok(self.node(kind, start))
+    }
+    // operators binding at least as tight as `min`, all left associative
+    fn binary(&mut self, min: u8) -> Result<Expr, ParseError> {
+        let start = self.span();
+        let mut lhs = self.cast()?;
+        while let Some((op, precedence)) = binary_op(self.peek()) {
+            if precedence < min {
+                break;
+            }
+            self.bump();
+            let rhs = self.binary(precedence + 1)?;
+            lhs = self.node(ExprKind::Binary(op, Box::new(lhs), Box::new(rhs)), start);
+        }
+        Ok(lhs)
+    }
+    fn cast(&mut self) -> Result<Expr, ParseError> {
+        if self.peek() != &Token::LeftParen || !self.starts_type(1) {
+            return self.unary();
+        }
+        let start = self.bump().span;
+        let ty = self.type_name()?;
+        self.expect(&Token::RightParen)?;
+        if self.peek() == &Token::LeftBrace {
+            return self.compound_literal(start, ty);
+        }
+        let operand = self.cast()?;
+        Ok(self.node(ExprKind::Cast(Box::new(ty), Box::new(operand)), start))
+    }
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.span();
+        let op = match self.peek() {
+            Token::Increment => UnaryOp::PreIncrement,
+            Token::Decrement => UnaryOp::PreDecrement,
+            Token::BitAnd => UnaryOp::AddressOf,
+            Token::Star => UnaryOp::Deref,
+            Token::Plus => UnaryOp::Plus,
+            Token::Minus => UnaryOp::Minus,
+            Token::BitNot => UnaryOp::BitNot,
+            Token::Not => UnaryOp::Not,
+            Token::Sizeof => {
+                self.bump();
+                if self.peek() == &Token::LeftParen && self.starts_type(1) {
+                    let parenthesis = self.bump().span;
+                    let ty = self.type_name()?;
+                    self.expect(&Token::RightParen)?;
+                    if self.peek() == &Token::LeftBrace {
+                        let operand = self.compound_literal(parenthesis, ty)?;
+                        return Ok(self.node(ExprKind::SizeofExpr(Box::new(operand)), start));
+                    }
+                    return Ok(self.node(ExprKind::SizeofType(Box::new(ty)), start));
+                }
+                let operand = self.unary()?;
+                return Ok(self.node(ExprKind::SizeofExpr(Box::new(operand)), start));
+            }
+            Token::Alignof => {
+                self.bump();
+                self.expect(&Token::LeftParen)?;
+                let ty = self.type_name()?;
+                self.expect(&Token::RightParen)?;
+                return Ok(self.node(ExprKind::AlignofType(Box::new(ty)), start));
+            }
+            _ => return self.postfix(),
+        };
+        self.bump();
+        let operand = match op {
+            UnaryOp::PreIncrement | UnaryOp::PreDecrement => self.unary()?,
+            _ => self.cast()?,
+        };
+        Ok(self.node(ExprKind::Unary(op, Box::new(operand)), start))
+    }
+    // `(T){ ... }` from its initializer on, with any postfix operators
+    fn compound_literal(&mut self, start: Span, ty: TypeName) -> Result<Expr, ParseError> {
+        let initializer = self.initializer()?;
+        let kind = ExprKind::CompoundLiteral(Box::new(ty), Box::new(initializer));
+        let literal = self.node(kind, start);
+        self.suffixes(start, literal)
+    }
+    fn postfix(&mut self) -> Result<Expr, ParseError> {
+        let start = self.span();
+        let expr = self.primary()?;
+        self.suffixes(start, expr)
+    }
+    // postfix operators applied to `expr`, which begins at `start`
+    fn suffixes(&mut self, start: Span, mut expr: Expr) -> Result<Expr, ParseError> {
+        loop {
+            let kind = match self.peek() {
+                Token::LeftBracket => {
+                    self.bump();
+                    let index = self.expression()?;
+                    self.expect(&Token::RightBracket)?;
+                    ExprKind::Index(Box::new(expr), Box::new(index))
+                }
+                Token::LeftParen => {
+                    self.bump();
+                    let mut arguments = Vec::new();
+                    if !self.eat(&Token::RightParen) {
+                        loop {
+                            arguments.push(self.assignment()?);
+                            if !self.eat(&Token::Comma) {
+                                break;
+                            }
+                        }
+                        self.expect(&Token::RightParen)?;
+                    }
+                    ExprKind::Call(Box::new(expr), arguments)
+                }
+                Token::Dot | Token::Arrow => {
+                    let arrow = self.bump().node == Token::Arrow;
+                    let member = self.identifier()?;
+                    ExprKind::Member {
+                        base: Box::new(expr),
+                        member,
+                        arrow,
+                    }
+                }
+                Token::Increment => {
+                    self.bump();
+                    ExprKind::Unary(UnaryOp::PostIncrement, Box::new(expr))
+                }
+                Token::Decrement => {
+                    self.bump();
+                    ExprKind::Unary(UnaryOp::PostDecrement, Box::new(expr))
+                }
+                _ => return Ok(expr),
+            };
+            expr = self.node(kind, start);
+        }
+    }
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let start = self.span();
+        let kind = match self.peek().clone() {
+            Token::Identifier(name) => ExprKind::Identifier(name),
+            Token::Integer(value) => ExprKind::Integer(value),
+            Token::Float(value) => ExprKind::Float(value),
+            Token::Character(value) => ExprKind::Character(value),
+            Token::String(_) => {
+                let value = self.string();
+                return Ok(self.node(ExprKind::String(value), start));
+            }
+            Token::LeftParen => return self.parenthesized(),
+            Token::Generic => return self.generic(),
+            _ => return self.error("an expression"),
+        };
+        self.bump();
+        Ok(self.node(kind, start))
+    }
+    // adjacent string literals, concatenated
+    fn string(&mut self) -> Vec<u8> {
+        let mut value = Vec::new();
+        while let Token::String(next) = self.peek() {
+            value.extend_from_slice(next);
+            self.bump();
+        }
+        value
+    }
+    // `_Generic(control, T: expr, ..., default: expr)`
+    fn generic(&mut self) -> Result<Expr, ParseError> {
+        let start = self.expect(&Token::Generic)?;
+        self.expect(&Token::LeftParen)?;
+        let control = self.assignment()?;
+        self.expect(&Token::Comma)?;
+        let mut associations = Vec::new();
+        loop {
+            let ty = match self.eat(&Token::Default) {
+                true => None,
+                false => Some(self.type_name()?),
+            };
+            self.expect(&Token::Colon)?;
+            let expr = self.assignment()?;
+            associations.push(GenericAssociation { ty, expr });
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        self.expect(&Token::RightParen)?;
+        let kind = ExprKind::Generic {
+            control: Box::new(control),
+            associations,
+        };
+        Ok(self.node(kind, start))
+    }
+}
+
+pub fn parse(source: &str) -> Result<TranslationUnit, ParseError> {
+    Parser::from_source(source)?.translation_unit()
+}
+
+pub fn parse_file(path: impl AsRef<Path>) -> Result<TranslationUnit, ParseError> {
+    Parser::from_file(path)?.translation_unit()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use super::*;
+    use crate::preprocessor::INCLUDE_DIR;
+
+    #[test]
+    fn parses_test_c_with_bundled_headers() {
+        let path = concat!(env!("CARGO_MANIFEST_DIR"), "/test.c");
+        let preprocessor = Preprocessor::new().with_include_path(INCLUDE_DIR);
+        let unit = Parser::with_preprocessor(preprocessor, path)
+            .and_then(|mut parser| parser.translation_unit())
+            .unwrap();
+        let main = unit.items.iter().find_map(|item| match item {
+            ExternalDeclaration::Function(f) => f.declarator.name.as_ref(),
+            _ => None,
+        });
+        assert_eq!(main.map(|name| name.node.as_str()), Some("main"));
+        let analysis = crate::sema::analyze(&unit).unwrap();
+        crate::typeck::check(&unit, &analysis).unwrap();
+    }
+
+    #[test]
+    fn errors_in_headers_name_the_header() {
+        let dir = env::temp_dir().join(format!("rem-syntax-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("bad.h"), "int y = ;\n").unwrap();
+        fs::write(dir.join("missing.h"), "#include \"nowhere.h\"\n").unwrap();
+        fs::write(dir.join("main.c"), "#include \"bad.h\"\n").unwrap();
+        fs::write(dir.join("other.c"), "\n#include \"missing.h\"\n").unwrap();
+        let header = dir.join("bad.h").display().to_string();
+        let e = parse_file(dir.join("main.c")).unwrap_err();
+        assert_eq!(e.file.as_deref(), Some(header.as_str()));
+        assert_eq!((e.span.line, e.span.column), (1, 9));
+        let header = dir.join("missing.h").display().to_string();
+        let e = parse_file(dir.join("other.c")).unwrap_err();
+        assert_eq!(e.file.as_deref(), Some(header.as_str()));
+        assert_eq!(e.span.line, 1);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // the expression with every operation in parentheses
+    fn show(expr: &Expr) -> String {
+        match &expr.kind {
+            ExprKind::Identifier(name) => name.clone(),
+            ExprKind::Integer(integer) => integer.to_string(),
+            ExprKind::Float(float) => float.to_string(),
+            ExprKind::Character(value) => format!("'{value}'"),
+            ExprKind::String(bytes) => format!("{:?}", String::from_utf8_lossy(bytes)),
+            ExprKind::Unary(op @ (UnaryOp::PostIncrement | UnaryOp::PostDecrement), e) => {
+                format!("({}{})", show(e), op.symbol())
+            }
+            ExprKind::Unary(op, e) => format!("({}{})", op.symbol(), show(e)),
+            ExprKind::Binary(op, a, b) => format!("({} {} {})", show(a), op.symbol(), show(b)),
+            ExprKind::Assign(op, a, b) => {
+                let op = op.map_or("", |op| op.symbol());
+                format!("({} {op}= {})", show(a), show(b))
+            }
+            ExprKind::Conditional(c, a, b) => {
+                format!("({} ? {} : {})", show(c), show(a), show(b))
+            }
+            ExprKind::Comma(a, b) => format!("({}, {})", show(a), show(b)),
+            ExprKind::Call(f, args) => {
+                let args: Vec<_> = args.iter().map(show).collect();
+                format!("{}({})", show(f), args.join(", "))
+            }
+            ExprKind::Index(a, i) => format!("{}[{}]", show(a), show(i)),
+            ExprKind::Member {
+                base,
+                member,
+                arrow,
+            } => {
+                let op = if *arrow { "->" } else { "." };
+                format!("{}{op}{}", show(base), member.node)
+            }
+            ExprKind::Cast(_, e) => format!("((type) {})", show(e)),
+            ExprKind::CompoundLiteral(..) => "(type){...}".to_string(),
+            ExprKind::SizeofExpr(e) => format!("sizeof({})", show(e)),
+            ExprKind::SizeofType(_) => "sizeof(type)".to_string(),
+            ExprKind::AlignofType(_) => "_Alignof(type)".to_string(),
+            ExprKind::Generic { control, .. } => format!("_Generic({}, ...)", show(control)),
+        }
+    }
+
+    fn expression(source: &str) -> String {
+        let mut parser = Parser::from_source(source).unwrap();
+        let expr = parser.expression().unwrap();
+        assert!(parser.at_end(), "{source} was not read to the end");
+        show(&expr)
+    }
+
+    #[test]
+    fn expressions_follow_c_precedence() {
+        let cases = [
+            ("a + b * c", "(a + (b * c))"),
+            ("a - b - c", "((a - b) - c)"),
+            ("a = b = c", "(a = (b = c))"),
+            ("a += b << 1 | c", "(a += ((b << 1) | c))"),
+            (
+                "a || b && c | d ^ e & f",
+                "(a || (b && (c | (d ^ (e & f)))))",
+            ),
+            ("a == b < c", "(a == (b < c))"),
+            ("a ? b : c ? d : e", "(a ? b : (c ? d : e))"),
+            ("a ? b, c : d", "(a ? (b, c) : d)"),
+            ("a, b = c", "(a, (b = c))"),
+            ("-a++", "(-(a++))"),
+            ("*p->q[1].r", "(*p->q[1].r)"),
+            ("!~-x", "(!(~(-x)))"),
+            ("f(a, b)(c)", "f(a, b)(c)"),
+            ("(int)x + 1", "(((type) x) + 1)"),
+            ("sizeof x + 1", "(sizeof(x) + 1)"),
+            ("sizeof(int) * 2", "(sizeof(type) * 2)"),
+            ("--x---y", "((--(x--)) - y)"),
+            ("\"ab\" \"cd\"", "\"abcd\""),
+        ];
+        for (source, expected) in cases {
+            assert_eq!(expression(source), expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn else_binds_to_the_nearest_if() {
+        let unit = parse("void f(int a, int b) { if (a) if (b) a = 1; else a = 2; }").unwrap();
+        let ExternalDeclaration::Function(function) = &unit.items[0] else {
+            panic!("not a function");
+        };
+        let [BlockItem::Statement(stmt)] = &function.body.items[..] else {
+            panic!("not one statement");
+        };
+        let StmtKind::If {
+            then, otherwise, ..
+        } = &stmt.kind
+        else {
+            panic!("not an if");
+        };
+        assert!(otherwise.is_none());
+        assert!(matches!(
+            then.kind,
+            StmtKind::If {
+                otherwise: Some(_),
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn errors_say_what_was_expected_where() {
+        let cases = [
+            ("int f(void) { return 1 }", "1:24: expected ';', found '}'"),
+            ("int x = (1 + ;", "1:14: expected an expression, found ';'"),
+            ("int f(void) { while 1; }", "1:21: expected '(', found '1'"),
+            ("int x", "1:6: expected ';', found end of input"),
+            (
+                "static extern int x;",
+                "1:8: more than one storage class in declaration",
+            ),
+            ("int s = \"open;", "1:9: unterminated string literal"),
+        ];
+        for (source, expected) in cases {
+            let error = parse(source).unwrap_err();
+            assert_eq!(
+                error.to_string(),
+                format!("<source>:{expected}"),
+                "{source}"
+            );
+        }
+    }
+
+    // the derivations of a declarator, innermost first, with the
+    // parameters of functions in brackets
+    fn derived(declarator: &Declarator) -> String {
+        let parts: Vec<_> = declarator
+            .derived
+            .iter()
+            .map(|part| match part {
+                DerivedDeclarator::Pointer(q) if q.is_const => "const pointer".to_string(),
+                DerivedDeclarator::Pointer(_) => "pointer".to_string(),
+                DerivedDeclarator::Array(Some(size)) => format!("array {}", show(size)),
+                DerivedDeclarator::Array(None) => "array".to_string(),
+                DerivedDeclarator::Function {
+                    parameters,
+                    variadic,
+                } => {
+                    let mut parameters: Vec<_> =
+                        parameters.iter().map(|p| derived(&p.declarator)).collect();
+                    if *variadic {
+                        parameters.push("...".to_string());
+                    }
+                    format!("function [{}]", parameters.join(", "))
+                }
+            })
+            .collect();
+        parts.join(" ")
+    }
+
+    fn declarators(source: &str) -> Vec<(String, String)> {
+        let unit = parse(source).unwrap();
+        let declarators = unit.items.iter().flat_map(|item| match item {
+            ExternalDeclaration::Declaration(d) => d.declarators.iter().collect(),
+            _ => Vec::new(),
+        });
+        declarators
+            .map(|d| {
+                let name = d.declarator.name.as_ref().map(|n| n.node.clone());
+                (name.unwrap_or_default(), derived(&d.declarator))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn declarators_nest_inside_out() {
+        let source = "
+            int *a[3], (*b)[3], **const c, d[2][4];
+            int (*fp)(int, char *), f(void), g(), h(int, ...);
+            void (*signal(int sig, void (*handler)(int)))(int);
+            char *(*(*x[2])(void))[5];
+        ";
+        let expected = [
+            ("a", "array 3 pointer"),
+            ("b", "pointer array 3"),
+            ("c", "const pointer pointer"),
+            ("d", "array 2 array 4"),
+            ("fp", "pointer function [, pointer]"),
+            ("f", "function []"),
+            ("g", "function []"),
+            ("h", "function [, ...]"),
+            (
+                "signal",
+                "function [, pointer function []] pointer function []",
+            ),
+            ("x", "array 2 pointer function [] pointer array 5 pointer"),
+        ];
+        let expected = expected.map(|(name, derived)| (name.to_string(), derived.to_string()));
+        assert_eq!(declarators(source), expected);
+    }
+
+    #[test]
+    fn typedef_names_decide_between_declarations_and_expressions() {
+        let body = |source: &str| {
+            let unit = parse(source).unwrap();
+            let function = unit.items.iter().find_map(|item| match item {
+                ExternalDeclaration::Function(function) => Some(function),
+                _ => None,
+            });
+            let items = &function.unwrap().body.items;
+            let kinds = items.iter().map(|item| match item {
+                BlockItem::Declaration(_) => "declaration",
+                BlockItem::Statement(_) => "statement",
+                BlockItem::StaticAssert(_) => "assertion",
+            });
+            kinds.collect::<Vec<_>>()
+        };
+        let source = "typedef int T; int y; void f(void) { T * x; y * x; }";
+        assert_eq!(body(source), ["declaration", "statement"]);
+        // a local variable hides the typedef until the end of its block
+        let source = "typedef int T; int x; void f(void) { { int T; T * x; } T * y; }";
+        assert_eq!(body(source), ["statement", "declaration"]);
+        // a typedef in parentheses is a cast, a variable a call
+        let cast = "typedef int T; int g(int x) { return (T)(x); }";
+        let call = "int (*T)(int); int g(int x) { return (T)(x); }";
+        for (source, cast) in [(cast, true), (call, false)] {
+            let unit = parse(source).unwrap();
+            let ExternalDeclaration::Function(function) = &unit.items[1] else {
+                panic!("not a function");
+            };
+            let BlockItem::Statement(Stmt {
+                kind: StmtKind::Return(Some(expr)),
+                ..
+            }) = &function.body.items[0]
+            else {
+                panic!("not a return");
+            };
+            assert_eq!(matches!(expr.kind, ExprKind::Cast(..)), cast, "{source}");
+        }
+        let mut parser = Parser::from_source("size_t * n").unwrap();
+        parser.declare_typedef("size_t");
+        let name = parser.type_name().unwrap();
+        assert_eq!(derived(&name.declarator), "pointer");
+        let mut parser = Parser::from_source("int (*)[4]").unwrap();
+        let name = parser.type_name().unwrap();
+        assert_eq!(derived(&name.declarator), "pointer array 4");
+        assert!(name.declarator.name.is_none());
+    }
+}
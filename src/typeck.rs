@@ -0,0 +1,1311 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error, fmt,
+};
+
+use crate::{
+    ast::{
+        BinaryOp, Block, BlockItem, Declaration, Designator, Expr, ExprKind, ExternalDeclaration,
+        ForInit, FunctionDefinition, GenericAssociation, Initializer, Qualifiers, RecordKind, Stmt,
+        StmtKind, TranslationUnit, TypeName, UnaryOp,
+    },
+    constant::{evaluate, Constants},
+    lexer::{FloatSuffix, Span},
+    sema::{Analysis, SymbolKind},
+    types::{merge, FloatKind, IntegerKind, QualifiedType, Type, TypeEnv},
+};
+
+// where a value is converted as if by assignment
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Conversion {
+    Assignment,
+    Initialization,
+    Return,
+    // the parameter, counted from 1
+    Argument(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TypeckErrorKind {
+    InvalidOperands {
+        op: &'static str,
+        lhs: QualifiedType,
+        rhs: QualifiedType,
+    },
+    InvalidOperand {
+        op: &'static str,
+        ty: QualifiedType,
+    },
+    Incompatible {
+        conversion: Conversion,
+        from: QualifiedType,
+        to: QualifiedType,
+    },
+    DiscardsQualifiers {
+        conversion: Conversion,
+        from: QualifiedType,
+        to: QualifiedType,
+    },
+    NotAssignable,
+    ReadOnly(QualifiedType),
+    AddressOfRvalue,
+    AddressOfBitField,
+    NotAFunction(QualifiedType),
+    ArgumentCount {
+        expected: usize,
+        found: usize,
+    },
+    NotARecord(QualifiedType),
+    NoMember {
+        name: String,
+        ty: QualifiedType,
+    },
+    NotSubscriptable(QualifiedType),
+    NotScalar(QualifiedType),
+    NotInteger(QualifiedType),
+    Incomplete(QualifiedType),
+    InvalidCast {
+        from: QualifiedType,
+        to: QualifiedType,
+    },
+    ReturnValue,
+    MissingReturnValue,
+    LiteralTooLarge,
+    NoAssociation(QualifiedType),
+    DuplicateAssociation(QualifiedType),
+    DuplicateDefault,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TypeckError {
+    pub kind: TypeckErrorKind,
+    pub span: Span,
+}
+
+fn describe(conversion: Conversion, from: &QualifiedType, to: &QualifiedType) -> String {
+    match conversion {
+        Conversion::Assignment => format!("assigning to '{to}' from '{from}'"),
+        Conversion::Initialization => format!("initializing '{to}' with '{from}'"),
+        Conversion::Return => format!("returning '{from}' from a function returning '{to}'"),
+        Conversion::Argument(n) => format!("passing '{from}' to parameter {n} of type '{to}'"),
+    }
+}
+
+impl fmt::Display for TypeckErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeckErrorKind::InvalidOperands { op, lhs, rhs } => {
+                write!(f, "invalid operands to '{op}' ('{lhs}' and '{rhs}')")
+            }
+            TypeckErrorKind::InvalidOperand { op, ty } => {
+                write!(f, "invalid operand to '{op}' of type '{ty}'")
+            }
+            TypeckErrorKind::Incompatible {
+                conversion,
+                from,
+                to,
+            } => write!(f, "incompatible types {}", describe(*conversion, from, to)),
+            TypeckErrorKind::DiscardsQualifiers {
+                conversion,
+                from,
+                to,
+            } => write!(f, "{} discards qualifiers", describe(*conversion, from, to)),
+            TypeckErrorKind::NotAssignable => write!(f, "expression is not assignable"),
+            TypeckErrorKind::ReadOnly(ty) => {
+                write!(f, "cannot modify an object of const-qualified type '{ty}'")
+            }
+            TypeckErrorKind::AddressOfRvalue => write!(f, "cannot take the address of an rvalue"),
+            TypeckErrorKind::AddressOfBitField => {
+                write!(f, "cannot take the address of a bit-field")
+            }
+            TypeckErrorKind::NotAFunction(ty) => {
+                write!(f, "called object of type '{ty}' is not a function")
+            }
+            TypeckErrorKind::ArgumentCount { expected, found } => {
+                let amount = if found < expected { "few" } else { "many" };
+                write!(
+                    f,
+                    "too {amount} arguments to function call, expected {expected}, found {found}"
+                )
+            }
+            TypeckErrorKind::NotARecord(ty) => {
+                write!(
+                    f,
+                    "member reference base type '{ty}' is not a struct or union"
+                )
+            }
+            TypeckErrorKind::NoMember { name, ty } => {
+                write!(f, "no member named '{name}' in '{ty}'")
+            }
+            TypeckErrorKind::NotSubscriptable(ty) => {
+                write!(
+                    f,
+                    "subscripted value of type '{ty}' is not an array or pointer"
+                )
+            }
+            TypeckErrorKind::NotScalar(ty) => {
+                write!(f, "'{ty}' used where a scalar is required")
+            }
+            TypeckErrorKind::NotInteger(ty) => {
+                write!(f, "'{ty}' used where an integer is required")
+            }
+            TypeckErrorKind::Incomplete(ty) => write!(f, "invalid use of incomplete type '{ty}'"),
+            TypeckErrorKind::InvalidCast { from, to } => {
+                write!(f, "cannot cast '{from}' to '{to}'")
+            }
+            TypeckErrorKind::ReturnValue => write!(f, "void function should not return a value"),
+            TypeckErrorKind::MissingReturnValue => {
+                write!(f, "non-void function should return a value")
+            }
+            TypeckErrorKind::LiteralTooLarge => {
+                write!(f, "integer literal is too large for any signed type")
+            }
+            TypeckErrorKind::NoAssociation(ty) => {
+                write!(f, "'{ty}' matches no association of the _Generic selection")
+            }
+            TypeckErrorKind::DuplicateAssociation(ty) => {
+                write!(f, "'{ty}' is compatible with an earlier association type")
+            }
+            TypeckErrorKind::DuplicateDefault => {
+                write!(f, "more than one default association in _Generic")
+            }
+        }
+    }
+}
+
+impl fmt::Display for TypeckError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.kind)
+    }
+}
+
+impl error::Error for TypeckError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Category {
+    // designates an object: variables, `*p`, `a[i]`, `s.m`, `p->m` and
+    // string literals
+    Lvalue,
+    Rvalue,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Typed {
+    pub ty: QualifiedType,
+    pub category: Category,
+}
+
+// what the checker found, keyed by the span of each expression
+#[derive(Debug, Clone, Default)]
+pub struct Typing {
+    pub types: HashMap<Span, Typed>,
+    // the type an expression's value is implicitly converted to, where it
+    // differs from its own: decay of arrays and functions, promotions, the
+    // usual arithmetic conversions and conversions as if by assignment
+    pub conversions: HashMap<Span, QualifiedType>,
+}
+
+impl Typing {
+    pub fn type_of(&self, expr: &Expr) -> Option<&Typed> {
+        self.types.get(&expr.span)
+    }
+    // the type of the value actually used
+    pub fn converted(&self, expr: &Expr) -> Option<&QualifiedType> {
+        let converted = self.conversions.get(&expr.span);
+        converted.or_else(|| Some(&self.type_of(expr)?.ty))
+    }
+}
+
+fn integer(kind: IntegerKind) -> QualifiedType {
+    Type::Integer(kind).into()
+}
+
+fn unqualified(ty: &QualifiedType) -> QualifiedType {
+    ty.ty.clone().into()
+}
+
+fn pointee(ty: &QualifiedType) -> Option<&QualifiedType> {
+    match &ty.ty {
+        Type::Pointer(to) => Some(to),
+        _ => None,
+    }
+}
+
+fn is_integer(ty: &Type) -> bool {
+    matches!(ty, Type::Integer(_) | Type::Enum { .. })
+}
+
+fn is_real(ty: &Type) -> bool {
+    is_integer(ty) || matches!(ty, Type::Float(_))
+}
+
+fn is_arithmetic(ty: &Type) -> bool {
+    is_real(ty) || matches!(ty, Type::Complex(_))
+}
+
+fn is_scalar(ty: &Type) -> bool {
+    is_arithmetic(ty) || matches!(ty, Type::Pointer(_))
+}
+
+// `outer` has every qualifier of `inner`
+fn contains(outer: Qualifiers, inner: Qualifiers) -> bool {
+    merge(outer, inner) == outer
+}
+
+struct Checker<'a> {
+    analysis: &'a Analysis,
+    typing: Typing,
+    // member expressions naming bit-fields, whose address can't be taken
+    bit_fields: HashSet<Span>,
+    // the result type of the function being checked
+    result: Option<QualifiedType>,
+    errors: Vec<TypeckError>,
+}
+
+impl Checker<'_> {
+    fn error(&mut self, kind: TypeckErrorKind, span: Span) {
+        self.errors.push(TypeckError { kind, span });
+    }
+    fn convert(&mut self, expr: &Expr, to: QualifiedType) -> QualifiedType {
+        match self.typing.types.get(&expr.span) {
+            Some(typed) if typed.ty == to => {
+                self.typing.conversions.remove(&expr.span);
+            }
+            _ => {
+                self.typing.conversions.insert(expr.span, to.clone());
+            }
+        }
+        to
+    }
+    // lvalue conversion: arrays and functions decay to pointers and the
+    // value loses its qualifiers
+    fn decay(&mut self, expr: &Expr, typed: &Typed) -> QualifiedType {
+        let ty = match &typed.ty.ty {
+            Type::Array(element, _) => Type::Pointer(element.clone()),
+            Type::Function(_) => Type::Pointer(Box::new(typed.ty.clone())),
+            ty => ty.clone(),
+        };
+        self.convert(expr, ty.into())
+    }
+    fn value(&mut self, expr: &Expr) -> Option<QualifiedType> {
+        let typed = self.expression(expr)?;
+        Some(self.decay(expr, &typed))
+    }
+    fn promote(&self, ty: &QualifiedType) -> QualifiedType {
+        self.analysis.env.promote(ty)
+    }
+    fn promoted(&mut self, expr: &Expr, ty: &QualifiedType) -> QualifiedType {
+        let promoted = self.promote(ty);
+        self.convert(expr, promoted)
+    }
+    fn arithmetic(
+        &mut self,
+        lhs: &Expr,
+        l: &QualifiedType,
+        rhs: &Expr,
+        r: &QualifiedType,
+    ) -> QualifiedType {
+        let ty = self.analysis.env.common(l, r);
+        self.convert(lhs, ty.clone());
+        self.convert(rhs, ty)
+    }
+    // a pointer to a complete object type, which pointer arithmetic needs
+    fn is_object_pointer(&self, ty: &QualifiedType) -> bool {
+        pointee(ty).is_some_and(|to| self.analysis.env.layout(to).is_some())
+    }
+    // the spellings of null pointer constants: an integer constant
+    // expression of value 0, and such an expression cast to `void *`
+    fn is_null(&self, expr: &Expr) -> bool {
+        let Some(typed) = self.typing.types.get(&expr.span) else {
+            return false;
+        };
+        let void = QualifiedType::from(Type::Void);
+        match (&expr.kind, &typed.ty.ty) {
+            (_, ty) if is_integer(ty) => evaluate(&mut &*self, expr).is_some_and(|c| c.value == 0),
+            (ExprKind::Cast(_, operand), Type::Pointer(to)) if **to == void => {
+                self.is_null(operand)
+            }
+            _ => false,
+        }
+    }
+    // a modifiable lvalue, as assignments and increments need
+    fn modifiable(&mut self, typed: &Typed, span: Span) -> bool {
+        let kind = match &typed.ty.ty {
+            _ if typed.category == Category::Rvalue => TypeckErrorKind::NotAssignable,
+            Type::Array(..) | Type::Function(_) => TypeckErrorKind::NotAssignable,
+            _ if typed.ty.qualifiers.is_const => TypeckErrorKind::ReadOnly(typed.ty.clone()),
+            _ if self.analysis.env.layout(&typed.ty).is_none() => {
+                TypeckErrorKind::Incomplete(typed.ty.clone())
+            }
+            _ => return true,
+        };
+        self.error(kind, span);
+        false
+    }
+    // converts the value of `expr`, of type `from`, as if assigned to an
+    // object of type `to`
+    fn assign(
+        &mut self,
+        expr: &Expr,
+        from: &QualifiedType,
+        to: QualifiedType,
+        conversion: Conversion,
+    ) {
+        match self.unassignable(expr, from, &to, conversion) {
+            Some(kind) => self.error(kind, expr.span),
+            None => {
+                self.convert(expr, unqualified(&to));
+            }
+        }
+    }
+    // why a value of type `from` can't be assigned to an object of type
+    // `to`; `expr` is the value, which may be a null pointer constant
+    fn unassignable(
+        &self,
+        expr: &Expr,
+        from: &QualifiedType,
+        to: &QualifiedType,
+        conversion: Conversion,
+    ) -> Option<TypeckErrorKind> {
+        let (compatible, qualified) = match (&to.ty, &from.ty) {
+            (a, b) if is_arithmetic(a) && is_arithmetic(b) => (true, true),
+            (Type::Record { .. }, _) => (unqualified(to).is_compatible(from), true),
+            (Type::Integer(IntegerKind::Bool), Type::Pointer(_)) => (true, true),
+            (Type::Pointer(_), _) if self.is_null(expr) => (true, true),
+            (Type::Pointer(a), Type::Pointer(b)) => {
+                let void = |ty: &QualifiedType, other: &QualifiedType| {
+                    ty.ty == Type::Void && !matches!(other.ty, Type::Function(_))
+                };
+                let compatible =
+                    unqualified(a).is_compatible(&unqualified(b)) || void(a, b) || void(b, a);
+                (compatible, contains(a.qualifiers, b.qualifiers))
+            }
+            _ => (false, true),
+        };
+        let (from, to) = (from.clone(), unqualified(to));
+        match (compatible, qualified) {
+            (false, _) => Some(TypeckErrorKind::Incompatible {
+                conversion,
+                from,
+                to,
+            }),
+            (true, false) => Some(TypeckErrorKind::DiscardsQualifiers {
+                conversion,
+                from,
+                to,
+            }),
+            (true, true) => None,
+        }
+    }
+    fn condition(&mut self, expr: &Expr) {
+        if let Some(ty) = self.value(expr) {
+            if !is_scalar(&ty.ty) {
+                self.error(TypeckErrorKind::NotScalar(ty), expr.span);
+            }
+        }
+    }
+    fn integer_value(&mut self, expr: &Expr) -> Option<QualifiedType> {
+        let ty = self.value(expr)?;
+        if !is_integer(&ty.ty) {
+            self.error(TypeckErrorKind::NotInteger(ty), expr.span);
+            return None;
+        }
+        Some(ty)
+    }
+    // sizeof needs a complete object type
+    fn sized(&mut self, ty: &QualifiedType, span: Span) {
+        if self.analysis.env.layout(ty).is_none() {
+            self.error(TypeckErrorKind::Incomplete(ty.clone()), span);
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Option<Typed> {
+        let (ty, category) = match &expr.kind {
+            ExprKind::Identifier(_) => {
+                let symbol = self.analysis.symbol(*self.analysis.uses.get(&expr.span)?);
+                match symbol.kind {
+                    SymbolKind::Constant(_) => (integer(IntegerKind::Int), Category::Rvalue),
+                    SymbolKind::Function => (symbol.ty.clone(), Category::Rvalue),
+                    SymbolKind::Typedef => return None,
+                    SymbolKind::Object | SymbolKind::Parameter => {
+                        (symbol.ty.clone(), Category::Lvalue)
+                    }
+                }
+            }
+            ExprKind::Integer(value) => {
+                // typed as GCC would so checking can go on
+                let kind = IntegerKind::of_literal(value).unwrap_or_else(|| {
+                    self.error(TypeckErrorKind::LiteralTooLarge, expr.span);
+                    IntegerKind::UnsignedLongLong
+                });
+                (integer(kind), Category::Rvalue)
+            }
+            ExprKind::Float(value) => {
+                let kind = match value.suffix {
+                    FloatSuffix::None => FloatKind::Double,
+                    FloatSuffix::Float => FloatKind::Float,
+                    FloatSuffix::Long => FloatKind::LongDouble,
+                };
+                (Type::Float(kind).into(), Category::Rvalue)
+            }
+            ExprKind::Character(_) => (integer(IntegerKind::Int), Category::Rvalue),
+            ExprKind::String(value) => {
+                let char = Box::new(integer(IntegerKind::Char));
+                let size = Some(value.len() as u64 + 1);
+                (Type::Array(char, size).into(), Category::Lvalue)
+            }
+            ExprKind::Unary(op, operand) => self.unary(*op, operand)?,
+            ExprKind::Binary(op, lhs, rhs) => {
+                let (l, r) = (self.value(lhs), self.value(rhs));
+                let ty = self.binary(*op, lhs, l?, rhs, r?, expr.span)?;
+                (ty, Category::Rvalue)
+            }
+            ExprKind::Assign(op, lhs, rhs) => {
+                let (target, r) = (self.expression(lhs), self.value(rhs));
+                let (target, r) = (target?, r?);
+                if !self.modifiable(&target, lhs.span) {
+                    return None;
+                }
+                let to = unqualified(&target.ty);
+                match op {
+                    None => self.assign(rhs, &r, to.clone(), Conversion::Assignment),
+                    // `a op= b` stores `a op b` back into a, so the result
+                    // must be assignable to it
+                    Some(op) => {
+                        let l = self.decay(lhs, &target);
+                        if let (BinaryOp::Add | BinaryOp::Sub, Type::Pointer(_)) = (op, &l.ty) {
+                            // a pointer only steps by an integer
+                            if !is_integer(&r.ty) {
+                                let op = match op {
+                                    BinaryOp::Add => "+=",
+                                    _ => "-=",
+                                };
+                                let kind = TypeckErrorKind::InvalidOperands { op, lhs: l, rhs: r };
+                                self.error(kind, expr.span);
+                                return None;
+                            }
+                        }
+                        let result = self.binary(*op, lhs, l, rhs, r, expr.span)?;
+                        let conversion = Conversion::Assignment;
+                        if let Some(kind) = self.unassignable(expr, &result, &to, conversion) {
+                            self.error(kind, expr.span);
+                            return None;
+                        }
+                    }
+                }
+                (to, Category::Rvalue)
+            }
+            ExprKind::Conditional(condition, then, otherwise) => {
+                self.condition(condition);
+                let (t, e) = (self.value(then), self.value(otherwise));
+                let (t, e) = (t?, e?);
+                let ty = match (&t.ty, &e.ty) {
+                    (a, b) if is_arithmetic(a) && is_arithmetic(b) => {
+                        Some(self.arithmetic(then, &t, otherwise, &e))
+                    }
+                    (Type::Void, Type::Void) => Some(t.clone()),
+                    (Type::Record { .. }, _) if t.is_compatible(&e) => Some(t.clone()),
+                    (Type::Pointer(_), _) if self.is_null(otherwise) => {
+                        Some(self.convert(otherwise, t.clone()))
+                    }
+                    (_, Type::Pointer(_)) if self.is_null(then) => {
+                        Some(self.convert(then, e.clone()))
+                    }
+                    // the result points to a type with the qualifiers of both
+                    (Type::Pointer(a), Type::Pointer(b)) => {
+                        let to = if unqualified(a).is_compatible(&unqualified(b)) {
+                            Some(a.ty.clone())
+                        } else if a.ty == Type::Void || b.ty == Type::Void {
+                            Some(Type::Void)
+                        } else {
+                            None
+                        };
+                        let qualifiers = merge(a.qualifiers, b.qualifiers);
+                        to.map(|ty| Type::Pointer(Box::new(QualifiedType { ty, qualifiers })))
+                            .map(QualifiedType::from)
+                    }
+                    _ => None,
+                };
+                match ty {
+                    Some(ty) => (ty, Category::Rvalue),
+                    None => {
+                        let kind = TypeckErrorKind::InvalidOperands {
+                            op: "?:",
+                            lhs: t,
+                            rhs: e,
+                        };
+                        self.error(kind, expr.span);
+                        return None;
+                    }
+                }
+            }
+            ExprKind::Comma(lhs, rhs) => {
+                self.expression(lhs);
+                (self.value(rhs)?, Category::Rvalue)
+            }
+            ExprKind::Call(callee, arguments) => {
+                let callee_ty = self.value(callee);
+                let values: Vec<_> = arguments.iter().map(|a| self.value(a)).collect();
+                let callee_ty = callee_ty?;
+                let function = match pointee(&callee_ty).map(|to| &to.ty) {
+                    Some(Type::Function(function)) => function.clone(),
+                    _ => {
+                        self.error(TypeckErrorKind::NotAFunction(callee_ty), callee.span);
+                        return None;
+                    }
+                };
+                let (expected, found) = (function.parameters.len(), arguments.len());
+                if function.prototyped
+                    && (found < expected || (found > expected && !function.variadic))
+                {
+                    let kind = TypeckErrorKind::ArgumentCount { expected, found };
+                    self.error(kind, expr.span);
+                }
+                for (i, (argument, value)) in arguments.iter().zip(values).enumerate() {
+                    let Some(value) = value else {
+                        continue;
+                    };
+                    match function.parameters.get(i) {
+                        Some(parameter) => {
+                            let conversion = Conversion::Argument(i + 1);
+                            self.assign(argument, &value, unqualified(parameter), conversion);
+                        }
+                        // the default argument promotions, for `...` and
+                        // functions without a prototype
+                        None => {
+                            let ty = match value.ty {
+                                Type::Float(FloatKind::Float) => {
+                                    Type::Float(FloatKind::Double).into()
+                                }
+                                _ => self.promote(&value),
+                            };
+                            self.convert(argument, ty);
+                        }
+                    }
+                }
+                (unqualified(&function.result), Category::Rvalue)
+            }
+            // either operand may be the pointer, as `a[i]` is `*(a + i)`
+            ExprKind::Index(base, index) => {
+                let (b, i) = (self.value(base), self.value(index));
+                let (b, i) = (b?, i?);
+                let element = if self.is_object_pointer(&b) && is_integer(&i.ty) {
+                    self.promoted(index, &i);
+                    pointee(&b)
+                } else if is_integer(&b.ty) && self.is_object_pointer(&i) {
+                    self.promoted(base, &b);
+                    pointee(&i)
+                } else {
+                    None
+                };
+                match element {
+                    Some(element) => (element.clone(), Category::Lvalue),
+                    None => {
+                        self.error(TypeckErrorKind::NotSubscriptable(b), base.span);
+                        return None;
+                    }
+                }
+            }
+            ExprKind::Member {
+                base,
+                member,
+                arrow,
+            } => {
+                let (record, category) = if *arrow {
+                    let ty = self.value(base)?;
+                    match pointee(&ty) {
+                        Some(to) => (to.clone(), Category::Lvalue),
+                        None => {
+                            let kind = TypeckErrorKind::InvalidOperand { op: "->", ty };
+                            self.error(kind, base.span);
+                            return None;
+                        }
+                    }
+                } else {
+                    let typed = self.expression(base)?;
+                    (typed.ty, typed.category)
+                };
+                let Type::Record { id, .. } = record.ty else {
+                    self.error(TypeckErrorKind::NotARecord(record), base.span);
+                    return None;
+                };
+                if self.analysis.env.record(id).members.is_none() {
+                    self.error(TypeckErrorKind::Incomplete(record), base.span);
+                    return None;
+                }
+                let Some(found) = self.analysis.env.member(id, &member.node) else {
+                    let name = member.node.clone();
+                    let kind = TypeckErrorKind::NoMember { name, ty: record };
+                    self.error(kind, member.span);
+                    return None;
+                };
+                if found.bit_field.is_some() {
+                    self.bit_fields.insert(expr.span);
+                }
+                // members of a const struct are const
+                let ty = QualifiedType {
+                    ty: found.ty.ty,
+                    qualifiers: merge(found.ty.qualifiers, record.qualifiers),
+                };
+                (ty, category)
+            }
+            ExprKind::Cast(name, operand) => {
+                let from = self.value(operand);
+                let to = self.analysis.type_names.get(&name.span)?.clone();
+                let from = from?;
+                let pointer_and_float = |a: &Type, b: &Type| {
+                    matches!(a, Type::Pointer(_)) && matches!(b, Type::Float(_) | Type::Complex(_))
+                };
+                let valid = to.ty == Type::Void
+                    || (is_scalar(&to.ty)
+                        && is_scalar(&from.ty)
+                        && !pointer_and_float(&to.ty, &from.ty)
+                        && !pointer_and_float(&from.ty, &to.ty));
+                if !valid {
+                    self.error(TypeckErrorKind::InvalidCast { from, to }, expr.span);
+                    return None;
+                }
+                (unqualified(&to), Category::Rvalue)
+            }
+            ExprKind::SizeofExpr(operand) => {
+                let typed = self.expression(operand)?;
+                self.sized(&typed.ty, operand.span);
+                (integer(IntegerKind::UnsignedLong), Category::Rvalue)
+            }
+            // an object of its own, so an lvalue
+            ExprKind::CompoundLiteral(name, initializer) => {
+                let ty = self.analysis.type_names.get(&name.span)?.clone();
+                if !matches!(ty.ty, Type::Array(_, None)) {
+                    self.sized(&ty, name.span);
+                }
+                self.initializer(initializer, Some(&ty));
+                (ty, Category::Lvalue)
+            }
+            ExprKind::SizeofType(name) | ExprKind::AlignofType(name) => {
+                let ty = self.analysis.type_names.get(&name.span)?.clone();
+                self.sized(&ty, name.span);
+                (integer(IntegerKind::UnsignedLong), Category::Rvalue)
+            }
+            ExprKind::Generic {
+                control,
+                associations,
+            } => return self.generic(control, associations, expr.span),
+        };
+        let typed = Typed { ty, category };
+        self.typing.types.insert(expr.span, typed.clone());
+        Some(typed)
+    }
+    fn unary(&mut self, op: UnaryOp, operand: &Expr) -> Option<(QualifiedType, Category)> {
+        let invalid = |ty: QualifiedType| TypeckErrorKind::InvalidOperand {
+            op: op.symbol(),
+            ty,
+        };
+        match op {
+            UnaryOp::AddressOf => {
+                let typed = self.expression(operand)?;
+                let function = matches!(typed.ty.ty, Type::Function(_));
+                let kind = if self.bit_fields.contains(&operand.span) {
+                    TypeckErrorKind::AddressOfBitField
+                } else if typed.category == Category::Rvalue && !function {
+                    TypeckErrorKind::AddressOfRvalue
+                } else {
+                    return Some((Type::Pointer(Box::new(typed.ty)).into(), Category::Rvalue));
+                };
+                self.error(kind, operand.span);
+                None
+            }
+            UnaryOp::PreIncrement
+            | UnaryOp::PreDecrement
+            | UnaryOp::PostIncrement
+            | UnaryOp::PostDecrement => {
+                let typed = self.expression(operand)?;
+                if !self.modifiable(&typed, operand.span) {
+                    return None;
+                }
+                let ty = self.decay(operand, &typed);
+                if !is_real(&ty.ty) && !self.is_object_pointer(&ty) {
+                    self.error(invalid(ty), operand.span);
+                    return None;
+                }
+                Some((ty, Category::Rvalue))
+            }
+            UnaryOp::Deref => {
+                let ty = self.value(operand)?;
+                match pointee(&ty) {
+                    Some(to) if matches!(to.ty, Type::Function(_)) => {
+                        Some((to.clone(), Category::Rvalue))
+                    }
+                    Some(to) => Some((to.clone(), Category::Lvalue)),
+                    None => {
+                        self.error(invalid(ty), operand.span);
+                        None
+                    }
+                }
+            }
+            UnaryOp::Plus | UnaryOp::Minus | UnaryOp::BitNot | UnaryOp::Not => {
+                let ty = self.value(operand)?;
+                let valid = match op {
+                    UnaryOp::BitNot => is_integer(&ty.ty),
+                    UnaryOp::Not => is_scalar(&ty.ty),
+                    _ => is_arithmetic(&ty.ty),
+                };
+                if !valid {
+                    self.error(invalid(ty), operand.span);
+                    return None;
+                }
+                match op {
+                    UnaryOp::Not => Some((integer(IntegerKind::Int), Category::Rvalue)),
+                    _ => Some((self.promoted(operand, &ty), Category::Rvalue)),
+                }
+            }
+        }
+    }
+    // the association whose type is compatible with the unqualified type
+    // of the control's value, or else the default one; the control itself
+    // is never evaluated
+    fn generic(
+        &mut self,
+        control: &Expr,
+        associations: &[GenericAssociation],
+        span: Span,
+    ) -> Option<Typed> {
+        let typed = self.expression(control)?;
+        let controlling = match &typed.ty.ty {
+            Type::Array(element, _) => Type::Pointer(element.clone()).into(),
+            Type::Function(_) => Type::Pointer(Box::new(typed.ty.clone())).into(),
+            _ => unqualified(&typed.ty),
+        };
+        let mut seen: Vec<QualifiedType> = Vec::new();
+        let (mut selected, mut default) = (None, None);
+        for (i, association) in associations.iter().enumerate() {
+            self.expression(&association.expr);
+            let Some(name) = &association.ty else {
+                match default {
+                    Some(_) => self.error(TypeckErrorKind::DuplicateDefault, association.expr.span),
+                    None => default = Some(i),
+                }
+                continue;
+            };
+            let Some(ty) = self.analysis.type_names.get(&name.span).cloned() else {
+                continue;
+            };
+            self.sized(&ty, name.span);
+            if seen.iter().any(|other| other.is_compatible(&ty)) {
+                self.error(TypeckErrorKind::DuplicateAssociation(ty), name.span);
+                continue;
+            }
+            if selected.is_none() && ty.is_compatible(&controlling) {
+                selected = Some(i);
+            }
+            seen.push(ty);
+        }
+        let Some(i) = selected.or(default) else {
+            self.error(TypeckErrorKind::NoAssociation(controlling), span);
+            return None;
+        };
+        let typed = self.typing.types.get(&associations[i].expr.span)?.clone();
+        self.typing.types.insert(span, typed.clone());
+        Some(typed)
+    }
+    // `l` and `r` are the types of the operands' values
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        lhs: &Expr,
+        l: QualifiedType,
+        rhs: &Expr,
+        r: QualifiedType,
+        span: Span,
+    ) -> Option<QualifiedType> {
+        let int = integer(IntegerKind::Int);
+        let both = |test: fn(&Type) -> bool| test(&l.ty) && test(&r.ty);
+        let pointers = matches!((&l.ty, &r.ty), (Type::Pointer(_), Type::Pointer(_)));
+        let compatible = pointers && {
+            let (a, b) = (pointee(&l).map(unqualified), pointee(&r).map(unqualified));
+            a.zip(b).is_some_and(|(a, b)| a.is_compatible(&b))
+        };
+        let ty = match op {
+            BinaryOp::Mul | BinaryOp::Div if both(is_arithmetic) => {
+                Some(self.arithmetic(lhs, &l, rhs, &r))
+            }
+            BinaryOp::Mod | BinaryOp::BitAnd | BinaryOp::Xor | BinaryOp::BitOr
+                if both(is_integer) =>
+            {
+                Some(self.arithmetic(lhs, &l, rhs, &r))
+            }
+            BinaryOp::Add | BinaryOp::Sub if both(is_arithmetic) => {
+                Some(self.arithmetic(lhs, &l, rhs, &r))
+            }
+            BinaryOp::Add | BinaryOp::Sub if self.is_object_pointer(&l) && is_integer(&r.ty) => {
+                self.promoted(rhs, &r);
+                Some(l.clone())
+            }
+            BinaryOp::Add if is_integer(&l.ty) && self.is_object_pointer(&r) => {
+                self.promoted(lhs, &l);
+                Some(r.clone())
+            }
+            // the difference of two pointers is a ptrdiff_t
+            BinaryOp::Sub if compatible && self.is_object_pointer(&l) => {
+                Some(integer(IntegerKind::Long))
+            }
+            BinaryOp::ShiftLeft | BinaryOp::ShiftRight if both(is_integer) => {
+                self.promoted(rhs, &r);
+                Some(self.promoted(lhs, &l))
+            }
+            BinaryOp::Less | BinaryOp::Greater | BinaryOp::LessEqual | BinaryOp::GreaterEqual
+                if both(is_real) =>
+            {
+                self.arithmetic(lhs, &l, rhs, &r);
+                Some(int)
+            }
+            BinaryOp::Less | BinaryOp::Greater | BinaryOp::LessEqual | BinaryOp::GreaterEqual
+                if compatible =>
+            {
+                Some(int)
+            }
+            BinaryOp::Equal | BinaryOp::NotEqual if both(is_arithmetic) => {
+                self.arithmetic(lhs, &l, rhs, &r);
+                Some(int)
+            }
+            BinaryOp::Equal | BinaryOp::NotEqual => {
+                let void = |ty: &QualifiedType| pointee(ty).is_some_and(|to| to.ty == Type::Void);
+                let comparable = compatible || (pointers && (void(&l) || void(&r)));
+                if matches!(l.ty, Type::Pointer(_)) && self.is_null(rhs) {
+                    self.convert(rhs, l.clone());
+                    Some(int)
+                } else if matches!(r.ty, Type::Pointer(_)) && self.is_null(lhs) {
+                    self.convert(lhs, r.clone());
+                    Some(int)
+                } else {
+                    comparable.then_some(int)
+                }
+            }
+            BinaryOp::And | BinaryOp::Or if both(is_scalar) => Some(int),
+            _ => None,
+        };
+        if ty.is_none() {
+            let kind = TypeckErrorKind::InvalidOperands {
+                op: op.symbol(),
+                lhs: l,
+                rhs: r,
+            };
+            self.error(kind, span);
+        }
+        ty
+    }
+
+    // the type of the element or member initialized by the `index`th item
+    // of a list without designators
+    fn element(&self, ty: &QualifiedType, index: usize) -> Option<QualifiedType> {
+        match &ty.ty {
+            Type::Array(element, _) => Some(element.as_ref().clone()),
+            Type::Record { kind, id, .. } => {
+                let members = self.analysis.env.record(*id).members.as_ref()?;
+                match kind {
+                    RecordKind::Union if index > 0 => None,
+                    _ => members.get(index).map(|member| member.ty.clone()),
+                }
+            }
+            _ if index == 0 => Some(ty.clone()),
+            _ => None,
+        }
+    }
+    // moves `next` past a member designator so the items after it follow
+    // on from that member
+    fn designated(
+        &mut self,
+        ty: &QualifiedType,
+        designator: &Designator,
+        next: &mut usize,
+    ) -> Option<QualifiedType> {
+        match (designator, &ty.ty) {
+            (Designator::Index(index), Type::Array(element, _)) => {
+                self.integer_value(index);
+                Some(element.as_ref().clone())
+            }
+            (Designator::Member(name), Type::Record { id, .. }) => {
+                let members = self.analysis.env.record(*id).members.as_ref()?;
+                let position = members
+                    .iter()
+                    .position(|member| member.name.as_deref() == Some(&name.node));
+                if let Some(position) = position {
+                    *next = position;
+                }
+                let found = self.analysis.env.member(*id, &name.node);
+                if found.is_none() {
+                    let kind = TypeckErrorKind::NoMember {
+                        name: name.node.clone(),
+                        ty: ty.clone(),
+                    };
+                    self.error(kind, name.span);
+                }
+                Some(found?.ty)
+            }
+            (Designator::Index(index), _) => {
+                self.value(index);
+                None
+            }
+            (Designator::Member(_), _) => None,
+        }
+    }
+    // brace elision is not modelled, so an expression meeting an aggregate
+    // in a list is only checked on its own, as is one without a target
+    fn initializer(&mut self, initializer: &Initializer, ty: Option<&QualifiedType>) {
+        match initializer {
+            Initializer::Expression(expr) => {
+                let Some(value) = self.value(expr) else {
+                    return;
+                };
+                match ty {
+                    Some(ty) if matches!(ty.ty, Type::Array(..)) => {}
+                    Some(ty)
+                        if matches!(ty.ty, Type::Record { .. })
+                            && !unqualified(ty).is_compatible(&value) => {}
+                    Some(ty) => self.assign(expr, &value, ty.clone(), Conversion::Initialization),
+                    None => {}
+                }
+            }
+            Initializer::List { items, .. } => {
+                let mut next = 0;
+                for (designators, item) in items {
+                    let mut target = match (ty, designators.first()) {
+                        (Some(ty), Some(designator)) => self.designated(ty, designator, &mut next),
+                        (Some(ty), None) => self.element(ty, next),
+                        (None, _) => None,
+                    };
+                    for designator in designators.iter().skip(1) {
+                        target = match target {
+                            Some(ty) => self.designated(&ty, designator, &mut 0),
+                            None => None,
+                        };
+                    }
+                    next += 1;
+                    self.initializer(item, target.as_ref());
+                }
+            }
+        }
+    }
+    fn declaration(&mut self, declaration: &Declaration) {
+        for init in &declaration.declarators {
+            let Some(initializer) = &init.initializer else {
+                continue;
+            };
+            let name = init.declarator.name.as_ref();
+            let symbol = name.and_then(|name| self.analysis.declarations.get(&name.span));
+            let ty = symbol.map(|&id| self.analysis.symbol(id).ty.clone());
+            self.initializer(initializer, ty.as_ref());
+        }
+    }
+    fn function(&mut self, function: &FunctionDefinition) {
+        let name = function.declarator.name.as_ref();
+        let symbol = name.and_then(|name| self.analysis.resolve(name.span));
+        self.result = match symbol.map(|symbol| &symbol.ty.ty) {
+            Some(Type::Function(function)) => Some(unqualified(&function.result)),
+            _ => None,
+        };
+        self.block(&function.body);
+        self.result = None;
+    }
+    fn block(&mut self, block: &Block) {
+        for item in &block.items {
+            match item {
+                BlockItem::Declaration(declaration) => self.declaration(declaration),
+                BlockItem::Statement(stmt) => self.statement(stmt),
+                BlockItem::StaticAssert(_) => {}
+            }
+        }
+    }
+    fn statement(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Compound(block) => self.block(block),
+            StmtKind::Expression(expr) => {
+                if let Some(expr) = expr {
+                    self.expression(expr);
+                }
+            }
+            StmtKind::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                self.condition(condition);
+                self.statement(then);
+                if let Some(otherwise) = otherwise {
+                    self.statement(otherwise);
+                }
+            }
+            StmtKind::While { condition, body } | StmtKind::DoWhile { body, condition } => {
+                self.condition(condition);
+                self.statement(body);
+            }
+            StmtKind::For {
+                init,
+                condition,
+                step,
+                body,
+            } => {
+                match init.as_deref() {
+                    Some(ForInit::Declaration(declaration)) => self.declaration(declaration),
+                    Some(ForInit::Expression(expr)) => {
+                        self.expression(expr);
+                    }
+                    None => {}
+                }
+                if let Some(condition) = condition {
+                    self.condition(condition);
+                }
+                if let Some(step) = step {
+                    self.expression(step);
+                }
+                self.statement(body);
+            }
+            StmtKind::Switch { condition, body } => {
+                if let Some(ty) = self.integer_value(condition) {
+                    self.promoted(condition, &ty);
+                }
+                self.statement(body);
+            }
+            StmtKind::Case { value, body } => {
+                self.integer_value(value);
+                self.statement(body);
+            }
+            StmtKind::Default(body) | StmtKind::Labeled { body, .. } => self.statement(body),
+            StmtKind::Return(value) => {
+                let result = self.result.clone();
+                match (value, result) {
+                    (Some(expr), Some(result)) => {
+                        let Some(ty) = self.value(expr) else {
+                            return;
+                        };
+                        match (result.ty == Type::Void, ty.ty == Type::Void) {
+                            (true, true) => {}
+                            (true, false) => self.error(TypeckErrorKind::ReturnValue, expr.span),
+                            (false, _) => self.assign(expr, &ty, result, Conversion::Return),
+                        }
+                    }
+                    (Some(expr), None) => {
+                        self.value(expr);
+                    }
+                    (None, Some(result)) if result.ty != Type::Void => {
+                        self.error(TypeckErrorKind::MissingReturnValue, stmt.span)
+                    }
+                    (None, _) => {}
+                }
+            }
+            StmtKind::Goto(_) | StmtKind::Break | StmtKind::Continue => {}
+        }
+    }
+}
+
+// integer constant expressions see the types the checker has already
+// given their operands; only a shared borrow is needed, so the trait is
+// implemented for the reference
+impl Constants for &Checker<'_> {
+    fn env(&self) -> &TypeEnv {
+        &self.analysis.env
+    }
+    fn enumerator(&self, expr: &Expr) -> Option<i64> {
+        match self.analysis.resolve(expr.span)?.kind {
+            SymbolKind::Constant(value) => Some(value),
+            _ => None,
+        }
+    }
+    fn type_name(&mut self, name: &TypeName) -> Option<QualifiedType> {
+        self.analysis.type_names.get(&name.span).cloned()
+    }
+    fn type_of(&mut self, expr: &Expr) -> Option<QualifiedType> {
+        self.typing
+            .types
+            .get(&expr.span)
+            .map(|typed| typed.ty.clone())
+    }
+}
+
+// types every expression of a translation unit that `sema::analyze`
+// accepted; errors are collected over the whole unit, in source order
+pub fn check(unit: &TranslationUnit, analysis: &Analysis) -> Result<Typing, Vec<TypeckError>> {
+    let mut checker = Checker {
+        analysis,
+        typing: Typing::default(),
+        bit_fields: HashSet::new(),
+        result: None,
+        errors: Vec::new(),
+    };
+    for item in &unit.items {
+        match item {
+            ExternalDeclaration::Declaration(declaration) => checker.declaration(declaration),
+            ExternalDeclaration::Function(function) => checker.function(function),
+            ExternalDeclaration::StaticAssert(_) => {}
+        }
+    }
+    match checker.errors.is_empty() {
+        true => Ok(checker.typing),
+        false => {
+            checker.errors.sort_by_key(|e| e.span.offset);
+            Err(checker.errors)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check, TypeckErrorKind};
+    use crate::{
+        ast::{BlockItem, ExprKind, ExternalDeclaration, StmtKind},
+        sema, syntax,
+    };
+
+    fn errors(source: &str) -> Vec<TypeckErrorKind> {
+        let unit = syntax::parse(source).unwrap();
+        let analysis = sema::analyze(&unit).unwrap();
+        match check(&unit, &analysis) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|e| e.kind).collect(),
+        }
+    }
+
+    // the type of each expression statement in the last function, with the
+    // types its operands are converted to when it is a binary operation
+    fn types(source: &str) -> Vec<String> {
+        let unit = syntax::parse(source).unwrap();
+        let analysis = sema::analyze(&unit).unwrap();
+        let typing = check(&unit, &analysis).unwrap();
+        let Some(ExternalDeclaration::Function(function)) = unit.items.last() else {
+            panic!("no function last");
+        };
+        let mut types = Vec::new();
+        for item in &function.body.items {
+            let BlockItem::Statement(stmt) = item else {
+                continue;
+            };
+            let StmtKind::Expression(Some(expr)) = &stmt.kind else {
+                continue;
+            };
+            let ty = &typing.type_of(expr).unwrap().ty;
+            types.push(match &expr.kind {
+                ExprKind::Binary(_, lhs, rhs) => {
+                    let lhs = typing.converted(lhs).unwrap();
+                    let rhs = typing.converted(rhs).unwrap();
+                    format!("{ty} = {lhs}, {rhs}")
+                }
+                _ => ty.to_string(),
+            });
+        }
+        types
+    }
+
+    #[test]
+    fn null_pointer_constants_are_integer_constant_expressions() {
+        let source = "
+            enum { ZERO };
+            void f(int *p, int n) {
+                p = 0;
+                p = 1 - 1;
+                p = ZERO;
+                p = sizeof(int) - 4;
+                p = (void *)(2 * 0);
+                p = n ? p : 0;
+                if (p == 0 || 0 != p) {}
+            }
+        ";
+        assert_eq!(errors(source), []);
+        let source = "void f(int *p, int n) { p = 1; p = 1 - 1.0; p = n - n; p = (char)256 + 1; }";
+        let errors = errors(source);
+        assert_eq!(errors.len(), 4);
+        assert!(errors
+            .iter()
+            .all(|e| matches!(e, TypeckErrorKind::Incompatible { .. })));
+    }
+
+    #[test]
+    fn operands_are_promoted_and_converted() {
+        let source = "
+            void f(char c, unsigned char uc, short s, int i, unsigned u, long l,
+                   unsigned long ul, long long ll, float x, double d, _Bool b) {
+                -c;
+                ~uc;
+                +b;
+                c + s;
+                c << l;
+                i + u;
+                u + l;
+                ul + ll;
+                i < u;
+                l + x;
+                x + d;
+                b ? c : s;
+            }
+        ";
+        let expected = [
+            "int",
+            "int",
+            "int",
+            "int = int, int",
+            "int = int, long",
+            "unsigned int = unsigned int, unsigned int",
+            "long = long, long",
+            "unsigned long long = unsigned long long, unsigned long long",
+            "int = unsigned int, unsigned int",
+            "float = float, float",
+            "double = double, double",
+            "int",
+        ];
+        assert_eq!(types(source), expected);
+    }
+
+    #[test]
+    fn qualifiers_are_kept_through_conversions() {
+        let source = "
+            struct s { int m; };
+            void g(int *p);
+            int *f(const int *p, const int n, const struct s r, int *q) {
+                // adding qualifiers is fine
+                const int *ok = q;
+                int *a = p;
+                q = p;
+                g(p);
+                n = 1;
+                r.m = 2;
+                return p;
+            }
+        ";
+        let errors: Vec<_> = errors(source).iter().map(|e| e.to_string()).collect();
+        let expected = [
+            "initializing 'int *' with 'const int *' discards qualifiers",
+            "assigning to 'int *' from 'const int *' discards qualifiers",
+            "passing 'const int *' to parameter 1 of type 'int *' discards qualifiers",
+            "cannot modify an object of const-qualified type 'const int'",
+            "cannot modify an object of const-qualified type 'const int'",
+            "returning 'const int *' from a function returning 'int *' discards qualifiers",
+        ];
+        assert_eq!(errors, expected);
+    }
+
+    #[test]
+    fn operands_and_arguments_are_checked() {
+        let source = "
+            struct s { int m; } r;
+            int g(int a, int b);
+            void f(int *p, double d) {
+                p * 2;
+                d % 2;
+                r + 1;
+                !r;
+                g(1);
+                g(1, 2, 3);
+                d();
+            }
+        ";
+        let errors: Vec<_> = errors(source).iter().map(|e| e.to_string()).collect();
+        let expected = [
+            "invalid operands to '*' ('int *' and 'int')",
+            "invalid operands to '%' ('double' and 'int')",
+            "invalid operands to '+' ('struct s' and 'int')",
+            "invalid operand to '!' of type 'struct s'",
+            "too few arguments to function call, expected 2, found 1",
+            "too many arguments to function call, expected 2, found 3",
+            "called object of type 'double' is not a function",
+        ];
+        assert_eq!(errors, expected);
+    }
+}
@@ -0,0 +1,1192 @@
+use std::{
+    collections::{HashMap, HashSet},
+    error, fmt,
+};
+
+use crate::{
+    ast::{
+        Alignment, Declaration, DeclarationSpecifiers, Declarator, DerivedDeclarator,
+        EnumSpecifier, Expr, MemberDeclaration, ParameterDeclaration, Qualifiers, RecordKind,
+        RecordSpecifier, StaticAssert, StorageClass, TypeName, TypeSpecifier,
+    },
+    constant::evaluate,
+    layout::{Field, Layout},
+    lexer::{Integer, IntegerSuffix, Span, Spanned},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum IntegerKind {
+    Bool,
+    Char,
+    SignedChar,
+    UnsignedChar,
+    Short,
+    UnsignedShort,
+    Int,
+    UnsignedInt,
+    Long,
+    UnsignedLong,
+    LongLong,
+    UnsignedLongLong,
+}
+
+impl IntegerKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            IntegerKind::Bool => "_Bool",
+            IntegerKind::Char => "char",
+            IntegerKind::SignedChar => "signed char",
+            IntegerKind::UnsignedChar => "unsigned char",
+            IntegerKind::Short => "short",
+            IntegerKind::UnsignedShort => "unsigned short",
+            IntegerKind::Int => "int",
+            IntegerKind::UnsignedInt => "unsigned int",
+            IntegerKind::Long => "long",
+            IntegerKind::UnsignedLong => "unsigned long",
+            IntegerKind::LongLong => "long long",
+            IntegerKind::UnsignedLongLong => "unsigned long long",
+        }
+    }
+    // plain char is signed on x86-64
+    pub fn is_signed(&self) -> bool {
+        matches!(
+            self,
+            IntegerKind::Char
+                | IntegerKind::SignedChar
+                | IntegerKind::Short
+                | IntegerKind::Int
+                | IntegerKind::Long
+                | IntegerKind::LongLong
+        )
+    }
+    // bytes, as on x86-64
+    pub fn size(&self) -> u64 {
+        match self {
+            IntegerKind::Bool
+            | IntegerKind::Char
+            | IntegerKind::SignedChar
+            | IntegerKind::UnsignedChar => 1,
+            IntegerKind::Short | IntegerKind::UnsignedShort => 2,
+            IntegerKind::Int | IntegerKind::UnsignedInt => 4,
+            _ => 8,
+        }
+    }
+    pub fn rank(&self) -> u8 {
+        match self {
+            IntegerKind::Bool => 0,
+            IntegerKind::Char | IntegerKind::SignedChar | IntegerKind::UnsignedChar => 1,
+            IntegerKind::Short | IntegerKind::UnsignedShort => 2,
+            IntegerKind::Int | IntegerKind::UnsignedInt => 3,
+            IntegerKind::Long | IntegerKind::UnsignedLong => 4,
+            IntegerKind::LongLong | IntegerKind::UnsignedLongLong => 5,
+        }
+    }
+    pub fn to_unsigned(&self) -> IntegerKind {
+        match self {
+            IntegerKind::Char | IntegerKind::SignedChar => IntegerKind::UnsignedChar,
+            IntegerKind::Short => IntegerKind::UnsignedShort,
+            IntegerKind::Int => IntegerKind::UnsignedInt,
+            IntegerKind::Long => IntegerKind::UnsignedLong,
+            IntegerKind::LongLong => IntegerKind::UnsignedLongLong,
+            unsigned => *unsigned,
+        }
+    }
+    // kinds of lower rank than int become int, which holds every value of
+    // the smaller types on x86-64
+    pub fn promoted(&self) -> IntegerKind {
+        match self.rank() < IntegerKind::Int.rank() {
+            true => IntegerKind::Int,
+            false => *self,
+        }
+    }
+    // the usual arithmetic conversions between integers: both promoted and
+    // the wider one, unsigned winning unless the signed type can hold every
+    // value of the unsigned one
+    pub fn common(&self, other: IntegerKind) -> IntegerKind {
+        let (x, y) = (self.promoted(), other.promoted());
+        if x == y {
+            x
+        } else if x.is_signed() == y.is_signed() {
+            if x.rank() > y.rank() {
+                x
+            } else {
+                y
+            }
+        } else {
+            let (unsigned, signed) = if x.is_signed() { (y, x) } else { (x, y) };
+            if unsigned.rank() >= signed.rank() {
+                unsigned
+            } else if signed.size() > unsigned.size() {
+                signed
+            } else {
+                signed.to_unsigned()
+            }
+        }
+    }
+    // the first type of the literal's list in C11 6.4.4.1 that holds its
+    // value; only octal and hex literals may be unsigned without a `u`, so a
+    // decimal one can have no type at all
+    pub fn of_literal(integer: &Integer) -> Option<IntegerKind> {
+        use IntegerKind::*;
+        let decimal = integer.radix == 10;
+        let candidates: &[IntegerKind] = match (integer.suffix, decimal) {
+            (IntegerSuffix::None, true) => &[Int, Long, LongLong],
+            (IntegerSuffix::None, false) => &[
+                Int,
+                UnsignedInt,
+                Long,
+                UnsignedLong,
+                LongLong,
+                UnsignedLongLong,
+            ],
+            (IntegerSuffix::Unsigned, _) => &[UnsignedInt, UnsignedLong, UnsignedLongLong],
+            (IntegerSuffix::Long, true) => &[Long, LongLong],
+            (IntegerSuffix::Long, false) => &[Long, UnsignedLong, LongLong, UnsignedLongLong],
+            (IntegerSuffix::UnsignedLong, _) => &[UnsignedLong, UnsignedLongLong],
+            (IntegerSuffix::LongLong, true) => &[LongLong],
+            (IntegerSuffix::LongLong, false) => &[LongLong, UnsignedLongLong],
+            (IntegerSuffix::UnsignedLongLong, _) => &[UnsignedLongLong],
+        };
+        let fits = |kind: &&IntegerKind| match kind {
+            Int => integer.value <= i32::MAX as u64,
+            UnsignedInt => integer.value <= u32::MAX as u64,
+            Long | LongLong => integer.value <= i64::MAX as u64,
+            _ => true,
+        };
+        candidates.iter().find(fits).copied()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FloatKind {
+    Float,
+    Double,
+    LongDouble,
+}
+
+impl FloatKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            FloatKind::Float => "float",
+            FloatKind::Double => "double",
+            FloatKind::LongDouble => "long double",
+        }
+    }
+    fn rank(&self) -> u8 {
+        match self {
+            FloatKind::Float => 0,
+            FloatKind::Double => 1,
+            FloatKind::LongDouble => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FunctionType {
+    pub result: Box<QualifiedType>,
+    pub parameters: Vec<QualifiedType>,
+    pub variadic: bool,
+    // false for `int f()`, which says nothing about the parameters
+    pub prototyped: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    Void,
+    Integer(IntegerKind),
+    Float(FloatKind),
+    Complex(FloatKind),
+    Pointer(Box<QualifiedType>),
+    // `None` when the size is left out, as in `extern int a[];`
+    Array(Box<QualifiedType>, Option<u64>),
+    Function(FunctionType),
+    // tagged types are identified by their index in the `TypeEnv` that
+    // declared them, the tag is kept for printing
+    Record {
+        kind: RecordKind,
+        tag: Option<String>,
+        id: usize,
+    },
+    Enum {
+        tag: Option<String>,
+        id: usize,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct QualifiedType {
+    pub ty: Type,
+    pub qualifiers: Qualifiers,
+}
+
+impl From<Type> for QualifiedType {
+    fn from(ty: Type) -> Self {
+        QualifiedType {
+            ty,
+            qualifiers: Qualifiers::default(),
+        }
+    }
+}
+
+fn qualifier_prefix(qualifiers: Qualifiers) -> String {
+    let names = [
+        (qualifiers.is_const, "const "),
+        (qualifiers.is_volatile, "volatile "),
+        (qualifiers.is_restrict, "restrict "),
+        (qualifiers.is_atomic, "_Atomic "),
+    ];
+    names
+        .into_iter()
+        .filter_map(|(set, name)| set.then_some(name))
+        .collect()
+}
+
+impl QualifiedType {
+    // C spelling of a declaration of `name` with this type, as in
+    // `int (*name)[3]`; an empty name spells the type itself
+    pub fn declare(&self, name: &str) -> String {
+        let mut inner = name.to_string();
+        let mut ty = self;
+        loop {
+            match &ty.ty {
+                Type::Pointer(to) => {
+                    inner = format!("*{}{inner}", qualifier_prefix(ty.qualifiers));
+                    if matches!(to.ty, Type::Array(..) | Type::Function(_)) {
+                        inner = format!("({})", inner.trim_end());
+                    }
+                    ty = to;
+                }
+                Type::Array(element, size) => {
+                    let size = size.map_or(String::new(), |n| n.to_string());
+                    inner = format!("{inner}[{size}]");
+                    ty = element;
+                }
+                Type::Function(function) => {
+                    let mut parameters: Vec<String> =
+                        function.parameters.iter().map(|p| p.declare("")).collect();
+                    if function.variadic {
+                        parameters.push("...".to_string());
+                    }
+                    if parameters.is_empty() && function.prototyped {
+                        parameters.push("void".to_string());
+                    }
+                    inner = format!("{inner}({})", parameters.join(", "));
+                    ty = &function.result;
+                }
+                base => {
+                    let base = match base {
+                        Type::Integer(kind) => kind.name().to_string(),
+                        Type::Float(kind) => kind.name().to_string(),
+                        Type::Complex(kind) => format!("{} _Complex", kind.name()),
+                        Type::Record { kind, tag, .. } => {
+                            let kind = match kind {
+                                RecordKind::Struct => "struct",
+                                RecordKind::Union => "union",
+                            };
+                            format!("{kind} {}", tag.as_deref().unwrap_or("(anonymous)"))
+                        }
+                        Type::Enum { tag, .. } => {
+                            format!("enum {}", tag.as_deref().unwrap_or("(anonymous)"))
+                        }
+                        _ => "void".to_string(),
+                    };
+                    let base = format!("{}{base}", qualifier_prefix(ty.qualifiers));
+                    let inner = inner.trim_end();
+                    return match inner.chars().next() {
+                        None => base,
+                        Some('[') => format!("{base}{inner}"),
+                        _ => format!("{base} {inner}"),
+                    };
+                }
+            }
+        }
+    }
+    // C's compatible types: the same type, except that a function without
+    // a prototype matches any with the same result and an array of unknown
+    // size matches any size
+    pub fn is_compatible(&self, other: &QualifiedType) -> bool {
+        if self.qualifiers != other.qualifiers {
+            return false;
+        }
+        match (&self.ty, &other.ty) {
+            (Type::Pointer(a), Type::Pointer(b)) => a.is_compatible(b),
+            (Type::Array(a, n), Type::Array(b, m)) => {
+                a.is_compatible(b) && (n.is_none() || m.is_none() || n == m)
+            }
+            (Type::Function(f), Type::Function(g)) => {
+                let unqualified = |ty: &QualifiedType| QualifiedType::from(ty.ty.clone());
+                let parameters = f.parameters.len() == g.parameters.len()
+                    && f.variadic == g.variadic
+                    && (f.parameters.iter().zip(&g.parameters))
+                        .all(|(a, b)| unqualified(a).is_compatible(&unqualified(b)));
+                f.result.is_compatible(&g.result) && (!f.prototyped || !g.prototyped || parameters)
+            }
+            (a, b) => a == b,
+        }
+    }
+}
+
+impl fmt::Display for QualifiedType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.declare(""))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum TypeErrorKind {
+    InvalidSpecifiers(String),
+    MissingSpecifier,
+    UnknownTypedef(String),
+    ArraySize,
+    ArrayOfVoid,
+    ArrayOfFunctions,
+    FunctionReturningArray,
+    FunctionReturningFunction,
+    VoidParameter,
+    StorageClassInTypeName,
+    NotConstant,
+    TagMismatch(String),
+    Redefinition(String),
+    DuplicateMember(String),
+    IncompleteMember(String),
+    BitFieldType,
+    BitFieldWidth,
+    Incomplete(QualifiedType),
+    InvalidAlignment(i64),
+    AlignmentTooWeak { requested: u64, natural: u64 },
+    MisplacedAlignment,
+    StaticAssertion(String),
+    TooLarge,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct TypeError {
+    pub kind: TypeErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for TypeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypeErrorKind::InvalidSpecifiers(specifiers) => {
+                write!(f, "invalid combination of type specifiers '{specifiers}'")
+            }
+            TypeErrorKind::MissingSpecifier => write!(f, "missing type specifier"),
+            TypeErrorKind::UnknownTypedef(name) => write!(f, "unknown type name '{name}'"),
+            TypeErrorKind::ArraySize => {
+                write!(f, "array size is not a positive integer constant")
+            }
+            TypeErrorKind::ArrayOfVoid => write!(f, "array of void"),
+            TypeErrorKind::ArrayOfFunctions => write!(f, "array of functions"),
+            TypeErrorKind::FunctionReturningArray => write!(f, "function returning an array"),
+            TypeErrorKind::FunctionReturningFunction => {
+                write!(f, "function returning a function")
+            }
+            TypeErrorKind::VoidParameter => {
+                write!(f, "void must be the only parameter and unnamed")
+            }
+            TypeErrorKind::StorageClassInTypeName => {
+                write!(f, "storage class in a type name")
+            }
+            TypeErrorKind::NotConstant => write!(f, "expression is not an integer constant"),
+            TypeErrorKind::TagMismatch(tag) => {
+                write!(f, "'{tag}' was declared as a different kind of tag")
+            }
+            TypeErrorKind::Redefinition(tag) => write!(f, "redefinition of '{tag}'"),
+            TypeErrorKind::DuplicateMember(name) => write!(f, "duplicate member '{name}'"),
+            TypeErrorKind::IncompleteMember(name) => {
+                write!(f, "member '{name}' has incomplete type")
+            }
+            TypeErrorKind::BitFieldType => write!(f, "bit-field has non-integer type"),
+            TypeErrorKind::BitFieldWidth => write!(f, "invalid bit-field width"),
+            TypeErrorKind::Incomplete(ty) => write!(f, "invalid use of incomplete type '{ty}'"),
+            TypeErrorKind::InvalidAlignment(n) => {
+                write!(f, "requested alignment {n} is not a power of two")
+            }
+            TypeErrorKind::AlignmentTooWeak { requested, natural } => write!(
+                f,
+                "requested alignment {requested} is less than the type's alignment {natural}"
+            ),
+            TypeErrorKind::MisplacedAlignment => write!(f, "_Alignas is not allowed here"),
+            TypeErrorKind::StaticAssertion(message) => {
+                write!(f, "static assertion failed: {message}")
+            }
+            TypeErrorKind::TooLarge => write!(f, "object too large"),
+        }
+    }
+}
+
+impl fmt::Display for TypeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.span, self.kind)
+    }
+}
+
+impl error::Error for TypeError {}
+
+fn spelling(specifier: &TypeSpecifier) -> &str {
+    match specifier {
+        TypeSpecifier::Void => "void",
+        TypeSpecifier::Char => "char",
+        TypeSpecifier::Short => "short",
+        TypeSpecifier::Int => "int",
+        TypeSpecifier::Long => "long",
+        TypeSpecifier::Float => "float",
+        TypeSpecifier::Double => "double",
+        TypeSpecifier::Signed => "signed",
+        TypeSpecifier::Unsigned => "unsigned",
+        TypeSpecifier::Bool => "_Bool",
+        TypeSpecifier::Complex => "_Complex",
+        TypeSpecifier::TypedefName(name) => name,
+        TypeSpecifier::Record(record) => match record.kind {
+            RecordKind::Struct => "struct",
+            RecordKind::Union => "union",
+        },
+        TypeSpecifier::Enum(_) => "enum",
+    }
+}
+
+pub(crate) fn merge(a: Qualifiers, b: Qualifiers) -> Qualifiers {
+    Qualifiers {
+        is_const: a.is_const || b.is_const,
+        is_volatile: a.is_volatile || b.is_volatile,
+        is_restrict: a.is_restrict || b.is_restrict,
+        is_atomic: a.is_atomic || b.is_atomic,
+    }
+}
+
+// the integer type of a list of specifiers without typedef names, in any
+// order as C allows: `long unsigned int long`
+fn arithmetic(types: &[Spanned<TypeSpecifier>]) -> Option<Type> {
+    let mut sign = None;
+    let mut longs = 0;
+    let mut int = false;
+    let mut complex = false;
+    let mut base = None;
+    for specifier in types {
+        match &specifier.node {
+            TypeSpecifier::Signed | TypeSpecifier::Unsigned if sign.is_none() => {
+                sign = Some(specifier.node == TypeSpecifier::Signed)
+            }
+            TypeSpecifier::Long if longs < 2 => longs += 1,
+            TypeSpecifier::Int if !int => int = true,
+            TypeSpecifier::Complex if !complex => complex = true,
+            TypeSpecifier::Signed
+            | TypeSpecifier::Unsigned
+            | TypeSpecifier::Long
+            | TypeSpecifier::Int
+            | TypeSpecifier::Complex
+            | TypeSpecifier::TypedefName(_)
+            | TypeSpecifier::Record(_)
+            | TypeSpecifier::Enum(_) => return None,
+            other if base.is_none() => base = Some(other),
+            _ => return None,
+        }
+    }
+    let integer = |signed, unsigned| match sign {
+        Some(false) => Type::Integer(unsigned),
+        _ => Type::Integer(signed),
+    };
+    let plain = !int && sign.is_none() && !complex;
+    Some(match (base, longs) {
+        (Some(TypeSpecifier::Void), 0) if plain => Type::Void,
+        (Some(TypeSpecifier::Bool), 0) if plain => Type::Integer(IntegerKind::Bool),
+        (Some(TypeSpecifier::Char), 0) if !int && !complex => match sign {
+            None => Type::Integer(IntegerKind::Char),
+            Some(true) => Type::Integer(IntegerKind::SignedChar),
+            Some(false) => Type::Integer(IntegerKind::UnsignedChar),
+        },
+        (Some(TypeSpecifier::Short), 0) if !complex => {
+            integer(IntegerKind::Short, IntegerKind::UnsignedShort)
+        }
+        (None, 0) if !complex && (int || sign.is_some()) => {
+            integer(IntegerKind::Int, IntegerKind::UnsignedInt)
+        }
+        (None, 1) if !complex => integer(IntegerKind::Long, IntegerKind::UnsignedLong),
+        (None, 2) if !complex => integer(IntegerKind::LongLong, IntegerKind::UnsignedLongLong),
+        (Some(TypeSpecifier::Float), 0) | (Some(TypeSpecifier::Double), 0 | 1)
+            if !int && sign.is_none() =>
+        {
+            let kind = match (base, longs) {
+                (Some(TypeSpecifier::Float), _) => FloatKind::Float,
+                (_, 0) => FloatKind::Double,
+                _ => FloatKind::LongDouble,
+            };
+            match complex {
+                true => Type::Complex(kind),
+                false => Type::Float(kind),
+            }
+        }
+        _ => return None,
+    })
+}
+
+// a member of a complete struct or union; unnamed members are anonymous
+// structs and unions whose own members are found through them
+#[derive(Debug, Clone, PartialEq)]
+pub struct Member {
+    pub name: Option<String>,
+    pub ty: QualifiedType,
+    // bytes from the start of the record, for a bit-field the start of the
+    // storage unit holding it
+    pub offset: u64,
+    pub bit_field: Option<BitField>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BitField {
+    // bits from the start of the storage unit, least significant first
+    pub offset: u64,
+    pub width: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub kind: RecordKind,
+    pub tag: Option<String>,
+    // both `None` until the definition is seen
+    pub members: Option<Vec<Member>>,
+    pub layout: Option<Layout>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enumeration {
+    pub tag: Option<String>,
+    // `None` until the definition is seen
+    pub constants: Option<Vec<(String, i64)>>,
+    // the smallest of unsigned int, int, unsigned long and long holding
+    // every constant, as GCC picks it
+    pub underlying: IntegerKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Tag {
+    Record(RecordKind, usize),
+    Enum(usize),
+}
+
+#[derive(Debug, Clone)]
+pub(crate) enum Ordinary {
+    Typedef(QualifiedType),
+    // functions and parameters as well
+    Object(QualifiedType),
+    Constant(i64),
+}
+
+// tags and ordinary identifiers are separate namespaces
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    ordinary: HashMap<String, Ordinary>,
+    tags: HashMap<String, Tag>,
+}
+
+// a declared name, if any, with its type
+pub type Declared = (Option<Spanned<String>>, QualifiedType);
+
+// builds types from specifiers and declarators; scoped like the parser's
+// table, so an ordinary declaration hides a typedef of the same name in an
+// enclosing scope, and owns every struct, union and enum it has seen
+#[derive(Debug, Clone)]
+pub struct TypeEnv {
+    scopes: Vec<Scope>,
+    records: Vec<Record>,
+    enums: Vec<Enumeration>,
+}
+
+impl Default for TypeEnv {
+    fn default() -> Self {
+        TypeEnv::new()
+    }
+}
+
+impl TypeEnv {
+    pub fn new() -> Self {
+        TypeEnv {
+            scopes: vec![Scope::default()],
+            records: Vec::new(),
+            enums: Vec::new(),
+        }
+    }
+    pub fn push(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+    pub fn pop(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+    fn define(&mut self, name: String, ordinary: Ordinary) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.ordinary.insert(name, ordinary);
+        }
+    }
+    pub fn define_typedef(&mut self, name: impl Into<String>, ty: QualifiedType) {
+        self.define(name.into(), Ordinary::Typedef(ty));
+    }
+    // an object or function, hiding any typedef of an enclosing scope
+    pub fn define_object(&mut self, name: impl Into<String>, ty: QualifiedType) {
+        self.define(name.into(), Ordinary::Object(ty));
+    }
+    pub(crate) fn ordinary(&self, name: &str) -> Option<&Ordinary> {
+        let mut scopes = self.scopes.iter().rev();
+        scopes.find_map(|scope| scope.ordinary.get(name))
+    }
+    pub fn typedef(&self, name: &str) -> Option<&QualifiedType> {
+        match self.ordinary(name)? {
+            Ordinary::Typedef(ty) => Some(ty),
+            _ => None,
+        }
+    }
+    // value of an enumeration constant
+    pub fn constant(&self, name: &str) -> Option<i64> {
+        match self.ordinary(name)? {
+            Ordinary::Constant(value) => Some(*value),
+            _ => None,
+        }
+    }
+    pub fn tag(&self, name: &str) -> Option<Tag> {
+        let mut scopes = self.scopes.iter().rev();
+        scopes.find_map(|scope| scope.tags.get(name)).copied()
+    }
+    pub fn record(&self, id: usize) -> &Record {
+        &self.records[id]
+    }
+    pub fn enumeration(&self, id: usize) -> &Enumeration {
+        &self.enums[id]
+    }
+
+    // integers of lower rank than int become int, enums their underlying
+    // type first
+    pub fn promote(&self, ty: &QualifiedType) -> QualifiedType {
+        match ty.ty {
+            Type::Integer(kind) => Type::Integer(kind.promoted()).into(),
+            Type::Enum { id, .. } => Type::Integer(self.enums[id].underlying.promoted()).into(),
+            _ => ty.ty.clone().into(),
+        }
+    }
+    // the usual arithmetic conversions: the wider floating type, complex if
+    // either is, else the common integer type
+    pub fn common(&self, a: &QualifiedType, b: &QualifiedType) -> QualifiedType {
+        let (a, b) = (self.promote(a), self.promote(b));
+        let floating = |ty: &Type| match ty {
+            Type::Float(kind) | Type::Complex(kind) => Some(*kind),
+            _ => None,
+        };
+        if floating(&a.ty).is_some() || floating(&b.ty).is_some() {
+            let kinds = [floating(&a.ty), floating(&b.ty)].into_iter().flatten();
+            let kind = kinds.fold(FloatKind::Float, |widest, kind| {
+                match kind.rank() > widest.rank() {
+                    true => kind,
+                    false => widest,
+                }
+            });
+            let complex = matches!(a.ty, Type::Complex(_)) || matches!(b.ty, Type::Complex(_));
+            return match complex {
+                true => Type::Complex(kind).into(),
+                false => Type::Float(kind).into(),
+            };
+        }
+        match (&a.ty, &b.ty) {
+            (Type::Integer(x), Type::Integer(y)) => Type::Integer(x.common(*y)).into(),
+            _ => a,
+        }
+    }
+
+    pub fn specified(
+        &mut self,
+        specifiers: &DeclarationSpecifiers,
+    ) -> Result<QualifiedType, TypeError> {
+        self.base(specifiers, false)
+    }
+    // `forward` is set for declarations without declarators, where
+    // `struct S;` declares a new S even if an enclosing scope has one
+    fn base(
+        &mut self,
+        specifiers: &DeclarationSpecifiers,
+        forward: bool,
+    ) -> Result<QualifiedType, TypeError> {
+        let error = |kind| TypeError {
+            kind,
+            span: specifiers.span,
+        };
+        let ty = match &specifiers.types[..] {
+            [] => return Err(error(TypeErrorKind::MissingSpecifier)),
+            [Spanned {
+                node: TypeSpecifier::TypedefName(name),
+                span,
+            }] => {
+                let ty = self.typedef(name).ok_or_else(|| TypeError {
+                    kind: TypeErrorKind::UnknownTypedef(name.clone()),
+                    span: *span,
+                })?;
+                return Ok(QualifiedType {
+                    ty: ty.ty.clone(),
+                    qualifiers: merge(ty.qualifiers, specifiers.qualifiers),
+                });
+            }
+            [Spanned {
+                node: TypeSpecifier::Record(record),
+                ..
+            }] => self.record_type(record, forward)?,
+            [Spanned {
+                node: TypeSpecifier::Enum(enumeration),
+                ..
+            }] => self.enum_type(enumeration)?,
+            types => arithmetic(types).ok_or_else(|| {
+                let names: Vec<&str> = types.iter().map(|t| spelling(&t.node)).collect();
+                error(TypeErrorKind::InvalidSpecifiers(names.join(" ")))
+            })?,
+        };
+        Ok(QualifiedType {
+            ty,
+            qualifiers: specifiers.qualifiers,
+        })
+    }
+    // the tag declared in the current scope for a definition or forward
+    // declaration, otherwise the visible one; `None` if a new type is needed
+    fn find_tag(&self, tag: &Spanned<String>, here: bool) -> Option<Tag> {
+        match here {
+            true => self.scopes.last()?.tags.get(&tag.node).copied(),
+            false => self.tag(&tag.node),
+        }
+    }
+    fn declare_tag(&mut self, tag: &Spanned<String>, declared: Tag) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.tags.insert(tag.node.clone(), declared);
+        }
+    }
+    fn record_type(&mut self, record: &RecordSpecifier, forward: bool) -> Result<Type, TypeError> {
+        let RecordSpecifier {
+            kind, tag, members, ..
+        } = record;
+        let error = |kind, tag: &Spanned<String>| TypeError {
+            kind,
+            span: tag.span,
+        };
+        let here = forward || members.is_some();
+        let existing = match tag {
+            Some(tag) => match self.find_tag(tag, here) {
+                Some(Tag::Record(k, id)) if k == *kind => {
+                    if members.is_some() && self.records[id].members.is_some() {
+                        return Err(error(TypeErrorKind::Redefinition(tag.node.clone()), tag));
+                    }
+                    Some(id)
+                }
+                Some(_) => return Err(error(TypeErrorKind::TagMismatch(tag.node.clone()), tag)),
+                None => None,
+            },
+            None => None,
+        };
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                self.records.push(Record {
+                    kind: *kind,
+                    tag: tag.as_ref().map(|t| t.node.clone()),
+                    members: None,
+                    layout: None,
+                });
+                let id = self.records.len() - 1;
+                if let Some(tag) = tag {
+                    self.declare_tag(tag, Tag::Record(*kind, id));
+                }
+                id
+            }
+        };
+        if let Some(members) = members {
+            self.define_record(id, members, &record.assertions)?;
+        }
+        Ok(Type::Record {
+            kind: *kind,
+            tag: tag.as_ref().map(|t| t.node.clone()),
+            id,
+        })
+    }
+    fn define_record(
+        &mut self,
+        id: usize,
+        members: &[MemberDeclaration],
+        assertions: &[StaticAssert],
+    ) -> Result<(), TypeError> {
+        let mut fields = Vec::new();
+        let mut seen = HashSet::new();
+        for member in members {
+            let base = self.specified(&member.specifiers)?;
+            if member.declarators.is_empty() {
+                // only anonymous structs and unions declare members this way
+                if let Type::Record { tag: None, id, .. } = base.ty {
+                    for name in self.member_names(id) {
+                        if !seen.insert(name.clone()) {
+                            return Err(TypeError {
+                                kind: TypeErrorKind::DuplicateMember(name),
+                                span: member.span,
+                            });
+                        }
+                    }
+                    let align = self.alignment(&member.specifiers, &base)?;
+                    fields.push(Field {
+                        name: None,
+                        ty: base,
+                        width: None,
+                        align,
+                        span: member.span,
+                    });
+                }
+                continue;
+            }
+            for declarator in &member.declarators {
+                let ty = self.declared(&base, &declarator.declarator)?;
+                let name = &declarator.declarator.name;
+                if let Some(name) = name {
+                    if !seen.insert(name.node.clone()) {
+                        return Err(TypeError {
+                            kind: TypeErrorKind::DuplicateMember(name.node.clone()),
+                            span: name.span,
+                        });
+                    }
+                }
+                let width = match &declarator.width {
+                    Some(_) if !member.specifiers.alignments.is_empty() => {
+                        return Err(TypeError {
+                            kind: TypeErrorKind::MisplacedAlignment,
+                            span: member.specifiers.span,
+                        })
+                    }
+                    Some(width) => Some(self.bit_width(width, &ty, name.is_some())?),
+                    None => None,
+                };
+                let align = self.alignment(&member.specifiers, &ty)?;
+                fields.push(Field {
+                    name: name.clone(),
+                    ty,
+                    width,
+                    align,
+                    span: declarator.declarator.span,
+                });
+            }
+        }
+        let (members, layout) = self.lay_out(self.records[id].kind, fields)?;
+        self.records[id].members = Some(members);
+        self.records[id].layout = Some(layout);
+        for assertion in assertions {
+            self.static_assert(assertion)?;
+        }
+        Ok(())
+    }
+    // the strictest alignment `_Alignas` asks of an object of type `ty`,
+    // `None` if there is none; an alignment of 0 asks for nothing
+    pub fn alignment(
+        &mut self,
+        specifiers: &DeclarationSpecifiers,
+        ty: &QualifiedType,
+    ) -> Result<Option<u64>, TypeError> {
+        if specifiers.alignments.is_empty() {
+            return Ok(None);
+        }
+        let error = |kind, span| Err(TypeError { kind, span });
+        let storage = specifiers.storage;
+        if matches!(
+            storage,
+            Some(StorageClass::Typedef | StorageClass::Register)
+        ) || matches!(ty.ty, Type::Function(_))
+        {
+            return error(TypeErrorKind::MisplacedAlignment, specifiers.span);
+        }
+        let mut strictest = None;
+        for alignment in &specifiers.alignments {
+            let (requested, span) = match alignment {
+                Alignment::Type(name) => {
+                    let ty = self.type_name(name)?;
+                    match self.align_of(&ty) {
+                        Some(align) => (align as i64, name.span),
+                        None => return error(TypeErrorKind::Incomplete(ty), name.span),
+                    }
+                }
+                Alignment::Expr(expr) => match self.integer_constant(expr) {
+                    Some(value) => (value, expr.span),
+                    None => return error(TypeErrorKind::NotConstant, expr.span),
+                },
+            };
+            match requested {
+                0 => {}
+                1.. if (requested as u64).is_power_of_two() => {
+                    strictest = strictest.max(Some(requested as u64));
+                }
+                _ => return error(TypeErrorKind::InvalidAlignment(requested), span),
+            }
+        }
+        match (strictest, self.align_of(ty)) {
+            (Some(requested), Some(natural)) if requested < natural => error(
+                TypeErrorKind::AlignmentTooWeak { requested, natural },
+                specifiers.span,
+            ),
+            _ => Ok(strictest),
+        }
+    }
+    // a static assertion whose condition is zero is an error
+    pub fn static_assert(&mut self, assertion: &StaticAssert) -> Result<(), TypeError> {
+        match self.integer_constant(&assertion.condition) {
+            Some(0) => Err(TypeError {
+                kind: TypeErrorKind::StaticAssertion(assertion.message.clone()),
+                span: assertion.span,
+            }),
+            Some(_) => Ok(()),
+            None => Err(TypeError {
+                kind: TypeErrorKind::NotConstant,
+                span: assertion.condition.span,
+            }),
+        }
+    }
+    fn bit_width(
+        &mut self,
+        width: &Expr,
+        ty: &QualifiedType,
+        named: bool,
+    ) -> Result<u64, TypeError> {
+        if !matches!(ty.ty, Type::Integer(_) | Type::Enum { .. }) {
+            return Err(TypeError {
+                kind: TypeErrorKind::BitFieldType,
+                span: width.span,
+            });
+        }
+        let bits = self.size_of(ty).unwrap_or(0) * 8;
+        match self.integer_constant(width) {
+            Some(0) if !named => Ok(0),
+            Some(n @ 1..) if n as u64 <= bits => Ok(n as u64),
+            _ => Err(TypeError {
+                kind: TypeErrorKind::BitFieldWidth,
+                span: width.span,
+            }),
+        }
+    }
+    // every member name reachable from a record, through anonymous members
+    fn member_names(&self, id: usize) -> Vec<String> {
+        let mut names = Vec::new();
+        for member in self.records[id].members.iter().flatten() {
+            match (&member.name, &member.ty.ty) {
+                (Some(name), _) => names.push(name.clone()),
+                (None, Type::Record { id, .. }) => names.extend(self.member_names(*id)),
+                _ => {}
+            }
+        }
+        names
+    }
+    fn enum_type(&mut self, enumeration: &EnumSpecifier) -> Result<Type, TypeError> {
+        let EnumSpecifier {
+            tag, enumerators, ..
+        } = enumeration;
+        let existing = match tag {
+            Some(tag) => match self.find_tag(tag, enumerators.is_some()) {
+                Some(Tag::Enum(id)) => {
+                    if enumerators.is_some() && self.enums[id].constants.is_some() {
+                        return Err(TypeError {
+                            kind: TypeErrorKind::Redefinition(tag.node.clone()),
+                            span: tag.span,
+                        });
+                    }
+                    Some(id)
+                }
+                Some(_) => {
+                    return Err(TypeError {
+                        kind: TypeErrorKind::TagMismatch(tag.node.clone()),
+                        span: tag.span,
+                    })
+                }
+                None => None,
+            },
+            None => None,
+        };
+        let id = match existing {
+            Some(id) => id,
+            None => {
+                self.enums.push(Enumeration {
+                    tag: tag.as_ref().map(|t| t.node.clone()),
+                    constants: None,
+                    underlying: IntegerKind::UnsignedInt,
+                });
+                let id = self.enums.len() - 1;
+                if let Some(tag) = tag {
+                    self.declare_tag(tag, Tag::Enum(id));
+                }
+                id
+            }
+        };
+        if let Some(enumerators) = enumerators {
+            let mut constants = Vec::new();
+            let mut next = 0i64;
+            for enumerator in enumerators {
+                let value = match &enumerator.value {
+                    Some(value) => self.integer_constant(value).ok_or(TypeError {
+                        kind: TypeErrorKind::NotConstant,
+                        span: value.span,
+                    })?,
+                    None => next,
+                };
+                // visible from the next enumerator on
+                let name = enumerator.name.node.clone();
+                self.define(name.clone(), Ordinary::Constant(value));
+                constants.push((name, value));
+                next = value.saturating_add(1);
+            }
+            let min = constants.iter().map(|(_, v)| *v).min().unwrap_or(0);
+            let max = constants.iter().map(|(_, v)| *v).max().unwrap_or(0);
+            self.enums[id].underlying = match (min, max) {
+                (0.., ..=0xffff_ffff) => IntegerKind::UnsignedInt,
+                (-0x8000_0000.., ..=0x7fff_ffff) => IntegerKind::Int,
+                (0.., _) => IntegerKind::UnsignedLong,
+                _ => IntegerKind::Long,
+            };
+            self.enums[id].constants = Some(constants);
+        }
+        Ok(Type::Enum {
+            tag: tag.as_ref().map(|t| t.node.clone()),
+            id,
+        })
+    }
+    // applies the derivations of a declarator to `base`, outermost first
+    pub fn declared(
+        &mut self,
+        base: &QualifiedType,
+        declarator: &Declarator,
+    ) -> Result<QualifiedType, TypeError> {
+        let error = |kind| TypeError {
+            kind,
+            span: declarator.span,
+        };
+        let mut ty = base.clone();
+        for derived in declarator.derived.iter().rev() {
+            ty = match derived {
+                DerivedDeclarator::Pointer(qualifiers) => QualifiedType {
+                    ty: Type::Pointer(Box::new(ty)),
+                    qualifiers: *qualifiers,
+                },
+                DerivedDeclarator::Array(size) => {
+                    match ty.ty {
+                        Type::Void => return Err(error(TypeErrorKind::ArrayOfVoid)),
+                        Type::Function(_) => return Err(error(TypeErrorKind::ArrayOfFunctions)),
+                        _ => {}
+                    }
+                    let size = match size {
+                        Some(size) => match evaluate(self, size).map(|c| c.value) {
+                            // the size in bytes must not overflow
+                            Some(n @ 1..)
+                                if u64::try_from(n).ok().is_none_or(|n| {
+                                    self.size_of(&ty)
+                                        .is_some_and(|element| element.checked_mul(n).is_none())
+                                }) =>
+                            {
+                                return Err(TypeError {
+                                    kind: TypeErrorKind::TooLarge,
+                                    span: size.span,
+                                })
+                            }
+                            Some(n @ 1..) => Some(n as u64),
+                            _ => {
+                                return Err(TypeError {
+                                    kind: TypeErrorKind::ArraySize,
+                                    span: size.span,
+                                })
+                            }
+                        },
+                        None => None,
+                    };
+                    Type::Array(Box::new(ty), size).into()
+                }
+                DerivedDeclarator::Function {
+                    parameters,
+                    variadic,
+                } => {
+                    match ty.ty {
+                        Type::Array(..) => {
+                            return Err(error(TypeErrorKind::FunctionReturningArray))
+                        }
+                        Type::Function(_) => {
+                            return Err(error(TypeErrorKind::FunctionReturningFunction))
+                        }
+                        _ => {}
+                    }
+                    let (parameters, prototyped) = self.parameters(parameters, *variadic)?;
+                    Type::Function(FunctionType {
+                        result: Box::new(ty),
+                        parameters,
+                        variadic: *variadic,
+                        prototyped,
+                    })
+                    .into()
+                }
+            };
+        }
+        Ok(ty)
+    }
+    // parameter types after adjustment, arrays and functions are passed as
+    // pointers; `(void)` is an empty prototype
+    fn parameters(
+        &mut self,
+        parameters: &[ParameterDeclaration],
+        variadic: bool,
+    ) -> Result<(Vec<QualifiedType>, bool), TypeError> {
+        let mut types = Vec::new();
+        for parameter in parameters {
+            if !parameter.specifiers.alignments.is_empty() {
+                return Err(TypeError {
+                    kind: TypeErrorKind::MisplacedAlignment,
+                    span: parameter.specifiers.span,
+                });
+            }
+            let base = self.specified(&parameter.specifiers)?;
+            let ty = self.declared(&base, &parameter.declarator)?;
+            let ty = match ty.ty {
+                Type::Array(element, _) => QualifiedType::from(Type::Pointer(element)),
+                Type::Function(_) => QualifiedType::from(Type::Pointer(Box::new(ty))),
+                Type::Void => {
+                    let only = parameters.len() == 1 && !variadic;
+                    if !only || parameter.declarator.name.is_some() {
+                        return Err(TypeError {
+                            kind: TypeErrorKind::VoidParameter,
+                            span: parameter.span,
+                        });
+                    }
+                    return Ok((Vec::new(), true));
+                }
+                _ => ty,
+            };
+            types.push(ty);
+        }
+        Ok((types, !parameters.is_empty() || variadic))
+    }
+    pub fn type_name(&mut self, name: &TypeName) -> Result<QualifiedType, TypeError> {
+        if name.specifiers.storage.is_some() {
+            return Err(TypeError {
+                kind: TypeErrorKind::StorageClassInTypeName,
+                span: name.specifiers.span,
+            });
+        }
+        if !name.specifiers.alignments.is_empty() {
+            return Err(TypeError {
+                kind: TypeErrorKind::MisplacedAlignment,
+                span: name.specifiers.span,
+            });
+        }
+        let base = self.specified(&name.specifiers)?;
+        self.declared(&base, &name.declarator)
+    }
+    // the type of every declarator in the current scope, typedefs among
+    // them are defined so later declarations can use them
+    pub fn declare(&mut self, declaration: &Declaration) -> Result<Vec<Declared>, TypeError> {
+        let forward = declaration.declarators.is_empty();
+        let base = self.base(&declaration.specifiers, forward)?;
+        let is_typedef = declaration.specifiers.storage == Some(StorageClass::Typedef);
+        let mut declared = Vec::new();
+        for init in &declaration.declarators {
+            let ty = self.declared(&base, &init.declarator)?;
+            if let Some(name) = &init.declarator.name {
+                match is_typedef {
+                    true => self.define_typedef(name.node.clone(), ty.clone()),
+                    false => self.define_object(name.node.clone(), ty.clone()),
+                }
+            }
+            declared.push((init.declarator.name.clone(), ty));
+        }
+        Ok(declared)
+    }
+}